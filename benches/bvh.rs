@@ -0,0 +1,57 @@
+// Compares BVH traversal with the linear scan on random_scene().
+// Run with `cargo bench`.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use raytracer_in_one_weekend::camera::Camera;
+use raytracer_in_one_weekend::hittable::Hittable;
+use raytracer_in_one_weekend::point::Point3;
+use raytracer_in_one_weekend::ray::Ray;
+use raytracer_in_one_weekend::scenes::random_scene;
+use raytracer_in_one_weekend::vector::Vec3;
+use std::time::Instant;
+
+fn bench<F: FnMut(&Ray) -> bool>(name: &str, rays: &[Ray], mut f: F) {
+    let start = Instant::now();
+    let hits = rays.iter().filter(|r| f(r)).count();
+    let elapsed = start.elapsed();
+    println!(
+        "{:>8}: {} rays, {} hits, {:.3?} ({:.2} Mrays/s)",
+        name,
+        rays.len(),
+        hits,
+        elapsed,
+        rays.len() as f64 / elapsed.as_secs_f64() / 1e6
+    );
+}
+
+fn main() {
+    let world = random_scene(0).world;
+    let camera = Camera::new(
+        Point3(13.0, 2.0, 3.0),
+        Point3::zero(),
+        Vec3(0.0, 1.0, 0.0),
+        20.0,
+        3.0 / 2.0,
+        0.1,
+        10.0,
+    );
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let rays: Vec<Ray> = (0..200_000)
+        .map(|_| {
+            let (s, t) = (rng.gen(), rng.gen());
+            camera.get_ray(&mut rng, s, t)
+        })
+        .collect();
+
+    println!("random_scene: {} objects", world.len());
+    // the first BVH hit also builds the tree
+    let start = Instant::now();
+    world.hit(&rays[0], 1e-3, f32::MAX);
+    println!("   build: {:.3?}", start.elapsed());
+
+    bench("linear", &rays, |r| {
+        world.hit_linear(r, 1e-3, f32::MAX).is_some()
+    });
+    bench("bvh", &rays, |r| world.hit(r, 1e-3, f32::MAX).is_some());
+}
@@ -0,0 +1,231 @@
+use crate::point::Point3;
+use crate::ray::Ray;
+use crate::vector::{AnimatedTransform, Len, Transform, Vec3};
+
+// Axis-aligned bounding box
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl Aabb {
+    pub fn new(min: Point3, max: Point3) -> Aabb {
+        assert!(
+            min.x() <= max.x() && min.y() <= max.y() && min.z() <= max.z(),
+            "min must not exceed max"
+        );
+        Aabb { min, max }
+    }
+
+    pub fn surrounding(self, other: Aabb) -> Aabb {
+        Aabb {
+            min: Point3(
+                f32::min(self.min.x(), other.min.x()),
+                f32::min(self.min.y(), other.min.y()),
+                f32::min(self.min.z(), other.min.z()),
+            ),
+            max: Point3(
+                f32::max(self.max.x(), other.max.x()),
+                f32::max(self.max.y(), other.max.y()),
+                f32::max(self.max.z(), other.max.z()),
+            ),
+        }
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        self.min + 0.5 * (self.max - self.min)
+    }
+
+    // Index of the axis along which the box is the widest
+    pub fn longest_axis(&self) -> usize {
+        let d = self.max - self.min;
+        if d.x() > d.y() && d.x() > d.z() {
+            0
+        } else if d.y() > d.z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = self.max - self.min;
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+
+    // Box around the transformed corners of this box
+    pub fn transformed(&self, t: &Transform) -> Aabb {
+        (0..8)
+            .map(|i| {
+                let corner = Point3(
+                    if i & 1 == 0 {
+                        self.min.x()
+                    } else {
+                        self.max.x()
+                    },
+                    if i & 2 == 0 {
+                        self.min.y()
+                    } else {
+                        self.max.y()
+                    },
+                    if i & 4 == 0 {
+                        self.min.z()
+                    } else {
+                        self.max.z()
+                    },
+                );
+                let p = t.transform_point(corner);
+                Aabb { min: p, max: p }
+            })
+            .reduce(Aabb::surrounding)
+            .unwrap()
+    }
+
+    // Box around this box over the whole motion. The motion is sampled,
+    // and the samples are padded by how far an arc between two of them
+    // can bulge out of their boxes.
+    pub fn swept(&self, motion: &AnimatedTransform) -> Aabb {
+        const STEPS: usize = 64;
+        let (time0, time1) = motion.time_range();
+        let mut bounds = self.transformed(&motion.at(time0));
+        if !motion.is_animated() {
+            return bounds;
+        }
+        let mut radius: f32 = 0.0;
+        for step in 0..=STEPS {
+            let t = motion.at(time0 + (time1 - time0) * step as f32 / STEPS as f32);
+            let b = self.transformed(&t);
+            let origin = t.transform_point(Point3::zero());
+            radius = radius
+                .max((b.min - origin).len())
+                .max((b.max - origin).len());
+            bounds = bounds.surrounding(b);
+        }
+        let sagitta = radius * (1.0 - (0.5 * motion.rotation_angle() / STEPS as f32).cos());
+        let pad = Vec3(sagitta, sagitta, sagitta);
+        Aabb::new(bounds.min - pad, bounds.max + pad)
+    }
+
+    pub fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> bool {
+        let inv_dir = Vec3(r.dir.0.recip(), r.dir.1.recip(), r.dir.2.recip());
+        self.hit_inv_dir(r.orig, inv_dir, t_min, t_max)
+    }
+
+    // Same as hit, for callers that test many boxes against one ray
+    pub fn hit_inv_dir(&self, orig: Point3, inv_dir: Vec3, mut t_min: f32, mut t_max: f32) -> bool {
+        // Slab test. Division by a zero direction component yields +-inf,
+        // and f32::min/max drop the NaN produced when the origin lies on a slab.
+        for axis in 0..3 {
+            let inv_d = inv_dir[axis];
+            let mut t0 = (self.min[axis] - orig[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - orig[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = f32::max(t0, t_min);
+            t_max = f32::min(t1, t_max);
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::Normalize;
+
+    #[test]
+    fn aabb_surrounding() {
+        let a = Aabb::new(Point3(0.0, 0.0, 0.0), Point3(1.0, 1.0, 1.0));
+        let b = Aabb::new(Point3(-1.0, 0.5, 0.5), Point3(0.5, 2.0, 0.5));
+        assert_eq!(
+            a.surrounding(b),
+            Aabb::new(Point3(-1.0, 0.0, 0.0), Point3(1.0, 2.0, 1.0))
+        );
+    }
+
+    #[test]
+    fn aabb_longest_axis() {
+        let a = Aabb::new(Point3(0.0, 0.0, 0.0), Point3(1.0, 3.0, 2.0));
+        assert_eq!(a.longest_axis(), 1);
+        assert_eq!(a.centroid(), Point3(0.5, 1.5, 1.0));
+    }
+
+    #[test]
+    fn aabb_surface_area() {
+        let a = Aabb::new(Point3(0.0, 0.0, 0.0), Point3(1.0, 2.0, 3.0));
+        assert_eq!(a.surface_area(), 22.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn aabb_min_exceed_max() {
+        Aabb::new(Point3(1.0, 0.0, 0.0), Point3(0.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn aabb_transformed() {
+        let a = Aabb::new(Point3(0.0, 0.0, 0.0), Point3(2.0, 1.0, 1.0));
+        let t = Transform::translation(Vec3(0.0, 0.0, 5.0))
+            * Transform::rotation(90.0, Vec3(0.0, 0.0, 1.0));
+        let b = a.transformed(&t);
+        assert!(Vec3::almost_eq(
+            b.min - Point3(-1.0, 0.0, 5.0),
+            Vec3::zero(),
+            1e-6
+        ));
+        assert!(Vec3::almost_eq(
+            b.max - Point3(0.0, 2.0, 6.0),
+            Vec3::zero(),
+            1e-6
+        ));
+    }
+
+    #[test]
+    fn aabb_swept_covers_the_motion() {
+        let a = Aabb::new(Point3(1.0, -0.1, -0.1), Point3(2.0, 0.1, 0.1));
+        let motion = AnimatedTransform::new(
+            Transform::identity(),
+            Transform::rotation(150.0, Vec3(0.0, 0.0, 1.0)),
+            0.0,
+            1.0,
+        )
+        .unwrap();
+        let b = a.swept(&motion);
+        // The far end passes through (0, 2, 0) between two samples
+        assert!(b.max.y() >= 2.0);
+        assert!(b.min.x() <= -1.75 && b.max.x() >= 2.0);
+        for i in 0..=1000 {
+            let t = motion.at(i as f32 / 1000.0);
+            let p = t.transform_point(Point3(2.0, 0.0, 0.0));
+            assert!(p.y() <= b.max.y() && p.x() >= b.min.x() && p.x() <= b.max.x());
+        }
+    }
+
+    #[test]
+    fn aabb_hit() {
+        let a = Aabb::new(Point3(-1.0, -1.0, -1.0), Point3(1.0, 1.0, 1.0));
+        let towards = Ray::new(Point3(0.0, 0.0, -5.0), Vec3(0.0, 0.0, 1.0));
+        let away = Ray::new(Point3(0.0, 0.0, -5.0), Vec3(0.0, 0.0, -1.0));
+        let miss = Ray::new(Point3(0.0, 2.0, -5.0), Vec3(0.0, 0.0, 1.0));
+        let diagonal = Ray::new(Point3(-5.0, -5.0, -5.0), Vec3(1.0, 1.0, 1.0).normalize());
+
+        assert!(a.hit(&towards, 0.0, f32::MAX));
+        assert!(!a.hit(&towards, 0.0, 3.0));
+        assert!(!a.hit(&away, 0.0, f32::MAX));
+        assert!(!a.hit(&miss, 0.0, f32::MAX));
+        assert!(a.hit(&diagonal, 0.0, f32::MAX));
+    }
+
+    #[test]
+    fn aabb_hit_flat() {
+        // A zero-thickness box must still be hit by a ray crossing it
+        let a = Aabb::new(Point3(-1.0, 0.0, -1.0), Point3(1.0, 0.0, 1.0));
+        let r = Ray::new(Point3(0.0, 1.0, 0.0), Vec3(0.0, -1.0, 0.0));
+        assert!(a.hit(&r, 0.0, f32::MAX));
+    }
+}
@@ -0,0 +1,356 @@
+// What a ray that leaves the scene sees
+use crate::color::Color;
+use crate::image::Framebuffer;
+use crate::sampling::Distribution2D;
+use crate::vector::{Normalize, Vec3};
+use rand::{Rng, RngCore};
+use std::f32::consts::PI;
+use std::io;
+use std::path::Path;
+
+pub trait Background: Send + Sync {
+    // dir is a unit vector pointing away from the scene
+    fn color(&self, dir: Vec3) -> Color;
+
+    // Picks a direction towards the background for direct lighting.
+    // Returns the direction, the radiance coming from it and the
+    // solid angle pdf, or None if the background cannot be sampled.
+    fn sample(&self, _rng: &mut dyn RngCore) -> Option<(Vec3, Color, f32)> {
+        None
+    }
+
+    // Solid angle pdf of sample() returning dir
+    fn pdf(&self, _dir: Vec3) -> f32 {
+        0.0
+    }
+}
+
+pub struct SolidColor {
+    color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> Self {
+        Self { color }
+    }
+
+    pub fn black() -> Self {
+        Self::new(Color::zero())
+    }
+}
+
+impl Background for SolidColor {
+    fn color(&self, _: Vec3) -> Color {
+        self.color
+    }
+}
+
+// Vertical blend from the horizon color to the zenith color
+pub struct Gradient {
+    horizon: Color,
+    zenith: Color,
+}
+
+impl Gradient {
+    pub fn new(horizon: Color, zenith: Color) -> Self {
+        Self { horizon, zenith }
+    }
+
+    pub fn sky() -> Self {
+        Self::new(Color(1.0, 1.0, 1.0), Color(0.5, 0.7, 1.0))
+    }
+}
+
+impl Background for Gradient {
+    fn color(&self, dir: Vec3) -> Color {
+        let t = 0.5 * (dir.y() + 1.0);
+        Color::lerp(self.horizon, self.zenith, t)
+    }
+}
+
+// Equirectangular (latitude-longitude) radiance map.
+// The top row looks up (+y), the middle row looks at the horizon and
+// x runs once around the vertical axis starting from -x through +z.
+pub struct EnvironmentMap {
+    image: Framebuffer<Color>,
+    // Proportional to luminance times the solid angle around each pixel
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    pub fn new(image: Framebuffer<Color>) -> Self {
+        assert!(
+            image.width() > 0 && image.height() > 0,
+            "environment map must not be empty"
+        );
+        let (width, height) = (image.width(), image.height());
+        let luminance = |x: usize, y: usize| image.get(x % width, y).luminance().max(0.0);
+        let mut weights = Vec::with_capacity(width * height);
+        for y in 0..height {
+            // Rows near the poles cover less of the sphere
+            let sin_theta = (PI * (y as f32 + 0.5) / height as f32).sin();
+            for x in 0..width {
+                // Bilinear lookup spreads every pixel into its neighbours,
+                // so take the brightest one around to keep the pdf positive
+                let mut max = 0.0f32;
+                for ny in y.saturating_sub(1)..usize::min(y + 2, height) {
+                    for nx in x + width - 1..=x + width + 1 {
+                        max = max.max(luminance(nx, ny));
+                    }
+                }
+                weights.push(max * sin_theta);
+            }
+        }
+        let distribution = Distribution2D::new(&weights, width, height);
+        Self {
+            image,
+            distribution,
+        }
+    }
+
+    // Usually a .hdr or .exr file, LDR images are linearized
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::new(Framebuffer::<Color>::load(path)?))
+    }
+
+    pub fn image(&self) -> &Framebuffer<Color> {
+        &self.image
+    }
+
+    // Maps a unit direction to image coordinates in [0, 1]^2
+    pub fn direction_to_uv(dir: Vec3) -> (f32, f32) {
+        let phi = f32::atan2(dir.z(), -dir.x());
+        let theta = f32::acos(dir.y().clamp(-1.0, 1.0));
+        let u = phi / (2.0 * PI);
+        (if u < 0.0 { u + 1.0 } else { u }, theta / PI)
+    }
+
+    pub fn uv_to_direction(u: f32, v: f32) -> Vec3 {
+        let (phi, theta) = (2.0 * PI * u, PI * v);
+        Vec3(
+            -theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+        )
+    }
+
+    // Bilinear lookup, wrapping around horizontally and clamped at the poles
+    pub fn lookup(&self, u: f32, v: f32) -> Color {
+        let (width, height) = (self.image.width(), self.image.height());
+        let x = u * width as f32 - 0.5;
+        let y = (v * height as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+
+        let x0 = (x0 as isize).rem_euclid(width as isize) as usize;
+        let x1 = (x0 + 1) % width;
+        let y0 = y0 as usize;
+        let y1 = usize::min(y0 + 1, height - 1);
+
+        let top = Color::lerp(*self.image.get(x0, y0), *self.image.get(x1, y0), tx);
+        let bottom = Color::lerp(*self.image.get(x0, y1), *self.image.get(x1, y1), tx);
+        Color::lerp(top, bottom, ty)
+    }
+}
+
+impl Background for EnvironmentMap {
+    fn color(&self, dir: Vec3) -> Color {
+        let (u, v) = Self::direction_to_uv(dir);
+        self.lookup(u, v)
+    }
+
+    fn sample(&self, rng: &mut dyn RngCore) -> Option<(Vec3, Color, f32)> {
+        if self.distribution.integral() <= 0.0 {
+            return None;
+        }
+        let ((u, v), pdf) = self.distribution.sample(rng.gen(), rng.gen());
+        let sin_theta = (PI * v).sin();
+        if pdf <= 0.0 || sin_theta <= 0.0 {
+            return None;
+        }
+        // The uv square maps onto the sphere with a 2 pi^2 sin(theta) jacobian
+        let pdf = pdf / (2.0 * PI * PI * sin_theta);
+        let dir = Self::uv_to_direction(u, v).normalize();
+        Some((dir, self.lookup(u, v), pdf))
+    }
+
+    fn pdf(&self, dir: Vec3) -> f32 {
+        if self.distribution.integral() <= 0.0 {
+            return 0.0;
+        }
+        let (u, v) = Self::direction_to_uv(dir);
+        let sin_theta = (PI * v).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::Len;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn assert_close(a: Color, b: Color) {
+        assert!(
+            (a.r() - b.r()).abs() < 1e-4
+                && (a.g() - b.g()).abs() < 1e-4
+                && (a.b() - b.b()).abs() < 1e-4,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn solid_and_gradient() {
+        let up = Vec3(0.0, 1.0, 0.0);
+        let down = Vec3(0.0, -1.0, 0.0);
+        let sky = Gradient::sky();
+        assert_eq!(sky.color(up), Color(0.5, 0.7, 1.0));
+        assert_eq!(sky.color(down), Color(1.0, 1.0, 1.0));
+        assert_eq!(
+            SolidColor::new(Color(0.1, 0.2, 0.3)).color(up),
+            Color(0.1, 0.2, 0.3)
+        );
+        assert_eq!(SolidColor::black().color(down), Color::zero());
+    }
+
+    #[test]
+    fn direction_uv_roundtrip() {
+        for &dir in &[
+            Vec3(1.0, 0.0, 0.0),
+            Vec3(0.0, 0.0, -1.0),
+            Vec3(0.6, 0.0, 0.8),
+            Vec3(0.48, -0.6, -0.64),
+        ] {
+            let (u, v) = EnvironmentMap::direction_to_uv(dir);
+            assert!((0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v));
+            let back = EnvironmentMap::uv_to_direction(u, v);
+            assert!(Vec3::almost_eq(dir, back, 1e-5), "{:?} != {:?}", dir, back);
+        }
+        assert_eq!(EnvironmentMap::direction_to_uv(Vec3(0.0, 1.0, 0.0)).1, 0.0);
+        assert_eq!(EnvironmentMap::direction_to_uv(Vec3(0.0, -1.0, 0.0)).1, 1.0);
+    }
+
+    #[test]
+    fn environment_map_bilinear_lookup() {
+        // Two rows of four pixels: a bright sky above a dark ground
+        let mut image = Framebuffer::new(4, 2, Color::zero());
+        for x in 0..4 {
+            image.set(x, 0, Color(x as f32, 1.0, 1.0));
+        }
+        let env = EnvironmentMap::new(image);
+
+        // Pixel centers return the pixel exactly
+        assert_close(env.lookup(0.375, 0.25), Color(1.0, 1.0, 1.0));
+        assert_close(env.lookup(0.375, 0.75), Color::zero());
+        // Halfway between the rows and between columns 1 and 2
+        assert_close(env.lookup(0.5, 0.5), Color(0.75, 0.5, 0.5));
+        // Clamped at the poles
+        assert_close(env.lookup(0.375, 0.0), Color(1.0, 1.0, 1.0));
+        // Wraps around the seam between the last and the first column
+        assert_close(env.lookup(0.0, 0.25), Color(1.5, 1.0, 1.0));
+        assert_close(env.lookup(1.0, 0.25), Color(1.5, 1.0, 1.0));
+
+        // Looking along +x samples the middle of the image at the horizon
+        assert_close(env.color(Vec3(1.0, 0.0, 0.0)), Color(0.75, 0.5, 0.5));
+        assert_eq!(env.color(Vec3(0.0, -1.0, 0.0)), Color::zero());
+    }
+
+    fn spot_map() -> EnvironmentMap {
+        // A dim sky with a small bright sun above the horizon
+        let mut image = Framebuffer::new(32, 16, Color(0.2, 0.3, 0.5));
+        image.set(9, 4, Color(200.0, 180.0, 160.0));
+        image.set(10, 4, Color(200.0, 180.0, 160.0));
+        EnvironmentMap::new(image)
+    }
+
+    #[test]
+    fn environment_map_pdf_integrates_to_one() {
+        let env = spot_map();
+        // Midpoint rule over (theta, phi) with the sin(theta) solid angle factor
+        let (n_theta, n_phi) = (256, 512);
+        let mut total = 0.0;
+        for i in 0..n_theta {
+            let theta = PI * (i as f32 + 0.5) / n_theta as f32;
+            for j in 0..n_phi {
+                let phi = 2.0 * PI * (j as f32 + 0.5) / n_phi as f32;
+                let dir = EnvironmentMap::uv_to_direction(phi / (2.0 * PI), theta / PI);
+                total += env.pdf(dir) * theta.sin();
+            }
+        }
+        total *= (PI / n_theta as f32) * (2.0 * PI / n_phi as f32);
+        assert!((total - 1.0).abs() < 1e-2, "{}", total);
+    }
+
+    #[test]
+    fn environment_map_sampling_prefers_bright_pixels() {
+        let env = spot_map();
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut towards_sun = 0;
+        let mut mismatched = 0;
+        // The estimate of the radiance integral over the sphere is unbiased
+        let mut estimate = 0.0;
+        let n = 20_000;
+        for _ in 0..n {
+            let (dir, radiance, pdf) = env.sample(&mut rng).unwrap();
+            assert!((dir.len() - 1.0).abs() < 1e-4);
+            // Directions right on a pixel border may round into the neighbour
+            if (pdf - env.pdf(dir)).abs() > 1e-3 * pdf {
+                mismatched += 1;
+            }
+            if radiance.r() > 1.0 {
+                towards_sun += 1;
+            }
+            estimate += radiance.luminance() / pdf / n as f32;
+        }
+        assert!(towards_sun > n / 3, "{}", towards_sun);
+        assert!(mismatched < n / 100, "{}", mismatched);
+
+        let mut expected = 0.0;
+        let (width, height) = (env.image().width(), env.image().height());
+        for y in 0..height {
+            let theta0 = PI * y as f32 / height as f32;
+            let theta1 = PI * (y + 1) as f32 / height as f32;
+            let solid_angle = 2.0 * PI / width as f32 * (theta0.cos() - theta1.cos());
+            for x in 0..width {
+                expected += env.image().get(x, y).luminance() * solid_angle;
+            }
+        }
+        // Bilinear lookup blurs the sun a little, so this is only approximate
+        assert!(
+            (estimate - expected).abs() < 0.1 * expected,
+            "{} {}",
+            estimate,
+            expected
+        );
+    }
+
+    #[test]
+    fn black_environment_map_is_not_sampled() {
+        let env = EnvironmentMap::new(Framebuffer::new(4, 2, Color::zero()));
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(env.sample(&mut rng).is_none());
+        assert_eq!(env.pdf(Vec3(0.0, 1.0, 0.0)), 0.0);
+        assert!(Gradient::sky().sample(&mut rng).is_none());
+    }
+
+    #[test]
+    fn environment_map_loads_hdr() {
+        let mut image = Framebuffer::new(8, 4, Color::zero());
+        image.set(2, 1, Color(4.0, 2.0, 1.0));
+        let path = std::env::temp_dir().join(format!("envmap-{}.hdr", std::process::id()));
+        image.save(&path).unwrap();
+        let env = EnvironmentMap::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let env = env.unwrap();
+        assert_eq!(env.image().width(), 8);
+        let c = env.lookup(2.5 / 8.0, 1.5 / 4.0);
+        assert!((c.r() - 4.0).abs() < 0.05 && (c.g() - 2.0).abs() < 0.05);
+    }
+}
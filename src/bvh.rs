@@ -0,0 +1,388 @@
+use crate::aabb::Aabb;
+use crate::point::Point3;
+use crate::ray::Ray;
+use crate::vector::Vec3;
+
+const BIN_COUNT: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+// Cost of visiting a node relative to a single primitive intersection
+const TRAVERSAL_COST: f32 = 0.5;
+// Past this depth only median splits are made, which bounds the traversal stack
+const MAX_SAH_DEPTH: usize = 64;
+const STACK_SIZE: usize = 128;
+
+// 32 bytes, so two nodes fit into a cache line.
+// Interior nodes keep the left child right after themselves and store
+// the index of the right child in `offset`; leaves store the first
+// entry of `Bvh::indices` in `offset` and the number of primitives in `count`.
+#[derive(Clone, Copy, Debug)]
+struct BvhNode {
+    bbox: Aabb,
+    offset: u32,
+    count: u16,
+    axis: u8,
+}
+
+impl BvhNode {
+    fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+// Bounding volume hierarchy over anything that has a bounding box.
+// It only stores primitive indices, the caller intersects the primitives itself.
+#[derive(Clone, Debug, Default)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    indices: Vec<u32>,
+}
+
+#[derive(Clone, Copy)]
+struct Bin {
+    bbox: Option<Aabb>,
+    count: usize,
+}
+
+fn surrounding(a: Option<Aabb>, b: Aabb) -> Option<Aabb> {
+    Some(a.map_or(b, |a| a.surrounding(b)))
+}
+
+impl Bvh {
+    // Builds the tree with the binned surface area heuristic
+    pub fn build(bounds: &[Aabb]) -> Bvh {
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(2 * bounds.len()),
+            indices: (0..bounds.len() as u32).collect(),
+        };
+        if !bounds.is_empty() {
+            let centroids: Vec<Point3> = bounds.iter().map(Aabb::centroid).collect();
+            bvh.build_recursive(bounds, &centroids, 0, bounds.len(), 0);
+        }
+        bvh
+    }
+
+    fn build_recursive(
+        &mut self,
+        bounds: &[Aabb],
+        centroids: &[Point3],
+        start: usize,
+        end: usize,
+        depth: usize,
+    ) -> usize {
+        let range = &self.indices[start..end];
+        let bbox = range
+            .iter()
+            .map(|&i| bounds[i as usize])
+            .reduce(Aabb::surrounding)
+            .unwrap();
+        let centroid_bbox = range
+            .iter()
+            .map(|&i| Aabb::new(centroids[i as usize], centroids[i as usize]))
+            .reduce(Aabb::surrounding)
+            .unwrap();
+
+        let node_index = self.nodes.len();
+        self.nodes.push(BvhNode {
+            bbox,
+            offset: start as u32,
+            count: (end - start) as u16,
+            axis: 0,
+        });
+
+        let count = end - start;
+        let axis = centroid_bbox.longest_axis();
+        let extent = centroid_bbox.max[axis] - centroid_bbox.min[axis];
+        if count == 1 || extent <= 0.0 && count <= u16::MAX as usize {
+            // All centroids coincide, no split can separate them
+            return node_index;
+        }
+
+        let mid = match self.sah_split(bounds, centroids, start, end, bbox, centroid_bbox, depth) {
+            Some(mid) => mid,
+            None if count <= MAX_LEAF_SIZE => return node_index,
+            None => self.median_split(centroids, start, end, axis),
+        };
+
+        self.nodes[node_index].axis = axis as u8;
+        self.nodes[node_index].count = 0;
+        self.build_recursive(bounds, centroids, start, mid, depth + 1);
+        let right = self.build_recursive(bounds, centroids, mid, end, depth + 1);
+        self.nodes[node_index].offset = right as u32;
+        node_index
+    }
+
+    // Returns the partition point if splitting is cheaper than a leaf
+    // (or if the range is too large for a leaf)
+    #[allow(clippy::too_many_arguments)]
+    fn sah_split(
+        &mut self,
+        bounds: &[Aabb],
+        centroids: &[Point3],
+        start: usize,
+        end: usize,
+        bbox: Aabb,
+        centroid_bbox: Aabb,
+        depth: usize,
+    ) -> Option<usize> {
+        if depth >= MAX_SAH_DEPTH {
+            return None;
+        }
+
+        let count = end - start;
+        let axis = centroid_bbox.longest_axis();
+        let min = centroid_bbox.min[axis];
+        let scale = BIN_COUNT as f32 / (centroid_bbox.max[axis] - min);
+        let bin_of = |i: u32| {
+            let b = ((centroids[i as usize][axis] - min) * scale) as usize;
+            b.min(BIN_COUNT - 1)
+        };
+
+        let mut bins = [Bin {
+            bbox: None,
+            count: 0,
+        }; BIN_COUNT];
+        for &i in &self.indices[start..end] {
+            let bin = &mut bins[bin_of(i)];
+            bin.bbox = surrounding(bin.bbox, bounds[i as usize]);
+            bin.count += 1;
+        }
+
+        // Sweep from the right to collect the cost of everything right of each plane
+        let mut right_cost = [0.0; BIN_COUNT - 1];
+        let mut acc_bbox = None;
+        let mut acc_count = 0;
+        for split in (1..BIN_COUNT).rev() {
+            if let Some(b) = bins[split].bbox {
+                acc_bbox = surrounding(acc_bbox, b);
+            }
+            acc_count += bins[split].count;
+            right_cost[split - 1] = acc_bbox.map_or(0.0, |b| b.surface_area()) * acc_count as f32;
+        }
+
+        let mut best = None;
+        let mut acc_bbox = None;
+        let mut acc_count = 0;
+        for split in 0..BIN_COUNT - 1 {
+            if let Some(b) = bins[split].bbox {
+                acc_bbox = surrounding(acc_bbox, b);
+            }
+            acc_count += bins[split].count;
+            if acc_count == 0 || acc_count == count {
+                continue;
+            }
+            let cost =
+                acc_bbox.map_or(0.0, |b| b.surface_area()) * acc_count as f32 + right_cost[split];
+            if best.is_none_or(|(_, best_cost)| cost < best_cost) {
+                best = Some((split, cost));
+            }
+        }
+
+        let (split, cost) = best?;
+        let area = bbox.surface_area();
+        let split_cost = if area > 0.0 {
+            TRAVERSAL_COST + cost / area
+        } else {
+            TRAVERSAL_COST
+        };
+        if count <= MAX_LEAF_SIZE && split_cost >= count as f32 {
+            return None;
+        }
+
+        let range = &mut self.indices[start..end];
+        let mut mid = 0;
+        for i in 0..range.len() {
+            if bin_of(range[i]) <= split {
+                range.swap(i, mid);
+                mid += 1;
+            }
+        }
+        Some(start + mid)
+    }
+
+    fn median_split(
+        &mut self,
+        centroids: &[Point3],
+        start: usize,
+        end: usize,
+        axis: usize,
+    ) -> usize {
+        let mid = (start + end) / 2;
+        self.indices[start..end].select_nth_unstable_by(mid - start, |&a, &b| {
+            f32::total_cmp(&centroids[a as usize][axis], &centroids[b as usize][axis])
+        });
+        mid
+    }
+
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        self.nodes.first().map(|n| n.bbox)
+    }
+
+    // Visits the nodes front to back and calls `hit_primitive(index, t_max)` for
+    // every primitive whose leaf the ray reaches. The callback returns the distance
+    // of a closer hit, which then shrinks the search interval.
+    pub fn traverse<F>(&self, r: &Ray, t_min: f32, mut t_max: f32, mut hit_primitive: F)
+    where
+        F: FnMut(usize, f32) -> Option<f32>,
+    {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let inv_dir = Vec3(r.dir.0.recip(), r.dir.1.recip(), r.dir.2.recip());
+        let dir_is_neg = [inv_dir.0 < 0.0, inv_dir.1 < 0.0, inv_dir.2 < 0.0];
+        let mut stack = [0u32; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            if node.bbox.hit_inv_dir(r.orig, inv_dir, t_min, t_max) {
+                if node.is_leaf() {
+                    let first = node.offset as usize;
+                    for &i in &self.indices[first..first + node.count as usize] {
+                        if let Some(t) = hit_primitive(i as usize, t_max) {
+                            t_max = t;
+                        }
+                    }
+                } else {
+                    // Descend into the child closer to the ray origin first
+                    let (near, far) = if dir_is_neg[node.axis as usize] {
+                        (node.offset, current as u32 + 1)
+                    } else {
+                        (current as u32 + 1, node.offset)
+                    };
+                    stack[stack_len] = far;
+                    stack_len += 1;
+                    current = near as usize;
+                    continue;
+                }
+            }
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            current = stack[stack_len] as usize;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::hittable::Hittable;
+    use crate::hittable_vec::HittableVec;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::vector::uniform_on_unit_sphere;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::sync::Arc;
+
+    fn random_spheres(rng: &mut StdRng, n: usize) -> HittableVec {
+        let material = Arc::new(Lambertian::new(Color(0.5, 0.5, 0.5)));
+        let mut world = HittableVec::new();
+        for _ in 0..n {
+            let center = Point3(
+                rng.gen_range(-10.0..10.0),
+                rng.gen_range(-10.0..10.0),
+                rng.gen_range(-10.0..10.0),
+            );
+            let radius = rng.gen_range(0.05..1.0);
+            world.push(Box::new(Sphere::new(center, radius, material.clone())));
+        }
+        world
+    }
+
+    #[test]
+    fn bvh_same_closest_hit_as_linear() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let world = random_spheres(&mut rng, 300);
+
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let origin = Point3(
+                rng.gen_range(-12.0..12.0),
+                rng.gen_range(-12.0..12.0),
+                rng.gen_range(-12.0..12.0),
+            );
+            let r = Ray::new(origin, Vec3::from(uniform_on_unit_sphere(&mut rng)));
+            let expected = world.hit_linear(&r, 1e-3, f32::MAX);
+            let actual = world.hit(&r, 1e-3, f32::MAX);
+            match (expected, actual) {
+                (Some((e, _)), Some((a, _))) => {
+                    hits += 1;
+                    assert_eq!(e.t(), a.t());
+                    assert_eq!(e.p(), a.p());
+                    assert_eq!(e.n(), a.n());
+                }
+                (None, None) => {}
+                _ => panic!("BVH and linear scan disagree for {:?}", r),
+            }
+        }
+        assert!(hits > 0);
+    }
+
+    #[test]
+    fn bvh_covers_every_primitive_once() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let bounds: Vec<Aabb> = (0..1000)
+            .map(|_| {
+                let c = Point3(
+                    rng.gen_range(-10.0..10.0),
+                    rng.gen_range(-10.0..10.0),
+                    rng.gen_range(-10.0..10.0),
+                );
+                let r = Vec3(0.1, 0.1, 0.1);
+                Aabb::new(c - r, c + r)
+            })
+            .collect();
+        let bvh = Bvh::build(&bounds);
+
+        let mut seen = vec![0; bounds.len()];
+        for node in bvh.nodes.iter().filter(|n| n.is_leaf()) {
+            let first = node.offset as usize;
+            for &i in &bvh.indices[first..first + node.count as usize] {
+                seen[i as usize] += 1;
+                let b = bounds[i as usize];
+                assert_eq!(node.bbox.surrounding(b), node.bbox);
+            }
+        }
+        assert!(seen.iter().all(|&n| n == 1));
+        assert!(bvh.nodes.len() < 2 * bounds.len());
+    }
+
+    #[test]
+    fn bvh_identical_boxes() {
+        // SAH can not separate these, the builder must still terminate
+        let b = Aabb::new(Point3(0.0, 0.0, 0.0), Point3(1.0, 1.0, 1.0));
+        let bvh = Bvh::build(&[b; 100]);
+        let r = Ray::new(Point3(0.5, 0.5, -5.0), Vec3(0.0, 0.0, 1.0));
+        let mut visited = 0;
+        bvh.traverse(&r, 0.0, f32::MAX, |_, _| {
+            visited += 1;
+            None
+        });
+        assert_eq!(visited, 100);
+    }
+
+    #[test]
+    fn bvh_empty() {
+        let bvh = Bvh::build(&[]);
+        assert_eq!(bvh.bounding_box(), None);
+        let r = Ray::new(Point3(0.0, 0.0, -5.0), Vec3(0.0, 0.0, 1.0));
+        bvh.traverse(&r, 0.0, f32::MAX, |_, _| panic!("nothing to visit"));
+    }
+
+    #[test]
+    fn bvh_single_object() {
+        let mut world = HittableVec::new();
+        world.push(Box::new(Sphere::new(
+            Point3(0.0, 0.0, 0.0),
+            1.0,
+            Arc::new(Lambertian::new(Color(0.5, 0.5, 0.5))),
+        )));
+        let r = Ray::new(Point3(0.0, 0.0, -5.0), Vec3(0.0, 0.0, 1.0));
+        let (hit, _) = world.hit(&r, 1e-3, f32::MAX).unwrap();
+        assert_eq!(hit.t(), 4.0);
+    }
+}
@@ -12,6 +12,9 @@ pub struct Camera {
     v: Vec3,
     w: Vec3,
     lens_radius: f32,
+    // Rays get random times in [shutter_open, shutter_close)
+    shutter_open: f32,
+    shutter_close: f32,
 }
 
 impl Camera {
@@ -47,10 +50,19 @@ impl Camera {
             v,
             w,
             lens_radius,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
         }
     }
 
-    pub fn get_ray<R: Rng>(&self, rng: &mut R, s: f32, t: f32) -> Ray {
+    pub fn with_shutter(mut self, open: f32, close: f32) -> Camera {
+        assert!(open <= close, "the shutter must open before it closes");
+        self.shutter_open = open;
+        self.shutter_close = close;
+        self
+    }
+
+    pub fn get_ray<R: Rng + ?Sized>(&self, rng: &mut R, s: f32, t: f32) -> Ray {
         let (x, y) = uniform_in_unit_disk(rng);
         let offset = self.lens_radius * (x * self.u + y * self.v);
         let direction = Vec3::normalize(
@@ -58,9 +70,16 @@ impl Camera {
                 - self.origin
                 - offset,
         );
+        // An instantaneous shutter draws no random number, so still images stay the same
+        let time = if self.shutter_open < self.shutter_close {
+            rng.gen_range(self.shutter_open..self.shutter_close)
+        } else {
+            self.shutter_open
+        };
         Ray::new(
             self.origin + offset,
             direction,
         )
+        .with_time(time)
     }
 }
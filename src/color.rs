@@ -26,6 +26,11 @@ impl Color {
         (1.0 - t) * self + t * end
     }
 
+    // Rec. 709 weights of linear RGB
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.0 + 0.7152 * self.1 + 0.0722 * self.2
+    }
+
     pub fn random<R: Rng>(rng: &mut R) -> Color {
         Color(rng.gen(), rng.gen(), rng.gen())
     }
@@ -0,0 +1,674 @@
+// Single part scanline OpenEXR images with R, G, B channels
+use crate::color::Color;
+use crate::image::Framebuffer;
+use crate::zlib;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+
+const MAGIC: u32 = 20_000_630;
+const VERSION: u32 = 2;
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExrPixelType {
+    Half,
+    Float,
+}
+
+impl ExrPixelType {
+    fn id(self) -> i32 {
+        match self {
+            ExrPixelType::Half => 1,
+            ExrPixelType::Float => 2,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ExrPixelType::Half => 2,
+            ExrPixelType::Float => 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExrCompression {
+    None,
+    // zlib over blocks of 16 scanlines
+    Zip,
+}
+
+impl ExrCompression {
+    fn id(self) -> u8 {
+        match self {
+            ExrCompression::None => 0,
+            ExrCompression::Zip => 3,
+        }
+    }
+
+    fn lines_per_block(self) -> usize {
+        match self {
+            ExrCompression::None => 1,
+            ExrCompression::Zip => 16,
+        }
+    }
+}
+
+pub fn f32_to_f16(v: f32) -> u16 {
+    let bits = v.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exp == 0xff {
+        // Infinity stays infinity, NaN stays NaN
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let e = exp - 127 + 15;
+    if e >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if e <= 0 {
+        if e < -10 {
+            return sign;
+        }
+        // Subnormal half, shift the mantissa with the implicit bit in place
+        let m = mantissa | 0x80_0000;
+        let shift = (14 - e) as u32;
+        let half = m >> shift;
+        let rest = m & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round = rest > halfway || rest == halfway && half & 1 == 1;
+        return sign | (half + round as u32) as u16;
+    }
+
+    let half = ((e as u32) << 10) | (mantissa >> 13);
+    let rest = mantissa & 0x1fff;
+    // Round to nearest even, a carry into the exponent is still correct
+    let round = rest > 0x1000 || rest == 0x1000 && half & 1 == 1;
+    sign | (half + round as u32) as u16
+}
+
+pub fn f16_to_f32(h: u16) -> f32 {
+    let sign = ((h & 0x8000) as u32) << 16;
+    let exp = ((h >> 10) & 0x1f) as u32;
+    let mantissa = (h & 0x3ff) as u32;
+    let bits = match exp {
+        0 if mantissa == 0 => sign,
+        0 => {
+            // Normalize a subnormal half
+            let shift = mantissa.leading_zeros() - 21;
+            let mantissa = (mantissa << shift) & 0x3ff;
+            sign | ((127 - 15 + 1 - shift) << 23) | (mantissa << 13)
+        }
+        0x1f => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exp + 127 - 15) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
+
+fn write_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+fn box2i(width: usize, height: usize) -> Vec<u8> {
+    [0, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect()
+}
+
+// Byte interleaving and delta predictor applied before zlib compression
+fn zip_encode(raw: &[u8]) -> Vec<u8> {
+    let mut data: Vec<u8> = raw
+        .iter()
+        .step_by(2)
+        .chain(raw.iter().skip(1).step_by(2))
+        .copied()
+        .collect();
+    for i in (1..data.len()).rev() {
+        data[i] = data[i].wrapping_sub(data[i - 1]).wrapping_add(128);
+    }
+    zlib::compress(&data)
+}
+
+fn zip_decode(compressed: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    let mut data = zlib::decompress(compressed)?;
+    if data.len() != size {
+        return Err(invalid_data("EXR block has the wrong size"));
+    }
+    for i in 1..data.len() {
+        data[i] = data[i - 1].wrapping_add(data[i]).wrapping_sub(128);
+    }
+    let (first, second) = data.split_at(size.div_ceil(2));
+    let mut raw = Vec::with_capacity(size);
+    for i in 0..size {
+        raw.push(if i % 2 == 0 {
+            first[i / 2]
+        } else {
+            second[i / 2]
+        });
+    }
+    Ok(raw)
+}
+
+fn header(
+    width: usize,
+    height: usize,
+    pixel_type: ExrPixelType,
+    compression: ExrCompression,
+) -> Vec<u8> {
+    let mut header = Vec::new();
+    header.extend_from_slice(&MAGIC.to_le_bytes());
+    header.extend_from_slice(&VERSION.to_le_bytes());
+
+    // Channels must be sorted by name
+    let mut channels = Vec::new();
+    for name in ["B", "G", "R"] {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&pixel_type.id().to_le_bytes());
+        // pLinear and reserved bytes, then x and y sampling
+        channels.extend_from_slice(&[0, 0, 0, 0]);
+        channels.extend_from_slice(&1i32.to_le_bytes());
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channels.push(0);
+
+    let h = &mut header;
+    write_attribute(h, "channels", "chlist", &channels);
+    write_attribute(h, "compression", "compression", &[compression.id()]);
+    write_attribute(h, "dataWindow", "box2i", &box2i(width, height));
+    write_attribute(h, "displayWindow", "box2i", &box2i(width, height));
+    write_attribute(h, "lineOrder", "lineOrder", &[0]);
+    write_attribute(h, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+    write_attribute(h, "screenWindowCenter", "v2f", &[0; 8]);
+    write_attribute(h, "screenWindowWidth", "float", &1f32.to_le_bytes());
+    header.push(0);
+    header
+}
+
+struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .pos
+            .checked_add(n)
+            .and_then(|end| self.data.get(self.pos..end))
+            .ok_or_else(|| invalid_data("EXR file is truncated"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    // Null terminated string
+    fn c_str(&mut self) -> io::Result<String> {
+        let len = self
+            .data
+            .get(self.pos..)
+            .unwrap_or_default()
+            .iter()
+            .position(|&c| c == 0)
+            .ok_or_else(|| invalid_data("EXR file is truncated"))?;
+        let s = String::from_utf8_lossy(self.take(len)?).into_owned();
+        self.pos += 1;
+        Ok(s)
+    }
+}
+
+impl Framebuffer<Color> {
+    pub fn write_exr<W: Write>(
+        &self,
+        w: &mut W,
+        pixel_type: ExrPixelType,
+        compression: ExrCompression,
+    ) -> io::Result<()> {
+        let width = self.width();
+        let lines = compression.lines_per_block();
+        let mut blocks = Vec::new();
+        for (block, rows) in self.pixels().chunks(width.max(1) * lines).enumerate() {
+            // Each scanline stores all B values, then all G values, then all R values
+            let mut raw = Vec::with_capacity(rows.len() * 3 * pixel_type.size());
+            for row in rows.chunks(width.max(1)) {
+                for channel in [Color::b, Color::g, Color::r] {
+                    for pixel in row {
+                        let v = channel(pixel);
+                        match pixel_type {
+                            ExrPixelType::Half => {
+                                raw.extend_from_slice(&f32_to_f16(v).to_le_bytes())
+                            }
+                            ExrPixelType::Float => raw.extend_from_slice(&v.to_le_bytes()),
+                        }
+                    }
+                }
+            }
+            let data = match compression {
+                ExrCompression::None => raw,
+                ExrCompression::Zip => {
+                    // Incompressible blocks are stored as they are
+                    let compressed = zip_encode(&raw);
+                    if compressed.len() < raw.len() {
+                        compressed
+                    } else {
+                        raw
+                    }
+                }
+            };
+            blocks.push(((block * lines) as i32, data));
+        }
+
+        let header = header(width, self.height(), pixel_type, compression);
+        w.write_all(&header)?;
+
+        // Offset table, counted from the start of the file
+        let mut offset = (header.len() + 8 * blocks.len()) as u64;
+        for (_, data) in &blocks {
+            w.write_all(&offset.to_le_bytes())?;
+            offset += 8 + data.len() as u64;
+        }
+        for (y, data) in &blocks {
+            w.write_all(&y.to_le_bytes())?;
+            w.write_all(&(data.len() as i32).to_le_bytes())?;
+            w.write_all(data)?;
+        }
+        Ok(())
+    }
+
+    // Reads what write_exr produces: RGB half or float channels, uncompressed or ZIP
+    pub fn read_exr<R: Read>(mut r: R) -> io::Result<Self> {
+        let mut data = Vec::new();
+        r.read_to_end(&mut data)?;
+        let mut r = ByteReader {
+            data: &data,
+            pos: 0,
+        };
+        let read_i32 = |b: &[u8]| match b.get(..4) {
+            Some(b) => Ok(i32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            None => Err(invalid_data("EXR file is truncated")),
+        };
+        // Sizes and offsets must not be negative
+        let read_size = |b: &[u8]| {
+            usize::try_from(read_i32(b)?).map_err(|_| invalid_data("negative size in EXR file"))
+        };
+
+        if read_i32(r.take(4)?)? as u32 != MAGIC {
+            return Err(invalid_data("not an OpenEXR image"));
+        }
+        if read_i32(r.take(4)?)? as u32 & 0xff != VERSION {
+            return Err(invalid_data("unsupported OpenEXR version"));
+        }
+
+        let mut channels = Vec::new();
+        let mut compression = None;
+        let mut window = None;
+        loop {
+            let name = r.c_str()?;
+            if name.is_empty() {
+                break;
+            }
+            let kind = r.c_str()?;
+            let size = read_size(r.take(4)?)?;
+            let value = r.take(size)?;
+            match (name.as_str(), kind.as_str()) {
+                ("channels", "chlist") => {
+                    // Name, pixel type, linearity, reserved bytes and sampling
+                    let mut rest = value;
+                    while rest.first().is_some_and(|&c| c != 0) {
+                        let truncated = || invalid_data("EXR channel list is truncated");
+                        let end = rest.iter().position(|&c| c == 0).ok_or_else(truncated)?;
+                        let name = String::from_utf8_lossy(&rest[..end]).into_owned();
+                        let pixel_type = match read_i32(rest.get(end + 1..).unwrap_or_default()) {
+                            Ok(1) => ExrPixelType::Half,
+                            Ok(2) => ExrPixelType::Float,
+                            Ok(_) => return Err(invalid_data("unsupported EXR pixel type")),
+                            Err(_) => return Err(truncated()),
+                        };
+                        channels.push((name, pixel_type));
+                        rest = rest.get(end + 17..).ok_or_else(truncated)?;
+                    }
+                }
+                ("compression", _) => {
+                    compression = Some(match value.first() {
+                        Some(0) => ExrCompression::None,
+                        Some(3) => ExrCompression::Zip,
+                        Some(_) => return Err(invalid_data("unsupported EXR compression")),
+                        None => return Err(invalid_data("EXR compression is truncated")),
+                    })
+                }
+                ("dataWindow", "box2i") => {
+                    if value.len() < 16 {
+                        return Err(invalid_data("EXR data window is truncated"));
+                    }
+                    let v = |i: usize| read_i32(&value[4 * i..]).map(i64::from);
+                    let (w, h) = (v(2)? - v(0)? + 1, v(3)? - v(1)? + 1);
+                    if w <= 0 || h <= 0 {
+                        return Err(invalid_data("EXR data window is empty"));
+                    }
+                    window = Some((w as usize, h as usize));
+                }
+                _ => {}
+            }
+        }
+        let compression = compression.ok_or_else(|| invalid_data("EXR has no compression"))?;
+        let (width, height) = window.ok_or_else(|| invalid_data("EXR has no data window"))?;
+        if channels.is_empty() {
+            return Err(invalid_data("EXR has no channels"));
+        }
+
+        // zlib expands data at most about a thousandfold, larger images
+        // cannot fit in the file
+        let row_size: usize = channels.iter().map(|(_, t)| t.size() * width).sum();
+        let too_large = || invalid_data("EXR data window is larger than the file");
+        let image_size = row_size.checked_mul(height).ok_or_else(too_large)?;
+        if image_size / 1032 > data.len() {
+            return Err(too_large());
+        }
+
+        let lines = compression.lines_per_block();
+        let block_count = height.div_ceil(lines);
+        let offsets: Vec<usize> = r
+            .take(8 * block_count)?
+            .chunks(8)
+            .map(|b| u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as usize)
+            .collect();
+
+        let mut pixels = vec![Color::zero(); width * height];
+        for offset in offsets {
+            let chunk_header = offset
+                .checked_add(8)
+                .and_then(|end| data.get(offset..end))
+                .ok_or_else(|| invalid_data("EXR block offset out of range"))?;
+            let y0 = usize::try_from(read_i32(chunk_header)?)
+                .ok()
+                .filter(|&y0| y0 < height)
+                .ok_or_else(|| invalid_data("EXR block outside of the data window"))?;
+            let size = read_size(&chunk_header[4..])?;
+            let rows = lines.min(height - y0);
+            let raw_size = rows * row_size;
+            let block = data
+                .get(offset + 8..)
+                .and_then(|rest| rest.get(..size))
+                .ok_or_else(|| invalid_data("EXR file is truncated"))?;
+            let raw = if size < raw_size {
+                zip_decode(block, raw_size)?
+            } else {
+                block.to_vec()
+            };
+
+            let mut offset = 0;
+            for y in y0..y0 + rows {
+                for (name, pixel_type) in &channels {
+                    for x in 0..width {
+                        let bytes = &raw[offset..offset + pixel_type.size()];
+                        offset += pixel_type.size();
+                        let v = match pixel_type {
+                            ExrPixelType::Half => {
+                                f16_to_f32(u16::from_le_bytes([bytes[0], bytes[1]]))
+                            }
+                            ExrPixelType::Float => {
+                                f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+                            }
+                        };
+                        let pixel = &mut pixels[y * width + x];
+                        match name.as_str() {
+                            "R" => pixel.0 = v,
+                            "G" => pixel.1 = v,
+                            "B" => pixel.2 = v,
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+        Ok(Framebuffer::from_pixels(width, height, pixels))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    fn sample_image() -> Framebuffer<Color> {
+        let (width, height) = (23, 37);
+        let mut image = Framebuffer::new(width, height, Color::zero());
+        for y in 0..height {
+            for x in 0..width {
+                let v = x as f32 * 0.125 + y as f32 * 4.0;
+                image.set(
+                    x,
+                    y,
+                    Color(v, 1.0 / (v + 1.0), if x == y { 1000.0 } else { 0.5 }),
+                );
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn half_conversion() {
+        assert_eq!(f32_to_f16(0.0), 0);
+        assert_eq!(f32_to_f16(-0.0), 0x8000);
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        assert_eq!(f32_to_f16(1e6), 0x7c00);
+        assert_eq!(f32_to_f16(f32::INFINITY), 0x7c00);
+        assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+        // smallest subnormal half
+        assert_eq!(f32_to_f16(5.960_464_5e-8), 1);
+        assert_eq!(f32_to_f16(1e-9), 0);
+        // 1 + 2^-11 lies exactly between two halves and rounds to even
+        assert_eq!(f32_to_f16(1.000_488_3), 0x3c00);
+
+        for h in (0..0x7c00).step_by(7) {
+            assert_eq!(f32_to_f16(f16_to_f32(h)), h);
+        }
+    }
+
+    #[test]
+    fn zip_predictor_roundtrip() {
+        let raw: Vec<u8> = (0..1001).map(|i| (i * 7 % 256) as u8).collect();
+        assert_eq!(zip_decode(&zip_encode(&raw), raw.len()).unwrap(), raw);
+    }
+
+    #[test]
+    fn exr_roundtrip() {
+        let image = sample_image();
+        for compression in [ExrCompression::None, ExrCompression::Zip] {
+            let mut exr = Vec::new();
+            image
+                .write_exr(&mut exr, ExrPixelType::Float, compression)
+                .unwrap();
+            assert_eq!(Framebuffer::read_exr(exr.as_slice()).unwrap(), image);
+
+            let mut exr = Vec::new();
+            image
+                .write_exr(&mut exr, ExrPixelType::Half, compression)
+                .unwrap();
+            let decoded = Framebuffer::read_exr(exr.as_slice()).unwrap();
+            for (&a, &b) in image.pixels().iter().zip(decoded.pixels()) {
+                assert!((a.r() - b.r()).abs() <= 1e-3 * a.r().max(1.0));
+                assert!((a.g() - b.g()).abs() <= 1e-3 * a.g().max(1.0));
+                assert!((a.b() - b.b()).abs() <= 1e-3 * a.b().max(1.0));
+            }
+        }
+    }
+
+    #[test]
+    fn exr_zip_is_smaller() {
+        let image = Framebuffer::new(64, 64, Color(0.5, 0.25, 2.0));
+        let mut none = Vec::new();
+        image
+            .write_exr(&mut none, ExrPixelType::Half, ExrCompression::None)
+            .unwrap();
+        let mut zip = Vec::new();
+        image
+            .write_exr(&mut zip, ExrPixelType::Half, ExrCompression::Zip)
+            .unwrap();
+        assert!(zip.len() < none.len() / 4);
+    }
+
+    #[test]
+    fn exr_offsets_point_at_blocks() {
+        let image = sample_image();
+        let mut exr = Vec::new();
+        image
+            .write_exr(&mut exr, ExrPixelType::Half, ExrCompression::Zip)
+            .unwrap();
+
+        let table_start = header(
+            image.width(),
+            image.height(),
+            ExrPixelType::Half,
+            ExrCompression::Zip,
+        )
+        .len();
+        let block_count = image.height().div_ceil(16);
+        for block in 0..block_count {
+            let entry = &exr[table_start + 8 * block..table_start + 8 * (block + 1)];
+            let offset = u64::from_le_bytes(entry.try_into().unwrap()) as usize;
+            let y = i32::from_le_bytes(exr[offset..offset + 4].try_into().unwrap());
+            assert_eq!(y as usize, 16 * block);
+        }
+    }
+
+    // Name, type and value
+    type Attribute<'a> = (&'a str, &'a str, &'a [u8]);
+
+    // A header with the given attributes and no pixel data
+    fn exr_header(attributes: &[Attribute]) -> Vec<u8> {
+        let mut exr = Vec::new();
+        exr.extend_from_slice(&MAGIC.to_le_bytes());
+        exr.extend_from_slice(&VERSION.to_le_bytes());
+        for (name, kind, value) in attributes {
+            write_attribute(&mut exr, name, kind, value);
+        }
+        exr.push(0);
+        exr
+    }
+
+    fn read_error(exr: &[u8]) -> String {
+        match Framebuffer::read_exr(exr) {
+            Ok(_) => panic!("read a malformed EXR"),
+            Err(e) => {
+                assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+                e.to_string()
+            }
+        }
+    }
+
+    #[test]
+    fn exr_rejects_truncated_attributes() {
+        let mut channel = b"R\0".to_vec();
+        channel.extend_from_slice(&1i32.to_le_bytes());
+        let window = box2i(4, 4);
+        let empty = box2i(0, 4);
+        let huge = box2i(1 << 20, 1 << 20);
+        let channels = header_channels();
+        let compression: &[u8] = &[0];
+        let cases: Vec<(Vec<Attribute>, &str)> = vec![
+            (
+                vec![("channels", "chlist", b"R\0\x01")],
+                "EXR channel list is truncated",
+            ),
+            (
+                vec![("channels", "chlist", b"RGB")],
+                "EXR channel list is truncated",
+            ),
+            (
+                vec![("channels", "chlist", &channel)],
+                "EXR channel list is truncated",
+            ),
+            (
+                vec![("compression", "compression", &[])],
+                "EXR compression is truncated",
+            ),
+            (
+                vec![("dataWindow", "box2i", &window[..8])],
+                "EXR data window is truncated",
+            ),
+            (
+                vec![("dataWindow", "box2i", &window[..15])],
+                "EXR data window is truncated",
+            ),
+            (
+                vec![("dataWindow", "box2i", &empty)],
+                "EXR data window is empty",
+            ),
+            (
+                vec![
+                    ("channels", "chlist", &channels),
+                    ("compression", "compression", compression),
+                    ("dataWindow", "box2i", &huge),
+                ],
+                "EXR data window is larger than the file",
+            ),
+        ];
+        for (attributes, expected) in cases {
+            assert_eq!(read_error(&exr_header(&attributes)), expected);
+        }
+
+        // A size that does not fit the file, or is negative
+        let mut exr = exr_header(&[("compression", "compression", compression)]);
+        let size = MAGIC.to_le_bytes().len() + 4 + "compression\0compression\0".len();
+        exr[size..size + 4].copy_from_slice(&(-1i32).to_le_bytes());
+        assert_eq!(read_error(&exr), "negative size in EXR file");
+        exr[size..size + 4].copy_from_slice(&1000i32.to_le_bytes());
+        assert_eq!(read_error(&exr), "EXR file is truncated");
+    }
+
+    // The channel list of a valid image
+    fn header_channels() -> Vec<u8> {
+        let mut channels = Vec::new();
+        for name in ["B", "G", "R"] {
+            channels.extend_from_slice(name.as_bytes());
+            channels.push(0);
+            channels.extend_from_slice(&1i32.to_le_bytes());
+            channels.extend_from_slice(&[0; 12]);
+        }
+        channels.push(0);
+        channels
+    }
+
+    #[test]
+    fn exr_rejects_bad_blocks() {
+        let image = sample_image();
+        let mut exr = Vec::new();
+        image
+            .write_exr(&mut exr, ExrPixelType::Half, ExrCompression::None)
+            .unwrap();
+        let table_start = header(
+            image.width(),
+            image.height(),
+            ExrPixelType::Half,
+            ExrCompression::None,
+        )
+        .len();
+
+        let mut bad = exr.clone();
+        bad[table_start..table_start + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(read_error(&bad), "EXR block offset out of range");
+
+        let offset = u64::from_le_bytes(exr[table_start..table_start + 8].try_into().unwrap());
+        let offset = offset as usize;
+        let mut bad = exr.clone();
+        bad[offset..offset + 4].copy_from_slice(&(-1i32).to_le_bytes());
+        assert_eq!(read_error(&bad), "EXR block outside of the data window");
+        let mut bad = exr.clone();
+        bad[offset + 4..offset + 8].copy_from_slice(&(-1i32).to_le_bytes());
+        assert_eq!(read_error(&bad), "negative size in EXR file");
+        assert_eq!(read_error(&exr[..exr.len() - 1]), "EXR file is truncated");
+    }
+}
@@ -0,0 +1,835 @@
+// glTF 2.0 scenes from .gltf files (with external or embedded base64 buffers)
+// and binary .glb files. Triangle meshes, perspective cameras and the
+// metallic-roughness materials are imported, textures are not.
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::hittable_vec::HittableVec;
+use crate::json::Json;
+use crate::material::{Dielectric, MetallicRoughness, Scatterable};
+use crate::mesh::TriangleMesh;
+use crate::parse::ParseError;
+use crate::point::Point3;
+use crate::vector::{Mat4, Normalize, Vec3};
+use std::convert::TryInto;
+use std::path::Path;
+use std::sync::Arc;
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_JSON_CHUNK: u32 = 0x4e4f_534a;
+const GLB_BIN_CHUNK: u32 = 0x004e_4942;
+// Mode of primitives made of separate triangles
+const TRIANGLES: u64 = 4;
+// Node hierarchies deeper than this are taken to be cyclic
+const MAX_NODE_DEPTH: usize = 256;
+
+// Camera::new parameters of a perspective camera in the scene
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GltfCamera {
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub vup: Vec3,
+    // Vertical field of view in degrees
+    pub vfov: f32,
+    pub aspect_ratio: Option<f32>,
+}
+
+impl GltfCamera {
+    // aspect_ratio is used when the file does not fix one
+    pub fn camera(&self, aspect_ratio: f32) -> Camera {
+        Camera::new(
+            self.look_from,
+            self.look_at,
+            self.vup,
+            self.vfov,
+            self.aspect_ratio.unwrap_or(aspect_ratio),
+            0.0,
+            1.0,
+        )
+    }
+}
+
+pub struct Gltf {
+    // One mesh per primitive, already in world space
+    pub world: HittableVec,
+    pub cameras: Vec<GltfCamera>,
+}
+
+pub fn load_gltf<P: AsRef<Path>>(
+    path: P,
+    default_material: Arc<dyn Scatterable>,
+) -> Result<Gltf, ParseError> {
+    let path = path.as_ref();
+    let data = std::fs::read(path).map_err(|e| ParseError::io(path, e))?;
+    parse_gltf(&data, path, default_material)
+}
+
+// External buffers are looked up next to `path`.
+// Primitives without a material get the default material.
+pub fn parse_gltf(
+    data: &[u8],
+    path: &Path,
+    default_material: Arc<dyn Scatterable>,
+) -> Result<Gltf, ParseError> {
+    let (json, bin) = if data.starts_with(GLB_MAGIC) {
+        split_glb(data, path)?
+    } else {
+        let text = std::str::from_utf8(data)
+            .map_err(|_| ParseError::new(path, 0, "glTF JSON is not UTF-8"))?;
+        (text, None)
+    };
+    let json = Json::parse(json, path)?;
+    let version = json
+        .get("asset")
+        .and_then(|a| a.get("version"))
+        .and_then(Json::as_str)
+        .unwrap_or("");
+    if !version.starts_with("2.") {
+        return Err(ParseError::new(path, 0, "only glTF 2.x is supported"));
+    }
+
+    let buffers = array(&json, "buffers")
+        .iter()
+        .enumerate()
+        .map(|(i, buffer)| load_buffer(buffer, i, bin, path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let materials = array(&json, "materials")
+        .iter()
+        .map(|m| material(m, path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut importer = Importer {
+        json: &json,
+        path,
+        buffers,
+        materials,
+        default_material,
+        gltf: Gltf {
+            world: HittableVec::new(),
+            cameras: Vec::new(),
+        },
+    };
+
+    let roots: Vec<usize> = match array(&json, "scenes")
+        .get(json.get("scene").and_then(Json::as_f64).unwrap_or(0.0) as usize)
+    {
+        Some(scene) => indices(scene.get("nodes")),
+        // Without scenes every node that is nobody's child is a root
+        None => {
+            let nodes = array(&json, "nodes");
+            let children: Vec<usize> = nodes
+                .iter()
+                .flat_map(|n| indices(n.get("children")))
+                .collect();
+            (0..nodes.len()).filter(|i| !children.contains(i)).collect()
+        }
+    };
+    for root in roots {
+        importer.node(root, Mat4::identity(), 0)?;
+    }
+    Ok(importer.gltf)
+}
+
+// Returns the JSON chunk and the binary chunk of a .glb file
+fn split_glb<'a>(data: &'a [u8], path: &Path) -> Result<(&'a str, Option<&'a [u8]>), ParseError> {
+    let invalid = || ParseError::new(path, 0, "invalid GLB file");
+    let u32_at = |pos: usize| -> Result<u32, ParseError> {
+        let bytes = data.get(pos..pos + 4).ok_or_else(invalid)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    };
+    if u32_at(4)? != 2 {
+        return Err(ParseError::new(path, 0, "only GLB version 2 is supported"));
+    }
+    let length = (u32_at(8)? as usize).min(data.len());
+    let mut pos = 12;
+    let (mut json, mut bin) = (None, None);
+    while pos + 8 <= length {
+        let chunk_length = u32_at(pos)? as usize;
+        let chunk_type = u32_at(pos + 4)?;
+        let chunk = data
+            .get(pos + 8..pos + 8 + chunk_length)
+            .ok_or_else(invalid)?;
+        match chunk_type {
+            GLB_JSON_CHUNK if json.is_none() => json = Some(chunk),
+            GLB_BIN_CHUNK if bin.is_none() => bin = Some(chunk),
+            _ => {}
+        }
+        // Chunks are padded to 4 bytes
+        pos += 8 + chunk_length;
+    }
+    let json = json.ok_or_else(|| ParseError::new(path, 0, "GLB file has no JSON chunk"))?;
+    let json = std::str::from_utf8(json).map_err(|_| invalid())?;
+    Ok((json, bin))
+}
+
+fn array<'a>(json: &'a Json, key: &str) -> &'a [Json] {
+    json.get(key).and_then(Json::as_array).unwrap_or(&[])
+}
+
+fn indices(json: Option<&Json>) -> Vec<usize> {
+    json.and_then(Json::as_array)
+        .unwrap_or(&[])
+        .iter()
+        .filter_map(Json::as_f64)
+        .map(|i| i as usize)
+        .collect()
+}
+
+// JSON numbers are doubles, those beyond f32 are rejected
+fn to_f32(value: f64, path: &Path) -> Result<f32, ParseError> {
+    let narrowed = value as f32;
+    if narrowed.is_finite() {
+        Ok(narrowed)
+    } else {
+        Err(ParseError::new(
+            path,
+            0,
+            format!("number {:e} is out of range", value),
+        ))
+    }
+}
+
+fn floats<const N: usize>(
+    json: Option<&Json>,
+    default: [f32; N],
+    path: &Path,
+) -> Result<[f32; N], ParseError> {
+    let mut values = default;
+    if let Some(items) = json.and_then(Json::as_array) {
+        for (value, item) in values.iter_mut().zip(items) {
+            if let Some(item) = item.as_f64() {
+                *value = to_f32(item, path)?;
+            }
+        }
+    }
+    Ok(values)
+}
+
+fn float(json: &Json, key: &str, default: f32, path: &Path) -> Result<f32, ParseError> {
+    match json.get(key).and_then(Json::as_f64) {
+        Some(value) => to_f32(value, path),
+        None => Ok(default),
+    }
+}
+
+fn number(json: &Json, key: &str, default: f64) -> f64 {
+    json.get(key).and_then(Json::as_f64).unwrap_or(default)
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text.bytes().take_while(|&c| c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+fn load_buffer(
+    buffer: &Json,
+    index: usize,
+    bin: Option<&[u8]>,
+    path: &Path,
+) -> Result<Vec<u8>, ParseError> {
+    let error = |msg: String| ParseError::new(path, 0, format!("buffer {}: {}", index, msg));
+    let data = match buffer.get("uri").and_then(Json::as_str) {
+        Some(uri) if uri.starts_with("data:") => {
+            let (header, payload) = uri.split_at(uri.find(',').unwrap_or(uri.len()));
+            if !header.ends_with(";base64") {
+                return Err(error("only base64 data URIs are supported".to_string()));
+            }
+            decode_base64(payload.get(1..).unwrap_or(""))
+                .ok_or_else(|| error("invalid base64 data".to_string()))?
+        }
+        Some(uri) => {
+            let file = path.parent().unwrap_or_else(|| Path::new("")).join(uri);
+            std::fs::read(&file).map_err(|e| error(format!("cannot read {}: {}", uri, e)))?
+        }
+        // The buffer of a .glb file is its binary chunk
+        None if index == 0 && bin.is_some() => bin.unwrap().to_vec(),
+        None => return Err(error("missing uri".to_string())),
+    };
+    let length = number(buffer, "byteLength", 0.0) as usize;
+    if data.len() < length {
+        return Err(error("shorter than its byteLength".to_string()));
+    }
+    Ok(data)
+}
+
+fn material(json: &Json, path: &Path) -> Result<Arc<dyn Scatterable>, ParseError> {
+    let extensions = json.get("extensions");
+    let extension = |name: &str| extensions.and_then(|e| e.get(name));
+    if let Some(transmission) = extension("KHR_materials_transmission") {
+        if number(transmission, "transmissionFactor", 0.0) > 0.0 {
+            let ior = match extension("KHR_materials_ior") {
+                Some(e) => float(e, "ior", 1.5, path)?,
+                None => 1.5,
+            };
+            return Ok(Arc::new(Dielectric::new(ior)));
+        }
+    }
+    let pbr = json.get("pbrMetallicRoughness");
+    let [r, g, b, _] = floats(pbr.and_then(|p| p.get("baseColorFactor")), [1.0; 4], path)?;
+    let (metallic, roughness) = match pbr {
+        Some(p) => (
+            float(p, "metallicFactor", 1.0, path)?,
+            float(p, "roughnessFactor", 1.0, path)?,
+        ),
+        None => (1.0, 1.0),
+    };
+    let [er, eg, eb] = floats(json.get("emissiveFactor"), [0.0; 3], path)?;
+    let strength = match extension("KHR_materials_emissive_strength") {
+        Some(e) => float(e, "emissiveStrength", 1.0, path)?,
+        None => 1.0,
+    };
+    Ok(Arc::new(MetallicRoughness::new(
+        Color(r, g, b),
+        metallic,
+        roughness,
+        strength * Color(er, eg, eb),
+    )))
+}
+
+struct Importer<'a> {
+    json: &'a Json,
+    path: &'a Path,
+    buffers: Vec<Vec<u8>>,
+    materials: Vec<Arc<dyn Scatterable>>,
+    default_material: Arc<dyn Scatterable>,
+    gltf: Gltf,
+}
+
+impl<'a> Importer<'a> {
+    fn error<S: Into<String>>(&self, msg: S) -> ParseError {
+        ParseError::new(self.path, 0, msg)
+    }
+
+    fn node(&mut self, index: usize, parent: Mat4, depth: usize) -> Result<(), ParseError> {
+        if depth > MAX_NODE_DEPTH {
+            return Err(self.error("node hierarchy is too deep or cyclic"));
+        }
+        let node = array(self.json, "nodes")
+            .get(index)
+            .ok_or_else(|| self.error(format!("node {} does not exist", index)))?;
+        let local = match node.get("matrix") {
+            Some(matrix) => Mat4::from_column_major(&floats(Some(matrix), [0.0; 16], self.path)?),
+            None => {
+                let t = floats(node.get("translation"), [0.0; 3], self.path)?;
+                let [x, y, z, w] = floats(node.get("rotation"), [0.0, 0.0, 0.0, 1.0], self.path)?;
+                let s = floats(node.get("scale"), [1.0; 3], self.path)?;
+                Mat4::translation(Vec3(t[0], t[1], t[2]))
+                    * Mat4::from_quaternion(x, y, z, w)
+                    * Mat4::scaling(Vec3(s[0], s[1], s[2]))
+            }
+        };
+        let world = parent * local;
+
+        if let Some(mesh) = node.get("mesh").and_then(Json::as_f64) {
+            self.mesh(mesh as usize, &world)?;
+        }
+        if let Some(camera) = node.get("camera").and_then(Json::as_f64) {
+            self.camera(camera as usize, &world)?;
+        }
+        for child in indices(node.get("children")) {
+            self.node(child, world, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    fn camera(&mut self, index: usize, world: &Mat4) -> Result<(), ParseError> {
+        let camera = array(self.json, "cameras")
+            .get(index)
+            .ok_or_else(|| self.error(format!("camera {} does not exist", index)))?;
+        // Orthographic cameras have no equivalent
+        let perspective = match camera.get("perspective") {
+            Some(perspective) => perspective,
+            None => return Ok(()),
+        };
+        // glTF cameras look down their local -z with +y up
+        let look_from = world.transform_point(Point3::zero());
+        let forward = world.transform_vector(Vec3(0.0, 0.0, -1.0)).normalize();
+        self.gltf.cameras.push(GltfCamera {
+            look_from,
+            look_at: look_from + forward,
+            vup: world.transform_vector(Vec3(0.0, 1.0, 0.0)).normalize(),
+            vfov: float(perspective, "yfov", 0.8, self.path)?.to_degrees(),
+            aspect_ratio: perspective
+                .get("aspectRatio")
+                .and_then(Json::as_f64)
+                .map(|a| to_f32(a, self.path))
+                .transpose()?,
+        });
+        Ok(())
+    }
+
+    fn mesh(&mut self, index: usize, world: &Mat4) -> Result<(), ParseError> {
+        let mesh = array(self.json, "meshes")
+            .get(index)
+            .ok_or_else(|| self.error(format!("mesh {} does not exist", index)))?;
+        // Normals go through the inverse transpose, and mirroring flips the winding
+        let normal_matrix = world.inverse().map(|m| m.transpose());
+        let mirrored = world.linear_determinant() < 0.0;
+
+        for primitive in array(mesh, "primitives") {
+            if number(primitive, "mode", TRIANGLES as f64) as u64 != TRIANGLES {
+                continue;
+            }
+            let attributes = primitive.get("attributes");
+            let attribute = |name: &str| {
+                attributes
+                    .and_then(|a| a.get(name))
+                    .and_then(Json::as_f64)
+                    .map(|i| i as usize)
+            };
+            let position = attribute("POSITION")
+                .ok_or_else(|| self.error(format!("mesh {} has no positions", index)))?;
+            let positions: Vec<Point3> = self
+                .accessor(position, 3)?
+                .iter()
+                .map(|p| world.transform_point(Point3(p[0], p[1], p[2])))
+                .collect();
+            // Finite positions and transforms can still overflow together
+            if positions
+                .iter()
+                .any(|p| !(p.x().is_finite() && p.y().is_finite() && p.z().is_finite()))
+            {
+                return Err(self.error(format!("mesh {} is out of range", index)));
+            }
+
+            let mut indices: Vec<[u32; 3]> = match primitive.get("indices").and_then(Json::as_f64) {
+                Some(accessor) => self
+                    .accessor(accessor as usize, 1)?
+                    .chunks_exact(3)
+                    .map(|t| [t[0][0] as u32, t[1][0] as u32, t[2][0] as u32])
+                    .collect(),
+                None => (0..positions.len() as u32 / 3)
+                    .map(|i| [3 * i, 3 * i + 1, 3 * i + 2])
+                    .collect(),
+            };
+            if indices
+                .iter()
+                .flatten()
+                .any(|&i| i as usize >= positions.len())
+            {
+                return Err(self.error(format!("mesh {} has an index out of range", index)));
+            }
+            if indices.is_empty() {
+                continue;
+            }
+            if mirrored {
+                for triangle in &mut indices {
+                    triangle.swap(1, 2);
+                }
+            }
+
+            let material = match primitive.get("material").and_then(Json::as_f64) {
+                Some(m) => self
+                    .materials
+                    .get(m as usize)
+                    .cloned()
+                    .ok_or_else(|| self.error(format!("material {} does not exist", m)))?,
+                None => self.default_material.clone(),
+            };
+            let count = positions.len();
+            let mut triangles = TriangleMesh::new(positions, indices, material);
+            if let (Some(normal), Some(normal_matrix)) = (attribute("NORMAL"), normal_matrix) {
+                let normals: Vec<Vec3> = self
+                    .accessor(normal, 3)?
+                    .iter()
+                    .map(|n| normal_matrix.transform_vector(Vec3(n[0], n[1], n[2])))
+                    .collect();
+                if normals.len() == count {
+                    triangles = triangles.with_normals(normals);
+                }
+            }
+            if let Some(uv) = attribute("TEXCOORD_0") {
+                let uvs: Vec<(f32, f32)> =
+                    self.accessor(uv, 2)?.iter().map(|t| (t[0], t[1])).collect();
+                if uvs.len() == count {
+                    triangles = triangles.with_uvs(uvs);
+                }
+            }
+            if let Some(color) = attribute("COLOR_0") {
+                // glTF vertex colors are linear, the alpha channel is ignored
+                let colors: Vec<Color> = self
+                    .accessor(color, 3)?
+                    .iter()
+                    .map(|c| Color(c[0], c[1], c[2]))
+                    .collect();
+                if colors.len() == count {
+                    triangles = triangles.with_colors(colors);
+                }
+            }
+            self.gltf.world.push(Box::new(triangles));
+        }
+        Ok(())
+    }
+
+    // Reads the first `components` components of every element of an accessor,
+    // normalized integers are mapped to [0, 1] or [-1, 1]
+    fn accessor(&self, index: usize, components: usize) -> Result<Vec<[f32; 4]>, ParseError> {
+        let error = |msg: &str| self.error(format!("accessor {}: {}", index, msg));
+        let accessor = array(self.json, "accessors")
+            .get(index)
+            .ok_or_else(|| error("does not exist"))?;
+        if accessor.get("sparse").is_some() {
+            return Err(error("sparse accessors are not supported"));
+        }
+        let count = number(accessor, "count", 0.0) as usize;
+        if count == 0 {
+            return Err(error("count must be positive"));
+        }
+        let element_components = match accessor.get("type").and_then(Json::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            _ => return Err(error("unsupported type")),
+        };
+        if element_components < components {
+            return Err(error("too few components"));
+        }
+        let component_type = number(accessor, "componentType", 0.0) as u32;
+        let size = match component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            _ => return Err(error("unsupported component type")),
+        };
+        let normalized = accessor
+            .get("normalized")
+            .and_then(Json::as_bool)
+            .unwrap_or(false);
+
+        let view_index = match accessor.get("bufferView").and_then(Json::as_f64) {
+            Some(view) => view as usize,
+            // Accessors without a buffer view are all zeros
+            None => return Ok(vec![[0.0; 4]; count]),
+        };
+        let view = array(self.json, "bufferViews")
+            .get(view_index)
+            .ok_or_else(|| error("buffer view does not exist"))?;
+        let buffer = self
+            .buffers
+            .get(number(view, "buffer", 0.0) as usize)
+            .ok_or_else(|| error("buffer does not exist"))?;
+        let view_start = number(view, "byteOffset", 0.0) as usize;
+        let view_length = number(view, "byteLength", 0.0) as usize;
+        let element_size = size * element_components;
+        let stride = match number(view, "byteStride", 0.0) as usize {
+            0 => element_size,
+            stride => stride,
+        };
+        let view = view_start
+            .checked_add(view_length)
+            .and_then(|view_end| buffer.get(view_start..view_end))
+            .ok_or_else(|| error("buffer view is out of range"))?;
+        let start = number(accessor, "byteOffset", 0.0) as usize;
+        let end = (count - 1)
+            .checked_mul(stride)
+            .and_then(|offset| offset.checked_add(start))
+            .and_then(|offset| offset.checked_add(element_size));
+        if end.filter(|&end| end <= view.len()).is_none() {
+            return Err(error("data is out of range"));
+        }
+
+        let mut elements = Vec::with_capacity(count);
+        for i in 0..count {
+            let mut element = [0.0; 4];
+            for (c, value) in element.iter_mut().enumerate().take(components) {
+                let pos = start + i * stride + c * size;
+                let bytes = &view[pos..pos + size];
+                *value = match component_type {
+                    5120 => {
+                        let v = bytes[0] as i8 as f32;
+                        if normalized {
+                            (v / 127.0).max(-1.0)
+                        } else {
+                            v
+                        }
+                    }
+                    5121 => {
+                        let v = bytes[0] as f32;
+                        if normalized {
+                            v / 255.0
+                        } else {
+                            v
+                        }
+                    }
+                    5122 => {
+                        let v = i16::from_le_bytes([bytes[0], bytes[1]]) as f32;
+                        if normalized {
+                            (v / 32767.0).max(-1.0)
+                        } else {
+                            v
+                        }
+                    }
+                    5123 => {
+                        let v = u16::from_le_bytes([bytes[0], bytes[1]]) as f32;
+                        if normalized {
+                            v / 65535.0
+                        } else {
+                            v
+                        }
+                    }
+                    5125 => u32::from_le_bytes(bytes.try_into().unwrap()) as f32,
+                    _ => {
+                        let v = f32::from_le_bytes(bytes.try_into().unwrap());
+                        if !v.is_finite() {
+                            return Err(error("non-finite float"));
+                        }
+                        v
+                    }
+                };
+            }
+            elements.push(element);
+        }
+        Ok(elements)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::material::Lambertian;
+    use crate::ray::Ray;
+
+    fn encode_base64(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut text = String::new();
+        for chunk in bytes.chunks(3) {
+            let bits = chunk
+                .iter()
+                .enumerate()
+                .fold(0u32, |acc, (i, &b)| acc | (b as u32) << (16 - 8 * i));
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    text.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char);
+                } else {
+                    text.push('=');
+                }
+            }
+        }
+        text
+    }
+
+    // A unit right triangle in the z = 0 plane followed by its u16 indices
+    fn triangle_buffer() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for v in &[0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        for i in &[0u16, 1, 2] {
+            bytes.extend_from_slice(&i.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn triangle_json(uri: Option<&str>, extra: &str) -> String {
+        let uri = uri.map_or(String::new(), |u| format!(r#""uri": "{}","#, u));
+        format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "scene": 0,
+                "scenes": [{{"nodes": [0, 1]}}],
+                "nodes": [
+                    {{"mesh": 0, "translation": [0, 0, -2]}},
+                    {{"camera": 0, "translation": [0, 0, 3]}}
+                ],
+                "cameras": [{{"type": "perspective",
+                    "perspective": {{"yfov": 0.5, "znear": 0.1}}}}],
+                "meshes": [{{"primitives": [{{
+                    "attributes": {{"POSITION": 0}}, "indices": 1 {}
+                }}]}}],
+                "buffers": [{{{} "byteLength": 42}}],
+                "bufferViews": [
+                    {{"buffer": 0, "byteOffset": 0, "byteLength": 36}},
+                    {{"buffer": 0, "byteOffset": 36, "byteLength": 6}}
+                ],
+                "accessors": [
+                    {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}},
+                    {{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}}
+                ]
+            }}"#,
+            extra, uri
+        )
+    }
+
+    fn parse(data: &[u8], path: &Path) -> Result<Gltf, ParseError> {
+        parse_gltf(data, path, Arc::new(Lambertian::new(Color(0.5, 0.5, 0.5))))
+    }
+
+    fn hit_distance(gltf: &Gltf, origin: Point3) -> Option<f32> {
+        let r = Ray::new(origin, Vec3(0.0, 0.0, -1.0));
+        gltf.world
+            .hit(&r, 0.001, f32::INFINITY)
+            .map(|(hit, _)| hit.t())
+    }
+
+    #[test]
+    fn gltf_with_embedded_buffer() {
+        let uri = format!(
+            "data:application/octet-stream;base64,{}",
+            encode_base64(&triangle_buffer())
+        );
+        let json = triangle_json(Some(&uri), "");
+        let gltf = parse(json.as_bytes(), Path::new("scene.gltf")).unwrap();
+        assert_eq!(gltf.world.len(), 1);
+        // The node translation moves the triangle to z = -2
+        let t = hit_distance(&gltf, Point3(0.25, 0.25, 0.0)).unwrap();
+        assert!((t - 2.0).abs() < 1e-5);
+        assert_eq!(hit_distance(&gltf, Point3(0.75, 0.75, 0.0)), None);
+
+        assert_eq!(gltf.cameras.len(), 1);
+        let camera = gltf.cameras[0];
+        assert_eq!(camera.look_from, Point3(0.0, 0.0, 3.0));
+        assert_eq!(camera.look_at, Point3(0.0, 0.0, 2.0));
+        assert_eq!(camera.vup, Vec3(0.0, 1.0, 0.0));
+        assert!((camera.vfov - 0.5f32.to_degrees()).abs() < 1e-4);
+        assert_eq!(camera.aspect_ratio, None);
+    }
+
+    #[test]
+    fn glb_with_binary_chunk() {
+        let mut json = triangle_json(None, "").into_bytes();
+        while json.len() % 4 != 0 {
+            json.push(b' ');
+        }
+        let mut bin = triangle_buffer();
+        while bin.len() % 4 != 0 {
+            bin.push(0);
+        }
+        let mut glb = Vec::new();
+        glb.extend_from_slice(b"glTF");
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        glb.extend_from_slice(&((28 + json.len() + bin.len()) as u32).to_le_bytes());
+        glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        glb.extend_from_slice(&GLB_JSON_CHUNK.to_le_bytes());
+        glb.extend_from_slice(&json);
+        glb.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        glb.extend_from_slice(&GLB_BIN_CHUNK.to_le_bytes());
+        glb.extend_from_slice(&bin);
+
+        let gltf = parse(&glb, Path::new("scene.glb")).unwrap();
+        let t = hit_distance(&gltf, Point3(0.25, 0.25, 0.0)).unwrap();
+        assert!((t - 2.0).abs() < 1e-5);
+        assert_eq!(gltf.cameras.len(), 1);
+    }
+
+    #[test]
+    fn gltf_with_external_buffer_and_materials() {
+        let dir = std::env::temp_dir().join("raytracer_gltf_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("triangle.bin"), triangle_buffer()).unwrap();
+        let json = triangle_json(Some("triangle.bin"), r#", "material": 0"#).replace(
+            r#""meshes""#,
+            r#""materials": [{"pbrMetallicRoughness": {"baseColorFactor": [1, 0, 0, 1],
+                "metallicFactor": 0, "roughnessFactor": 1},
+                "emissiveFactor": [0.5, 0.5, 0.5],
+                "extensions": {"KHR_materials_emissive_strength": {"emissiveStrength": 4}}}],
+            "meshes""#,
+        );
+        let path = dir.join("scene.gltf");
+        std::fs::write(&path, json).unwrap();
+        let gltf = load_gltf(&path, Arc::new(Lambertian::new(Color(0.5, 0.5, 0.5)))).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let r = Ray::new(Point3(0.25, 0.25, 0.0), Vec3(0.0, 0.0, -1.0));
+        let (hit, material) = gltf.world.hit(&r, 0.001, f32::INFINITY).unwrap();
+        assert_eq!(material.emitted(&hit), Color(2.0, 2.0, 2.0));
+    }
+
+    #[test]
+    fn gltf_errors() {
+        let path = Path::new("scene.gltf");
+        let error = |json: &str| parse(json.as_bytes(), path).err().unwrap().to_string();
+        assert_eq!(
+            error(r#"{"asset": {"version": "1.0"}}"#),
+            "scene.gltf: only glTF 2.x is supported"
+        );
+        assert_eq!(
+            error(&triangle_json(None, "")),
+            "scene.gltf: buffer 0: missing uri"
+        );
+        let uri = format!("data:;base64,{}", encode_base64(&triangle_buffer()[..30]));
+        assert_eq!(
+            error(&triangle_json(Some(&uri), "")),
+            "scene.gltf: buffer 0: shorter than its byteLength"
+        );
+        let uri = format!("data:;base64,{}", encode_base64(&triangle_buffer()));
+        assert_eq!(
+            error(&triangle_json(Some(&uri), "").replace(
+                "\"count\": 3, \"type\": \"VEC3\"",
+                "\"count\": 4, \"type\": \"VEC3\""
+            )),
+            "scene.gltf: accessor 0: data is out of range"
+        );
+        assert_eq!(
+            error(&triangle_json(Some(&uri), "").replace(
+                "\"count\": 3, \"type\": \"VEC3\"",
+                "\"count\": 0, \"type\": \"VEC3\""
+            )),
+            "scene.gltf: accessor 0: count must be positive"
+        );
+        assert_eq!(
+            error(&triangle_json(Some(&uri), "").replace(
+                "\"count\": 3, \"type\": \"VEC3\"",
+                "\"count\": 1e19, \"type\": \"VEC3\""
+            )),
+            "scene.gltf: accessor 0: data is out of range"
+        );
+        assert_eq!(
+            error(&triangle_json(Some(&uri), "").replace(
+                "\"byteOffset\": 36, \"byteLength\": 6",
+                "\"byteOffset\": 36, \"byteLength\": 1e20"
+            )),
+            "scene.gltf: accessor 1: buffer view is out of range"
+        );
+        let mut nan = triangle_buffer();
+        nan[4..8].copy_from_slice(&f32::NAN.to_le_bytes());
+        let nan_uri = format!("data:;base64,{}", encode_base64(&nan));
+        assert_eq!(
+            error(&triangle_json(Some(&nan_uri), "")),
+            "scene.gltf: accessor 0: non-finite float"
+        );
+        assert_eq!(
+            error(&triangle_json(Some(&uri), "").replace(
+                "\"translation\": [0, 0, -2]",
+                "\"translation\": [1e300, 0, -2]"
+            )),
+            "scene.gltf: number 1e300 is out of range"
+        );
+        assert_eq!(
+            error(&triangle_json(Some(&uri), "").replace(
+                "\"translation\": [0, 0, -2]",
+                "\"translation\": [3e38, 0, -2], \"scale\": [1e38, 1, 1]"
+            )),
+            "scene.gltf: mesh 0 is out of range"
+        );
+        assert_eq!(
+            error(&triangle_json(Some(&uri), "").replace("\"yfov\": 0.5", "\"yfov\": -1e39")),
+            "scene.gltf: number -1e39 is out of range"
+        );
+        assert_eq!(
+            error(&triangle_json(Some(&uri), r#", "material": 3"#)),
+            "scene.gltf: material 3 does not exist"
+        );
+        assert_eq!(
+            error("{\"asset\": {\"version\": \"2.0\"},\n\"nodes\": [}"),
+            "scene.gltf:2: unexpected character"
+        );
+        assert!(parse(b"glTF\x02\0\0\0\x0c\0\0\0", path).is_err());
+    }
+}
@@ -0,0 +1,293 @@
+// Radiance RGBE (.hdr) images: three 8-bit mantissas sharing one exponent byte
+use crate::color::Color;
+use crate::image::Framebuffer;
+use std::io::{self, BufRead, Write};
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Scanlines of this width are run-length encoded per component
+const RLE_MIN_WIDTH: usize = 8;
+const RLE_MAX_WIDTH: usize = 0x7fff;
+const MIN_RUN: usize = 4;
+
+pub fn to_rgbe(c: &Color) -> [u8; 4] {
+    let (r, g, b) = (c.r().max(0.0), c.g().max(0.0), c.b().max(0.0));
+    let v = r.max(g).max(b);
+    // f32::max already replaced NaN with 0
+    if v < 1e-32 || v.is_infinite() {
+        return [0; 4];
+    }
+    // v = m * 2^e with m in [0.5, 1)
+    let e = ((v.to_bits() >> 23) & 0xff) as i32 - 126;
+    let scale = f32::powi(2.0, 8 - e);
+    [
+        (r * scale) as u8,
+        (g * scale) as u8,
+        (b * scale) as u8,
+        (e + 128) as u8,
+    ]
+}
+
+pub fn from_rgbe(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::zero();
+    }
+    let f = f32::powi(2.0, rgbe[3] as i32 - (128 + 8));
+    Color(
+        (rgbe[0] as f32 + 0.5) * f,
+        (rgbe[1] as f32 + 0.5) * f,
+        (rgbe[2] as f32 + 0.5) * f,
+    )
+}
+
+// Run-length encodes one component of a scanline
+fn write_rle<W: Write>(w: &mut W, data: &[u8]) -> io::Result<()> {
+    let mut pos = 0;
+    while pos < data.len() {
+        // Find the start of the next run that is long enough to pay off
+        let mut run_start = pos;
+        let mut run_len = 0;
+        while run_start < data.len() {
+            run_len = data[run_start..]
+                .iter()
+                .take(127)
+                .take_while(|&&v| v == data[run_start])
+                .count();
+            if run_len >= MIN_RUN {
+                break;
+            }
+            run_start += run_len;
+        }
+        if run_len < MIN_RUN {
+            run_start = data.len();
+        }
+
+        // Literal bytes before the run
+        for chunk in data[pos..run_start].chunks(128) {
+            w.write_all(&[chunk.len() as u8])?;
+            w.write_all(chunk)?;
+        }
+        if run_start < data.len() {
+            w.write_all(&[128 + run_len as u8, data[run_start]])?;
+        }
+        pos = run_start + run_len;
+    }
+    Ok(())
+}
+
+impl Framebuffer<Color> {
+    pub fn write_hdr<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write!(
+            w,
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+            self.height(),
+            self.width()
+        )?;
+        let rle = (RLE_MIN_WIDTH..=RLE_MAX_WIDTH).contains(&self.width());
+        for row in self.pixels().chunks(self.width().max(1)) {
+            let rgbe: Vec<[u8; 4]> = row.iter().map(to_rgbe).collect();
+            if !rle {
+                w.write_all(&rgbe.concat())?;
+                continue;
+            }
+            let width = self.width() as u16;
+            w.write_all(&[2, 2, (width >> 8) as u8, width as u8])?;
+            for component in 0..4 {
+                let data: Vec<u8> = rgbe.iter().map(|p| p[component]).collect();
+                write_rle(w, &data)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Reads flat and run-length encoded RGBE images stored top to bottom
+    pub fn read_hdr<R: BufRead>(mut r: R) -> io::Result<Self> {
+        let mut line = String::new();
+        r.read_line(&mut line)?;
+        if !line.starts_with("#?") {
+            return Err(invalid_data("not a Radiance HDR image"));
+        }
+        loop {
+            line.clear();
+            if r.read_line(&mut line)? == 0 {
+                return Err(invalid_data("unexpected end of HDR header"));
+            }
+            let line = line.trim();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(format) = line.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    return Err(invalid_data("only RGBE HDR images are supported"));
+                }
+            }
+        }
+
+        line.clear();
+        r.read_line(&mut line)?;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (height, width) = match tokens.as_slice() {
+            ["-Y", h, "+X", w] => (h.parse(), w.parse()),
+            _ => return Err(invalid_data("unsupported HDR orientation")),
+        };
+        let (height, width): (usize, usize) = (
+            height.map_err(|_| invalid_data("invalid HDR height"))?,
+            width.map_err(|_| invalid_data("invalid HDR width"))?,
+        );
+
+        let mut data = Vec::new();
+        r.read_to_end(&mut data)?;
+        // A two byte run covers at most 127 pixels of one of the 4 components,
+        // larger images cannot fit in the data
+        let too_large = || invalid_data("HDR image is larger than the file");
+        let size = width.checked_mul(height).ok_or_else(too_large)?;
+        if size.max(width) / 16 > data.len() {
+            return Err(too_large());
+        }
+        let mut pos = 0usize;
+        let mut take = |n: usize| -> io::Result<&[u8]> {
+            let bytes = pos
+                .checked_add(n)
+                .and_then(|end| data.get(pos..end))
+                .ok_or_else(|| invalid_data("HDR image data is truncated"))?;
+            pos += n;
+            Ok(bytes)
+        };
+
+        let mut pixels = Vec::with_capacity(size);
+        let mut row = vec![[0u8; 4]; width];
+        for _ in 0..height {
+            let start = take(4.min(width * 4))?.to_vec();
+            let is_rle = (RLE_MIN_WIDTH..=RLE_MAX_WIDTH).contains(&width)
+                && start[0] == 2
+                && start[1] == 2
+                && start[2] & 0x80 == 0;
+            if is_rle {
+                if ((start[2] as usize) << 8 | start[3] as usize) != width {
+                    return Err(invalid_data("HDR scanline width mismatch"));
+                }
+                for component in 0..4 {
+                    let mut x = 0;
+                    while x < width {
+                        let count = take(1)?[0] as usize;
+                        let (len, run) = if count > 128 {
+                            (count - 128, true)
+                        } else {
+                            (count, false)
+                        };
+                        if len == 0 || x + len > width {
+                            return Err(invalid_data("invalid HDR run length"));
+                        }
+                        if run {
+                            let value = take(1)?[0];
+                            for pixel in &mut row[x..x + len] {
+                                pixel[component] = value;
+                            }
+                        } else {
+                            for (pixel, &value) in row[x..x + len].iter_mut().zip(take(len)?) {
+                                pixel[component] = value;
+                            }
+                        }
+                        x += len;
+                    }
+                }
+            } else {
+                let rest = take(width * 4 - start.len())?;
+                let flat: Vec<u8> = start.iter().chain(rest).copied().collect();
+                for (pixel, bytes) in row.iter_mut().zip(flat.chunks(4)) {
+                    pixel.copy_from_slice(bytes);
+                }
+            }
+            pixels.extend(row.iter().map(|&p| from_rgbe(p)));
+        }
+        Ok(Framebuffer::from_pixels(width, height, pixels))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Color, b: Color) {
+        let tolerance = 0.01 * a.r().max(a.g()).max(a.b());
+        assert!(
+            (a.r() - b.r()).abs() <= tolerance
+                && (a.g() - b.g()).abs() <= tolerance
+                && (a.b() - b.b()).abs() <= tolerance,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    fn sample_image(width: usize, height: usize) -> Framebuffer<Color> {
+        let mut image = Framebuffer::new(width, height, Color::zero());
+        for y in 0..height {
+            for x in 0..width {
+                // Bright and dark values mixed with constant areas that compress into runs
+                let v = if x < width / 2 {
+                    1.0
+                } else {
+                    (x * y) as f32 * 7.5 + 0.01
+                };
+                image.set(x, y, Color(v, 0.25 * v, 1000.0 * (y as f32 + 1.0)));
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn rgbe_roundtrip() {
+        for c in [
+            Color(1.0, 0.5, 0.25),
+            Color(1000.0, 1.0, 0.0),
+            Color(0.001, 0.002, 0.003),
+        ] {
+            assert_close(c, from_rgbe(to_rgbe(&c)));
+        }
+        assert_eq!(to_rgbe(&Color(-1.0, 0.0, f32::NAN)), [0; 4]);
+        assert_eq!(from_rgbe([0; 4]), Color::zero());
+    }
+
+    #[test]
+    fn hdr_roundtrip() {
+        // 5 is too narrow for run-length encoding, 300 is encoded
+        for width in [5, 300] {
+            let image = sample_image(width, 7);
+            let mut hdr = Vec::new();
+            image.write_hdr(&mut hdr).unwrap();
+            let decoded = Framebuffer::read_hdr(hdr.as_slice()).unwrap();
+            assert_eq!(decoded.width(), width);
+            assert_eq!(decoded.height(), 7);
+            for (&a, &b) in image.pixels().iter().zip(decoded.pixels()) {
+                assert_close(a, b);
+            }
+            if width == 300 {
+                assert!(hdr.len() < width * 7 * 4);
+            }
+        }
+    }
+
+    #[test]
+    fn hdr_rejects_truncated_data() {
+        let mut hdr = Vec::new();
+        sample_image(64, 4).write_hdr(&mut hdr).unwrap();
+        hdr.truncate(hdr.len() - 10);
+        assert!(Framebuffer::read_hdr(hdr.as_slice()).is_err());
+    }
+
+    #[test]
+    fn hdr_rejects_huge_size() {
+        for size in ["4294967296 +X 4294967296", "1 +X 1000", "0 +X 4294967296"] {
+            let hdr = format!("#?RADIANCE\n\n-Y {}\n{}", size, "\0".repeat(40));
+            assert_eq!(
+                Framebuffer::read_hdr(hdr.as_bytes())
+                    .unwrap_err()
+                    .to_string(),
+                "HDR image is larger than the file"
+            );
+        }
+    }
+}
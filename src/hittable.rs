@@ -1,13 +1,23 @@
+use crate::aabb::Aabb;
+use crate::color::Color;
 use crate::material::Scatterable;
 use crate::point::Point3;
 use crate::ray::Ray;
 use crate::vector::{Dot, Normalize, Vec3};
 
+#[derive(Clone)]
 pub struct Hit {
     p: Point3,
     n: Vec3,
     t: f32,
     front_face: bool,
+    // Surface parametrization, e.g. texture coordinates
+    uv: (f32, f32),
+    // Weights of the second and third triangle vertex, the first one
+    // gets the rest. Zero for surfaces that are not triangles.
+    barycentric: (f32, f32),
+    // Interpolated vertex color of meshes that have one
+    color: Option<Color>,
 }
 
 impl Hit {
@@ -29,9 +39,37 @@ impl Hit {
             n,
             t,
             front_face,
+            uv: (0.0, 0.0),
+            barycentric: (0.0, 0.0),
+            color: None,
         }
     }
 
+    pub fn with_uv(mut self, u: f32, v: f32) -> Self {
+        self.uv = (u, v);
+        self
+    }
+
+    pub fn with_barycentric(mut self, b1: f32, b2: f32) -> Self {
+        self.barycentric = (b1, b2);
+        self
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    // Shading normal on the side of the ray, e.g. tilted by a bump map
+    pub fn with_normal(mut self, n: Vec3) -> Self {
+        assert!(
+            Vec3::almost_eq(n.normalize(), n, 1e-5),
+            "n must be a unit vector"
+        );
+        self.n = n;
+        self
+    }
+
     pub fn p(&self) -> Point3 {
         self.p
     }
@@ -47,8 +85,21 @@ impl Hit {
     pub fn front_face(&self) -> bool {
         self.front_face
     }
+
+    pub fn uv(&self) -> (f32, f32) {
+        self.uv
+    }
+
+    pub fn barycentric(&self) -> (f32, f32) {
+        self.barycentric
+    }
+
+    pub fn color(&self) -> Option<Color> {
+        self.color
+    }
 }
 
-pub trait Hittable {
+pub trait Hittable: Send + Sync {
     fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(Hit, &dyn Scatterable)>;
+    fn bounding_box(&self) -> Aabb;
 }
@@ -1,28 +1,70 @@
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
 use crate::hittable::{Hit, Hittable};
 use crate::material::Scatterable;
 use crate::ray::Ray;
-use std::ops::{Deref, DerefMut};
+use std::sync::OnceLock;
 
+// A list of objects intersected through a BVH.
+// The tree is built on the first hit after the list was changed.
 #[derive(Default)]
 pub struct HittableVec {
     inner: Vec<Box<dyn Hittable>>,
+    bvh: OnceLock<Bvh>,
 }
 
-impl Deref for HittableVec {
-    type Target = Vec<Box<dyn Hittable>>;
-    fn deref(&self) -> &Self::Target {
-        self.inner.as_ref()
+impl Hittable for HittableVec {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(Hit, &dyn Scatterable)> {
+        let bvh = self.bvh.get_or_init(|| {
+            let bounds: Vec<Aabb> = self.inner.iter().map(|h| h.bounding_box()).collect();
+            Bvh::build(&bounds)
+        });
+
+        let mut hit_closest: Option<(Hit, &dyn Scatterable)> = None;
+        bvh.traverse(r, t_min, t_max, |i, t_closest| {
+            let (hit, mat) = self.inner[i].hit(r, t_min, t_closest)?;
+            let t = hit.t();
+            hit_closest = Some((hit, mat));
+            Some(t)
+        });
+        hit_closest
     }
-}
 
-impl DerefMut for HittableVec {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        self.inner.as_mut()
+    fn bounding_box(&self) -> Aabb {
+        self.inner
+            .iter()
+            .map(|h| h.bounding_box())
+            .reduce(Aabb::surrounding)
+            .expect("bounding box of an empty HittableVec")
     }
 }
 
-impl Hittable for HittableVec {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(Hit, &dyn Scatterable)> {
+impl HittableVec {
+    pub fn new() -> HittableVec {
+        HittableVec::default()
+    }
+
+    pub fn push(&mut self, h: Box<dyn Hittable>) {
+        self.inner.push(h);
+        self.bvh = OnceLock::new();
+    }
+
+    // Moves every object of `other` into this list
+    pub fn append(&mut self, other: HittableVec) {
+        self.inner.extend(other.inner);
+        self.bvh = OnceLock::new();
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    // Tests every object, kept as a reference for the BVH
+    pub fn hit_linear(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(Hit, &dyn Scatterable)> {
         let mut t_closest = t_max;
         let mut hit_closest: Option<(Hit, &dyn Scatterable)> = None;
         for h in self.inner.iter() {
@@ -34,9 +76,3 @@ impl Hittable for HittableVec {
         hit_closest
     }
 }
-
-impl HittableVec {
-    pub fn new() -> HittableVec {
-        HittableVec { inner: Vec::new() }
-    }
-}
@@ -0,0 +1,638 @@
+use crate::color::Color;
+use crate::exr::{ExrCompression, ExrPixelType};
+use crate::zlib;
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+pub type Rgb8 = [u8; 3];
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Image stored row by row, starting from the top row
+#[derive(Clone, Debug, PartialEq)]
+pub struct Framebuffer<T> {
+    width: usize,
+    height: usize,
+    pixels: Vec<T>,
+}
+
+impl<T: Clone> Framebuffer<T> {
+    pub fn new(width: usize, height: usize, fill: T) -> Self {
+        Self::from_pixels(width, height, vec![fill; width * height])
+    }
+}
+
+impl<T> Framebuffer<T> {
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<T>) -> Self {
+        assert_eq!(pixels.len(), width * height, "pixel count must match size");
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[T] {
+        &self.pixels
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> &T {
+        assert!(x < self.width && y < self.height, "pixel out of bounds");
+        &self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, value: T) {
+        assert!(x < self.width && y < self.height, "pixel out of bounds");
+        self.pixels[y * self.width + x] = value;
+    }
+
+    pub fn map<U, F: FnMut(&T) -> U>(&self, f: F) -> Framebuffer<U> {
+        Framebuffer::from_pixels(self.width, self.height, self.pixels.iter().map(f).collect())
+    }
+}
+
+// Clamps to [0, 1] and applies gamma 2.0
+pub fn gamma_encode(c: &Color) -> Rgb8 {
+    let encode = |v: f32| (256.0 * f32::clamp(f32::sqrt(v), 0.0, 0.999)) as u8;
+    [encode(c.r()), encode(c.g()), encode(c.b())]
+}
+
+// Inverse of gamma_encode
+pub fn gamma_decode(c: &Rgb8) -> Color {
+    let decode = |v: u8| {
+        let v = v as f32 / 255.0;
+        v * v
+    };
+    Color(decode(c[0]), decode(c[1]), decode(c[2]))
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase()
+}
+
+fn unsupported_format(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("unsupported image format: {}", path.display()),
+    )
+}
+
+impl Framebuffer<Color> {
+    // Writes the image in the format given by the file extension.
+    // Radiance HDR and OpenEXR keep the full range, the 8-bit formats are gamma encoded.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let write = |w: &mut BufWriter<fs::File>| match extension(path).as_str() {
+            "hdr" => self.write_hdr(w),
+            _ => self.write_exr(w, ExrPixelType::Half, ExrCompression::Zip),
+        };
+        match extension(path).as_str() {
+            "hdr" | "exr" => {
+                let mut w = BufWriter::new(fs::File::create(path)?);
+                write(&mut w)?;
+                w.flush()
+            }
+            _ => self.map(gamma_encode).save(path),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        match extension(path).as_str() {
+            "hdr" => Self::read_hdr(BufReader::new(fs::File::open(path)?)),
+            "exr" => Self::read_exr(BufReader::new(fs::File::open(path)?)),
+            _ => Ok(Framebuffer::<Rgb8>::load(path)?.map(gamma_decode)),
+        }
+    }
+}
+
+impl Framebuffer<Rgb8> {
+    // Writes the image in the format given by the file extension
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let format = extension(path);
+        if format != "ppm" && format != "png" {
+            return Err(unsupported_format(path));
+        }
+        let mut w = BufWriter::new(fs::File::create(path)?);
+        if format == "ppm" {
+            self.write_ppm(&mut w)?;
+        } else {
+            self.write_png(&mut w)?;
+        }
+        w.flush()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        match extension(path).as_str() {
+            "ppm" => Self::read_ppm(BufReader::new(fs::File::open(path)?)),
+            "png" => Self::read_png(BufReader::new(fs::File::open(path)?)),
+            _ => Err(unsupported_format(path)),
+        }
+    }
+
+    // Binary P6 PPM
+    pub fn write_ppm<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write!(w, "P6\n{} {}\n255\n", self.width, self.height)?;
+        w.write_all(&self.pixels.concat())
+    }
+
+    // Reads both the binary P6 and the ASCII P3 PPM variants
+    pub fn read_ppm<R: BufRead>(mut r: R) -> io::Result<Self> {
+        let mut data = Vec::new();
+        r.read_to_end(&mut data)?;
+
+        // Header tokens are separated by whitespace and may be followed by comments
+        let mut pos = 0;
+        let mut next_token = |data: &[u8]| -> io::Result<String> {
+            loop {
+                match data.get(pos) {
+                    Some(b'#') => {
+                        while data.get(pos).is_some_and(|&c| c != b'\n') {
+                            pos += 1;
+                        }
+                    }
+                    Some(c) if c.is_ascii_whitespace() => pos += 1,
+                    Some(_) => break,
+                    None => return Err(invalid_data("unexpected end of PPM header")),
+                }
+            }
+            let start = pos;
+            while data.get(pos).is_some_and(|c| !c.is_ascii_whitespace()) {
+                pos += 1;
+            }
+            Ok(String::from_utf8_lossy(&data[start..pos]).into_owned())
+        };
+        let parse = |token: String| -> io::Result<usize> {
+            token
+                .parse()
+                .map_err(|_| invalid_data("invalid number in PPM"))
+        };
+
+        let magic = next_token(&data)?;
+        let width = parse(next_token(&data)?)?;
+        let height = parse(next_token(&data)?)?;
+        let max = parse(next_token(&data)?)?;
+        if max == 0 || max > 255 {
+            return Err(invalid_data("only 8-bit PPM images are supported"));
+        }
+        let scale = |v: usize| (v * 255 / max) as u8;
+
+        // Every sample takes at least a byte
+        let count = width
+            .checked_mul(height)
+            .and_then(|n| n.checked_mul(3))
+            .filter(|&n| n <= data.len())
+            .ok_or_else(|| invalid_data("PPM raster is truncated"))?;
+        let samples: Vec<u8> = match magic.as_str() {
+            "P6" => {
+                // A single whitespace byte separates the header from the raster
+                let raster = data
+                    .get(pos + 1..pos + 1 + count)
+                    .ok_or_else(|| invalid_data("PPM raster is truncated"))?;
+                raster.iter().map(|&v| scale(v as usize)).collect()
+            }
+            "P3" => (0..count)
+                .map(|_| Ok(scale(parse(next_token(&data)?)?)))
+                .collect::<io::Result<_>>()?,
+            _ => return Err(invalid_data("not a P3 or P6 PPM image")),
+        };
+        let pixels = samples.chunks(3).map(|c| [c[0], c[1], c[2]]).collect();
+        Ok(Framebuffer::from_pixels(width, height, pixels))
+    }
+
+    // 8-bit RGB PNG, every row uses the filter that gives the smallest residuals
+    pub fn write_png<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(&PNG_SIGNATURE)?;
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // bit depth 8, color type RGB, deflate, adaptive filtering, no interlace
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+        write_png_chunk(w, b"IHDR", &header)?;
+
+        let stride = self.width * 3;
+        let mut filtered = Vec::with_capacity((stride + 1) * self.height);
+        let zero_row = vec![0; stride];
+        let mut candidate = vec![0; stride];
+        let mut best = vec![0; stride];
+        for y in 0..self.height {
+            let row = self.pixels[y * self.width..(y + 1) * self.width].concat();
+            let previous = if y == 0 {
+                zero_row.clone()
+            } else {
+                self.pixels[(y - 1) * self.width..y * self.width].concat()
+            };
+
+            let mut best_filter = 0;
+            let mut best_cost = u64::MAX;
+            for filter in 0..5 {
+                for i in 0..stride {
+                    let a = if i >= 3 { row[i - 3] } else { 0 };
+                    let c = if i >= 3 { previous[i - 3] } else { 0 };
+                    candidate[i] = row[i].wrapping_sub(predict(filter, a, previous[i], c));
+                }
+                // Sum of residuals as signed bytes, small values compress best
+                let cost = candidate
+                    .iter()
+                    .map(|&v| (v as i8).unsigned_abs() as u64)
+                    .sum();
+                if cost < best_cost {
+                    best_cost = cost;
+                    best_filter = filter;
+                    std::mem::swap(&mut best, &mut candidate);
+                }
+            }
+            filtered.push(best_filter);
+            filtered.extend_from_slice(&best);
+        }
+        write_png_chunk(w, b"IDAT", &zlib::compress(&filtered))?;
+        write_png_chunk(w, b"IEND", &[])
+    }
+
+    // Decodes non-interlaced 8 and 16-bit PNG images of every color type
+    pub fn read_png<R: Read>(mut r: R) -> io::Result<Self> {
+        let mut signature = [0; 8];
+        r.read_exact(&mut signature)?;
+        if signature != PNG_SIGNATURE {
+            return Err(invalid_data("not a PNG image"));
+        }
+
+        let mut header = None;
+        let mut palette = Vec::new();
+        let mut compressed = Vec::new();
+        loop {
+            let mut len = [0; 4];
+            r.read_exact(&mut len)?;
+            let mut chunk = vec![0; u32::from_be_bytes(len) as usize + 4];
+            r.read_exact(&mut chunk)?;
+            let mut crc = [0; 4];
+            r.read_exact(&mut crc)?;
+            if crc32(&chunk) != u32::from_be_bytes(crc) {
+                return Err(invalid_data("PNG chunk checksum mismatch"));
+            }
+
+            let (kind, data) = chunk.split_at(4);
+            match kind {
+                b"IHDR" if data.len() == 13 => header = Some(PngHeader::parse(data)?),
+                b"PLTE" if data.len() % 3 == 0 && data.len() <= 256 * 3 => {
+                    palette = data.chunks(3).map(|c| [c[0], c[1], c[2]]).collect()
+                }
+                b"PLTE" => return Err(invalid_data("invalid PNG palette")),
+                b"IDAT" => compressed.extend_from_slice(data),
+                b"IEND" => break,
+                _ => {}
+            }
+        }
+        let header = header.ok_or_else(|| invalid_data("PNG has no IHDR chunk"))?;
+
+        let raw = zlib::decompress(&compressed)?;
+        let bpp = header.bytes_per_pixel();
+        // Every row starts with its filter type
+        let stride = header.width.checked_mul(bpp);
+        let size = stride
+            .and_then(|n| n.checked_add(1))
+            .and_then(|n| n.checked_mul(header.height));
+        let (stride, size) = stride
+            .zip(size)
+            .ok_or_else(|| invalid_data("PNG image is too large"))?;
+        if raw.len() < size {
+            return Err(invalid_data("PNG image data is truncated"));
+        }
+
+        let mut pixels = Vec::with_capacity(header.width * header.height);
+        let mut previous = vec![0; stride];
+        let mut row = vec![0; stride];
+        for y in 0..header.height {
+            let line = &raw[y * (stride + 1)..(y + 1) * (stride + 1)];
+            let filter = line[0];
+            if filter > 4 {
+                return Err(invalid_data("invalid PNG filter type"));
+            }
+            for i in 0..stride {
+                let a = if i >= bpp { row[i - bpp] } else { 0 };
+                let c = if i >= bpp { previous[i - bpp] } else { 0 };
+                row[i] = line[i + 1].wrapping_add(predict(filter, a, previous[i], c));
+            }
+            for x in 0..header.width {
+                pixels.push(header.to_rgb8(&row[x * bpp..(x + 1) * bpp], &palette)?);
+            }
+            std::mem::swap(&mut previous, &mut row);
+        }
+        Ok(Framebuffer::from_pixels(
+            header.width,
+            header.height,
+            pixels,
+        ))
+    }
+}
+
+struct PngHeader {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+}
+
+impl PngHeader {
+    fn parse(data: &[u8]) -> io::Result<PngHeader> {
+        let header = PngHeader {
+            width: u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize,
+            height: u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize,
+            bit_depth: data[8],
+            color_type: data[9],
+        };
+        let (compression, filter, interlace) = (data[10], data[11], data[12]);
+        if compression != 0 || filter != 0 {
+            return Err(invalid_data("unknown PNG compression or filter method"));
+        }
+        if interlace != 0 {
+            return Err(invalid_data("interlaced PNG images are not supported"));
+        }
+        let depth_supported = match header.color_type {
+            3 => header.bit_depth == 8,
+            0 | 2 | 4 | 6 => header.bit_depth == 8 || header.bit_depth == 16,
+            _ => false,
+        };
+        if !depth_supported {
+            return Err(invalid_data("unsupported PNG bit depth or color type"));
+        }
+        Ok(header)
+    }
+
+    fn channels(&self) -> usize {
+        match self.color_type {
+            0 | 3 => 1,
+            4 => 2,
+            2 => 3,
+            _ => 4,
+        }
+    }
+
+    fn bytes_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth as usize / 8
+    }
+
+    fn to_rgb8(&self, pixel: &[u8], palette: &[Rgb8]) -> io::Result<Rgb8> {
+        // 16-bit samples are big endian, keep the most significant byte
+        let sample = |i: usize| pixel[i * self.bit_depth as usize / 8];
+        Ok(match self.color_type {
+            0 | 4 => [sample(0); 3],
+            3 => *palette
+                .get(pixel[0] as usize)
+                .ok_or_else(|| invalid_data("PNG palette index out of range"))?,
+            _ => [sample(0), sample(1), sample(2)],
+        })
+    }
+}
+
+// PNG filter predictors, `a` is the byte to the left, `b` above and `c` above-left
+fn predict(filter: u8, a: u8, b: u8, c: u8) -> u8 {
+    match filter {
+        0 => 0,
+        1 => a,
+        2 => b,
+        3 => ((a as u16 + b as u16) / 2) as u8,
+        _ => {
+            let p = a as i16 + b as i16 - c as i16;
+            let (pa, pb, pc) = (
+                (p - a as i16).abs(),
+                (p - b as i16).abs(),
+                (p - c as i16).abs(),
+            );
+            if pa <= pb && pa <= pc {
+                a
+            } else if pb <= pc {
+                b
+            } else {
+                c
+            }
+        }
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut n = 0;
+        while n < 256 {
+            let mut c = n as u32;
+            let mut k = 0;
+            while k < 8 {
+                c = if c & 1 != 0 {
+                    0xedb8_8320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                };
+                k += 1;
+            }
+            table[n] = c;
+            n += 1;
+        }
+        table
+    };
+    !data.iter().fold(!0u32, |crc, &b| {
+        TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+fn write_png_chunk<W: Write>(w: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    let mut chunk = Vec::with_capacity(data.len() + 4);
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    w.write_all(&chunk)?;
+    w.write_all(&crc32(&chunk).to_be_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn gradient(width: usize, height: usize) -> Framebuffer<Rgb8> {
+        let mut image = Framebuffer::new(width, height, [0; 3]);
+        for y in 0..height {
+            for x in 0..width {
+                image.set(
+                    x,
+                    y,
+                    [(x * 255 / width) as u8, (y * 255 / height) as u8, 128],
+                );
+            }
+        }
+        image
+    }
+
+    fn noise(width: usize, height: usize) -> Framebuffer<Rgb8> {
+        let mut rng = StdRng::seed_from_u64(5);
+        let pixels = (0..width * height).map(|_| rng.gen()).collect();
+        Framebuffer::from_pixels(width, height, pixels)
+    }
+
+    #[test]
+    fn crc32_known_value() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn gamma_encode_clamps() {
+        assert_eq!(gamma_encode(&Color(0.0, 0.25, 4.0)), [0, 128, 255]);
+    }
+
+    #[test]
+    fn png_roundtrip() {
+        for image in [gradient(64, 48), noise(17, 9), gradient(1, 1)] {
+            let mut png = Vec::new();
+            image.write_png(&mut png).unwrap();
+            assert_eq!(Framebuffer::read_png(png.as_slice()).unwrap(), image);
+        }
+    }
+
+    #[test]
+    fn png_is_smaller_than_raw() {
+        let image = gradient(256, 256);
+        let mut png = Vec::new();
+        image.write_png(&mut png).unwrap();
+        assert!(png.len() < 256 * 256 * 3 / 10);
+    }
+
+    #[test]
+    fn png_rejects_corrupted_chunk() {
+        let mut png = Vec::new();
+        gradient(8, 8).write_png(&mut png).unwrap();
+        png[20] ^= 0xff;
+        assert!(Framebuffer::read_png(png.as_slice()).is_err());
+    }
+
+    #[test]
+    fn png_decodes_rgba_and_grayscale() {
+        // 2x1 RGBA and 2x1 grayscale images
+        let encode = |color_type: u8, raw: &[u8]| {
+            let mut png = PNG_SIGNATURE.to_vec();
+            let header = [0, 0, 0, 2, 0, 0, 0, 1, 8, color_type, 0, 0, 0];
+            write_png_chunk(&mut png, b"IHDR", &header).unwrap();
+            write_png_chunk(&mut png, b"IDAT", &zlib::compress(raw)).unwrap();
+            write_png_chunk(&mut png, b"IEND", &[]).unwrap();
+            Framebuffer::read_png(png.as_slice()).unwrap()
+        };
+        let rgba = encode(6, &[0, 1, 2, 3, 255, 4, 5, 6, 0]);
+        assert_eq!(rgba.pixels(), &[[1, 2, 3], [4, 5, 6]]);
+        let gray = encode(0, &[1, 10, 20]);
+        // filter 1 (Sub) adds the left neighbour
+        assert_eq!(gray.pixels(), &[[10, 10, 10], [30, 30, 30]]);
+    }
+
+    #[test]
+    fn png_rejects_bad_palette_and_huge_size() {
+        let decode = |header: &[u8], palette: &[u8]| {
+            let mut png = PNG_SIGNATURE.to_vec();
+            write_png_chunk(&mut png, b"IHDR", header).unwrap();
+            write_png_chunk(&mut png, b"PLTE", palette).unwrap();
+            write_png_chunk(&mut png, b"IDAT", &zlib::compress(&[0, 0])).unwrap();
+            write_png_chunk(&mut png, b"IEND", &[]).unwrap();
+            Framebuffer::read_png(png.as_slice())
+                .unwrap_err()
+                .to_string()
+        };
+        let indexed = [0, 0, 0, 1, 0, 0, 0, 1, 8, 3, 0, 0, 0];
+        assert_eq!(decode(&indexed, &[1, 2]), "invalid PNG palette");
+        assert_eq!(decode(&indexed, &[0; 771]), "invalid PNG palette");
+        let huge = [255, 255, 255, 255, 255, 255, 255, 255, 16, 6, 0, 0, 0];
+        assert_eq!(decode(&huge, &[]), "PNG image is too large");
+    }
+
+    #[test]
+    fn ppm_rejects_huge_size() {
+        for ppm in [
+            &b"P6\n4294967296 4294967296\n255\n\0"[..],
+            b"P3\n9 1\n255\n0 0 0\n",
+        ] {
+            assert_eq!(
+                Framebuffer::read_ppm(ppm).unwrap_err().to_string(),
+                "PPM raster is truncated"
+            );
+        }
+    }
+
+    #[test]
+    fn ppm_roundtrip() {
+        let image = noise(13, 7);
+        let mut ppm = Vec::new();
+        image.write_ppm(&mut ppm).unwrap();
+        assert!(ppm.starts_with(b"P6\n13 7\n255\n"));
+        assert_eq!(ppm.len(), 12 + 13 * 7 * 3);
+        assert_eq!(Framebuffer::read_ppm(ppm.as_slice()).unwrap(), image);
+    }
+
+    #[test]
+    fn ppm_reads_ascii_with_comments() {
+        let ppm = b"P3\n# a comment\n2 1\n255\n255 0 0\n0 0 255\n";
+        let image = Framebuffer::read_ppm(&ppm[..]).unwrap();
+        assert_eq!(image.pixels(), &[[255, 0, 0], [0, 0, 255]]);
+    }
+
+    #[test]
+    fn save_hdr_formats_keep_full_range() {
+        let dir = std::env::temp_dir();
+        let image = Framebuffer::new(4, 2, Color(8.0, 0.5, 0.0));
+        for name in ["framebuffer_test.hdr", "framebuffer_test.exr"] {
+            let path = dir.join(name);
+            image.save(&path).unwrap();
+            let loaded = Framebuffer::<Color>::load(&path).unwrap();
+            assert!((loaded.get(3, 1).r() - 8.0).abs() < 0.1);
+            fs::remove_file(&path).unwrap();
+        }
+
+        // 8-bit formats are clamped
+        let path = dir.join("framebuffer_test_ldr.png");
+        image.save(&path).unwrap();
+        assert_eq!(
+            Framebuffer::<Rgb8>::load(&path).unwrap().get(3, 1),
+            &[255, 181, 0]
+        );
+        let linear = Framebuffer::<Color>::load(&path).unwrap();
+        assert_eq!(linear.get(3, 1).r(), 1.0);
+        assert!((linear.get(3, 1).g() - 0.5).abs() < 0.01);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn save_picks_format_from_extension() {
+        let dir = std::env::temp_dir();
+        let image = gradient(5, 3);
+        for name in ["framebuffer_test.png", "framebuffer_test.PPM"] {
+            let path = dir.join(name);
+            image.save(&path).unwrap();
+            assert_eq!(Framebuffer::<Rgb8>::load(&path).unwrap(), image);
+            fs::remove_file(&path).unwrap();
+        }
+        assert_eq!(
+            image
+                .save(dir.join("framebuffer_test.bmp"))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+}
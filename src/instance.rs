@@ -0,0 +1,173 @@
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, Hittable};
+use crate::material::Scatterable;
+use crate::ray::Ray;
+use crate::vector::{AnimatedTransform, Len, Normalize, Transform};
+use std::sync::Arc;
+
+// A shared object placed in the world by an object-to-world transform,
+// which may change over the shutter interval.
+// Rays are moved into object space, hits are moved back.
+pub struct Instance {
+    object: Arc<dyn Hittable>,
+    object_to_world: AnimatedTransform,
+    bbox: Aabb,
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Hittable>, object_to_world: Transform) -> Instance {
+        Instance::animated(object, AnimatedTransform::fixed(object_to_world))
+    }
+
+    pub fn animated(object: Arc<dyn Hittable>, object_to_world: AnimatedTransform) -> Instance {
+        let bbox = object.bounding_box().swept(&object_to_world);
+        Instance {
+            object,
+            object_to_world,
+            bbox,
+        }
+    }
+
+    pub fn object(&self) -> &Arc<dyn Hittable> {
+        &self.object
+    }
+
+    pub fn transform(&self) -> &AnimatedTransform {
+        &self.object_to_world
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(Hit, &dyn Scatterable)> {
+        let object_to_world = self.object_to_world.at(r.time);
+        let world_to_object = object_to_world.inverse();
+        let dir = world_to_object.transform_vector(r.dir);
+        // Ray directions are unit vectors, so distances scale by the length
+        let scale = dir.len();
+        let object_ray =
+            Ray::new(world_to_object.transform_point(r.orig), dir / scale).with_time(r.time);
+        let (hit, material) = self.object.hit(&object_ray, t_min * scale, t_max * scale)?;
+
+        let outward_normal = if hit.front_face() { hit.n() } else { -hit.n() };
+        let mut world_hit = Hit::new(
+            r.dir,
+            object_to_world.transform_point(hit.p()),
+            object_to_world.transform_normal(outward_normal).normalize(),
+            hit.t() / scale,
+        )
+        .with_uv(hit.uv().0, hit.uv().1)
+        .with_barycentric(hit.barycentric().0, hit.barycentric().1);
+        if let Some(color) = hit.color() {
+            world_hit = world_hit.with_color(color);
+        }
+        Some((world_hit, material))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::hittable_vec::HittableVec;
+    use crate::material::Lambertian;
+    use crate::point::Point3;
+    use crate::sphere::Sphere;
+    use crate::vector::Vec3;
+
+    fn unit_sphere() -> Arc<dyn Hittable> {
+        Arc::new(Sphere::new(
+            Point3::zero(),
+            1.0,
+            Arc::new(Lambertian::new(Color(0.5, 0.5, 0.5))),
+        ))
+    }
+
+    #[test]
+    fn instance_translated_and_scaled() {
+        let instance = Instance::new(
+            unit_sphere(),
+            Transform::translation(Vec3(0.0, 0.0, -10.0)) * Transform::scaling(Vec3(2.0, 2.0, 2.0)),
+        );
+        let r = Ray::new(Point3::zero(), Vec3(0.0, 0.0, -1.0));
+        let (hit, _) = instance.hit(&r, 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t() - 8.0).abs() < 1e-5);
+        assert!(Vec3::almost_eq(
+            hit.p() - Point3(0.0, 0.0, -8.0),
+            Vec3::zero(),
+            1e-5
+        ));
+        assert!(Vec3::almost_eq(hit.n(), Vec3(0.0, 0.0, 1.0), 1e-5));
+        assert!(hit.front_face());
+
+        // t_max is measured in world space
+        assert!(instance.hit(&r, 0.001, 7.9).is_none());
+        assert_eq!(
+            instance.bounding_box(),
+            Aabb::new(Point3(-2.0, -2.0, -12.0), Point3(2.0, 2.0, -8.0))
+        );
+    }
+
+    #[test]
+    fn instance_normals_follow_non_uniform_scaling() {
+        // An ellipsoid twice as wide as it is high
+        let instance = Instance::new(unit_sphere(), Transform::scaling(Vec3(2.0, 1.0, 1.0)));
+        let p = Point3(2.0f32.sqrt(), 0.5f32.sqrt(), 0.0);
+        let dir = Vec3(-1.0, -1.0, 0.0).normalize();
+        let r = Ray::new(p - 5.0 * dir, dir);
+        let (hit, _) = instance.hit(&r, 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t() - 5.0).abs() < 1e-4);
+        // The gradient of x^2/4 + y^2
+        let expected = Vec3(p.x() / 2.0, 2.0 * p.y(), 0.0).normalize();
+        assert!(Vec3::almost_eq(hit.n(), expected, 1e-4));
+
+        // From inside the normal faces the ray
+        let r = Ray::new(Point3::zero(), Vec3(1.0, 0.0, 0.0));
+        let (hit, _) = instance.hit(&r, 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t() - 2.0).abs() < 1e-5);
+        assert!(!hit.front_face());
+        assert!(Vec3::almost_eq(hit.n(), Vec3(-1.0, 0.0, 0.0), 1e-5));
+    }
+
+    #[test]
+    fn animated_instance_moves_with_the_ray_time() {
+        let motion = AnimatedTransform::new(
+            Transform::translation(Vec3(0.0, 0.0, -10.0)),
+            Transform::translation(Vec3(4.0, 0.0, -10.0)),
+            0.0,
+            1.0,
+        )
+        .unwrap();
+        let instance = Instance::animated(unit_sphere(), motion);
+        let r = Ray::new(Point3(2.0, 0.0, 0.0), Vec3(0.0, 0.0, -1.0));
+        assert!(instance.hit(&r, 0.001, f32::INFINITY).is_none());
+        let (hit, _) = instance
+            .hit(&r.with_time(0.5), 0.001, f32::INFINITY)
+            .unwrap();
+        assert!((hit.t() - 9.0).abs() < 1e-5);
+
+        let bbox = instance.bounding_box();
+        assert!(bbox.min.x() <= -1.0 && bbox.max.x() >= 5.0);
+    }
+
+    #[test]
+    fn instances_share_one_object() {
+        let sphere = unit_sphere();
+        let mut world = HittableVec::new();
+        for i in 0..1000 {
+            let x = (i % 10) as f32 * 3.0;
+            let z = -((i / 10) as f32) * 3.0;
+            let place = Transform::translation(Vec3(x, 0.0, z))
+                * Transform::rotation(i as f32, Vec3(0.0, 1.0, 0.0));
+            world.push(Box::new(Instance::new(sphere.clone(), place)));
+        }
+        assert_eq!(Arc::strong_count(&sphere), 1001);
+
+        let r = Ray::new(Point3(27.0, 10.0, -297.0), Vec3(0.0, -1.0, 0.0));
+        let (hit, _) = world.hit(&r, 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t() - 9.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,33 @@
+pub mod aabb;
+pub mod background;
+pub mod bvh;
+pub mod camera;
+pub mod color;
+pub mod exr;
+pub mod gltf;
+pub mod hdr;
+pub mod hittable;
+pub mod hittable_vec;
+pub mod image;
+pub mod instance;
+pub mod json;
+pub mod light;
+pub mod material;
+pub mod mesh;
+pub mod microfacet;
+pub mod noise;
+pub mod obj;
+pub mod parse;
+pub mod pbrt;
+pub mod ply;
+pub mod point;
+pub mod ray;
+pub mod render;
+pub mod sampling;
+pub mod scene_file;
+pub mod scenes;
+pub mod sphere;
+pub mod texture;
+pub mod triangle;
+pub mod vector;
+pub mod zlib;
@@ -0,0 +1,212 @@
+use crate::color::Color;
+use crate::material::Scatterable;
+use crate::point::Point3;
+use crate::ray::Ray;
+use crate::sphere::hit_sphere;
+use crate::vector::{orthonormal_basis, Len, Normalize, Vec3};
+use rand::{Rng, RngCore};
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+// A direction from a point toward a light
+pub struct LightSample {
+    pub dir: Vec3,
+    // Up to the point on the light, shadow rays stop short of it
+    pub distance: f32,
+    // What the light emits back along dir
+    pub radiance: Color,
+    // Solid angle pdf of dir
+    pub pdf: f32,
+}
+
+// An emitter the renderer can aim at instead of waiting for a bounce
+// to find it. The same object must also be in the world to be seen.
+pub trait Light: Send + Sync {
+    fn sample(&self, origin: Point3, time: f32, rng: &mut dyn RngCore) -> Option<LightSample>;
+
+    // Solid angle pdf with which sample picks the direction from origin
+    // to p, 0 unless p is on the light
+    fn pdf(&self, origin: Point3, p: Point3, time: f32) -> f32;
+}
+
+// A sphere seen from outside covers a cone of directions,
+// which is sampled uniformly
+pub struct SphereLight {
+    center: Point3,
+    radius: f32,
+    material: Arc<dyn Scatterable>,
+}
+
+impl SphereLight {
+    pub fn new(center: Point3, radius: f32, material: Arc<dyn Scatterable>) -> SphereLight {
+        assert!(radius > 0.0, "radius must be positive");
+        SphereLight {
+            center,
+            radius,
+            material,
+        }
+    }
+
+    // 1 - cos of the half angle of the cone, None from inside the sphere
+    fn cone(&self, origin: Point3) -> Option<f32> {
+        let distance_squared = (self.center - origin).len_squared();
+        let sin2_theta_max = self.radius * self.radius / distance_squared;
+        if sin2_theta_max >= 1.0 {
+            return None;
+        }
+        // Avoids the cancellation of 1 - cos for small or far away lights
+        Some(sin2_theta_max / (1.0 + (1.0 - sin2_theta_max).sqrt()))
+    }
+}
+
+impl Light for SphereLight {
+    fn sample(&self, origin: Point3, time: f32, rng: &mut dyn RngCore) -> Option<LightSample> {
+        let one_minus_cos_max = self.cone(origin)?;
+        let w = Vec3::normalize(self.center - origin);
+        let (u, v) = orthonormal_basis(w);
+        let cos_theta = 1.0 - rng.gen::<f32>() * one_minus_cos_max;
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.gen::<f32>();
+        let dir = sin_theta * phi.cos() * u + sin_theta * phi.sin() * v + cos_theta * w;
+
+        // Directions at the rim of the cone can just miss in floating point
+        let ray = Ray::new(origin, dir.normalize()).with_time(time);
+        let hit = hit_sphere(self.center, self.radius, &ray, 0.0, f32::INFINITY)?;
+        Some(LightSample {
+            dir: ray.dir,
+            distance: hit.t(),
+            radiance: self.material.emitted(&hit),
+            pdf: 1.0 / (2.0 * PI * one_minus_cos_max),
+        })
+    }
+
+    fn pdf(&self, origin: Point3, p: Point3, _time: f32) -> f32 {
+        if ((p - self.center).len() - self.radius).abs() > 1e-3 * self.radius {
+            return 0.0;
+        }
+        match self.cone(origin) {
+            Some(one_minus_cos_max) => 1.0 / (2.0 * PI * one_minus_cos_max),
+            None => 0.0,
+        }
+    }
+}
+
+// The lights of a scene, one of them picked at random for each sample
+#[derive(Default)]
+pub struct LightList {
+    lights: Vec<Box<dyn Light>>,
+}
+
+impl LightList {
+    pub fn new() -> LightList {
+        LightList::default()
+    }
+
+    pub fn push(&mut self, light: Box<dyn Light>) {
+        self.lights.push(light);
+    }
+
+    pub fn append(&mut self, other: LightList) {
+        self.lights.extend(other.lights);
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    pub fn sample(&self, origin: Point3, time: f32, rng: &mut dyn RngCore) -> Option<LightSample> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let light = &self.lights[rng.gen_range(0..self.lights.len())];
+        let mut sample = light.sample(origin, time, rng)?;
+        sample.pdf /= self.lights.len() as f32;
+        Some(sample)
+    }
+
+    pub fn pdf(&self, origin: Point3, p: Point3, time: f32) -> f32 {
+        if self.lights.is_empty() {
+            return 0.0;
+        }
+        let sum: f32 = self.lights.iter().map(|l| l.pdf(origin, p, time)).sum();
+        sum / self.lights.len() as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::DiffuseLight;
+    use crate::vector::Dot;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn sphere_light_samples_its_cone() {
+        let lamp = Arc::new(DiffuseLight::new(Color(4.0, 4.0, 4.0)));
+        let light = SphereLight::new(Point3(0.0, 10.0, 0.0), 1.0, lamp);
+        let origin = Point3::zero();
+        let cos_max = (1.0f32 - 0.01).sqrt();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut hits = 0;
+        for _ in 0..1000 {
+            let sample = match light.sample(origin, 0.0, &mut rng) {
+                Some(sample) => sample,
+                None => continue,
+            };
+            hits += 1;
+            assert!(sample.dir.dot(Vec3(0.0, 1.0, 0.0)) >= cos_max - 1e-6);
+            assert!((9.0 - 1e-4..=10.0).contains(&sample.distance));
+            assert_eq!(sample.radiance, Color(4.0, 4.0, 4.0));
+            let p = origin + sample.distance * sample.dir;
+            assert!((light.pdf(origin, p, 0.0) - sample.pdf).abs() < 1e-3 * sample.pdf);
+        }
+        assert!(hits > 990);
+
+        // Integrating the pdf over the cone gives 1
+        let solid_angle = 2.0 * PI * (1.0 - cos_max);
+        let p = Point3(0.0, 9.0, 0.0);
+        assert!((light.pdf(origin, p, 0.0) * solid_angle - 1.0).abs() < 1e-3);
+
+        // Off the surface, or from inside, the light cannot be sampled
+        assert_eq!(light.pdf(origin, Point3(0.0, 8.0, 0.0), 0.0), 0.0);
+        let inside = Point3(0.0, 10.5, 0.0);
+        assert!(light.sample(inside, 0.0, &mut rng).is_none());
+        assert_eq!(light.pdf(inside, p, 0.0), 0.0);
+    }
+
+    #[test]
+    fn light_list_picks_uniformly() {
+        let lamp: Arc<dyn Scatterable> = Arc::new(DiffuseLight::new(Color(1.0, 1.0, 1.0)));
+        let mut lights = LightList::new();
+        let origin = Point3::zero();
+        let mut rng = StdRng::seed_from_u64(1);
+        assert!(lights.sample(origin, 0.0, &mut rng).is_none());
+        assert_eq!(lights.pdf(origin, Point3(0.0, 9.0, 0.0), 0.0), 0.0);
+
+        lights.push(Box::new(SphereLight::new(
+            Point3(0.0, 10.0, 0.0),
+            1.0,
+            lamp.clone(),
+        )));
+        lights.push(Box::new(SphereLight::new(
+            Point3(0.0, -10.0, 0.0),
+            1.0,
+            lamp.clone(),
+        )));
+        assert_eq!(lights.len(), 2);
+        let up = (0..1000)
+            .filter_map(|_| lights.sample(origin, 0.0, &mut rng))
+            .filter(|s| s.dir.y() > 0.0)
+            .count();
+        assert!((400..600).contains(&up), "{}", up);
+
+        let single = SphereLight::new(Point3(0.0, 10.0, 0.0), 1.0, lamp);
+        let p = Point3(0.0, 9.0, 0.0);
+        assert!((lights.pdf(origin, p, 0.0) - 0.5 * single.pdf(origin, p, 0.0)).abs() < 1e-3);
+    }
+}
@@ -1,42 +1,113 @@
 use crate::color::Color;
 use crate::hittable::Hit;
+use crate::microfacet::{Frame, Ggx};
 use crate::ray::Ray;
-use crate::vector::{uniform_in_unit_sphere, uniform_on_unit_sphere, Dot, Len, Normalize, Vec3};
-use rand::thread_rng;
+use crate::texture::Texture;
+use crate::vector::{
+    uniform_in_unit_sphere, uniform_on_unit_sphere, Cross, Dot, Len, Normalize, Vec3,
+};
+use rand::{Rng, RngCore};
+use std::f32::consts::PI;
+use std::sync::Arc;
 
-pub trait Scatterable {
-    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<(Color, Ray)>;
+pub trait Scatterable: Send + Sync {
+    fn scatter(&self, r_in: &Ray, hit: &Hit, rng: &mut dyn RngCore) -> Option<(Color, Ray)>;
+
+    // Radiance the surface gives off by itself
+    fn emitted(&self, _hit: &Hit) -> Color {
+        Color::zero()
+    }
+
+    // The scattering function times the cosine of wi, consistent with what
+    // scatter returns on average. wo points back along the incoming ray and
+    // wi toward where the light comes from, both unit vectors.
+    fn eval(&self, _hit: &Hit, _wi: Vec3, _wo: Vec3) -> Color {
+        Color::zero()
+    }
+
+    // Solid angle pdf with which scatter picks wi. It stays 0 for surfaces
+    // that scatter in a single direction like mirrors and glass, which
+    // tells the renderer that sampling the lights cannot help there.
+    fn pdf(&self, _hit: &Hit, _wi: Vec3, _wo: Vec3) -> f32 {
+        0.0
+    }
+}
+
+fn texture_at(texture: &dyn Texture, hit: &Hit) -> Color {
+    let (u, v) = hit.uv();
+    texture.value(u, v, hit.p())
+}
+
+// Roughness and height maps are gray, read by their luminance
+fn scalar_at(texture: &dyn Texture, hit: &Hit) -> f32 {
+    texture_at(texture, hit).luminance()
 }
 
 pub struct Lambertian {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
+    // Prefer the color interpolated from the mesh vertices
+    vertex_colors: bool,
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Self {
-        Self { albedo }
+        Self::textured(Arc::new(albedo))
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Self {
+        Self {
+            albedo,
+            vertex_colors: false,
+        }
+    }
+
+    // albedo is used where the surface has no vertex colors
+    pub fn with_vertex_colors(albedo: Color) -> Self {
+        Self {
+            albedo: Arc::new(albedo),
+            vertex_colors: true,
+        }
+    }
+
+    fn albedo(&self, hit: &Hit) -> Color {
+        match hit.color() {
+            Some(color) if self.vertex_colors => color,
+            _ => texture_at(&*self.albedo, hit),
+        }
     }
 }
 
 impl Scatterable for Lambertian {
-    fn scatter(&self, _: &Ray, hit: &Hit) -> Option<(Color, Ray)> {
-        let mut scatter_dir = hit.n() + uniform_on_unit_sphere(&mut thread_rng()).into();
+    fn scatter(&self, r_in: &Ray, hit: &Hit, rng: &mut dyn RngCore) -> Option<(Color, Ray)> {
+        let mut scatter_dir = hit.n() + uniform_on_unit_sphere(rng).into();
         if scatter_dir.len() < 1e-7 {
             scatter_dir = hit.n();
         }
 
-        let scattered = Ray::new(hit.p(), scatter_dir.normalize());
-        Some((self.albedo, scattered))
+        let scattered = Ray::new(hit.p(), scatter_dir.normalize()).with_time(r_in.time);
+        Some((self.albedo(hit), scattered))
+    }
+
+    fn eval(&self, hit: &Hit, wi: Vec3, wo: Vec3) -> Color {
+        self.pdf(hit, wi, wo) * self.albedo(hit)
+    }
+
+    fn pdf(&self, hit: &Hit, wi: Vec3, _wo: Vec3) -> f32 {
+        f32::max(Vec3::dot(wi, hit.n()), 0.0) / PI
     }
 }
 
 pub struct Metal {
-    albedo: Color,
-    fuzz: f32,
+    albedo: Arc<dyn Texture>,
+    fuzz: Arc<dyn Texture>,
 }
 
 impl Metal {
     pub fn new(albedo: Color, fuzz: f32) -> Self {
+        Self::textured(Arc::new(albedo), Arc::new(Color(fuzz, fuzz, fuzz)))
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>, fuzz: Arc<dyn Texture>) -> Self {
         Self { albedo, fuzz }
     }
 }
@@ -54,23 +125,211 @@ fn reflect(v: Vec3, un: Vec3) -> Vec3 {
     v - 2.0 * Vec3::dot(v, un) * un
 }
 
+// Solid angle pdf of normalize(center + radius * u) for u uniform in the
+// unit ball and a unit center, the blurred reflection of Metal
+fn fuzzed_pdf(center: Vec3, radius: f32, dir: Vec3) -> f32 {
+    if radius <= 0.0 {
+        return 0.0;
+    }
+    // The ray t dir crosses the ball between the roots of
+    // t^2 - 2 b t + 1 - radius^2 = 0, with 1 - b^2 computed without cancellation
+    let b = Vec3::dot(dir, center);
+    let discriminant = radius * radius - Vec3::cross(dir, center).len_squared();
+    if discriminant <= 0.0 {
+        return 0.0;
+    }
+    let half_width = discriminant.sqrt();
+    let (t0, t1) = (f32::max(b - half_width, 0.0), b + half_width);
+    if t1 <= 0.0 {
+        return 0.0;
+    }
+    // Volume of the ball along the ray over the volume of the ball
+    (t1 - t0) * (t1 * t1 + t1 * t0 + t0 * t0) / (4.0 * PI * radius.powi(3))
+}
+
 impl Scatterable for Metal {
-    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<(Color, Ray)> {
+    fn scatter(&self, r_in: &Ray, hit: &Hit, rng: &mut dyn RngCore) -> Option<(Color, Ray)> {
         if Vec3::dot(r_in.dir, hit.n()) > 0.0 {
             return None;
         }
 
         let reflected = reflect(r_in.dir, hit.n());
+        let fuzz = scalar_at(&*self.fuzz, hit);
         let scattered = Ray::new(
             hit.p(),
-            Vec3::normalize(
-                reflected + self.fuzz * Vec3::from(uniform_in_unit_sphere(&mut thread_rng())),
-            ),
-        );
+            Vec3::normalize(reflected + fuzz * Vec3::from(uniform_in_unit_sphere(rng))),
+        )
+        .with_time(r_in.time);
         if Vec3::dot(scattered.dir, hit.n()) <= 0.0 {
             return None;
         }
-        Some((self.albedo, scattered))
+        Some((texture_at(&*self.albedo, hit), scattered))
+    }
+
+    // Reflections that end up below the surface are absorbed
+    fn eval(&self, hit: &Hit, wi: Vec3, wo: Vec3) -> Color {
+        if Vec3::dot(wi, hit.n()) <= 0.0 {
+            return Color::zero();
+        }
+        self.pdf(hit, wi, wo) * texture_at(&*self.albedo, hit)
+    }
+
+    fn pdf(&self, hit: &Hit, wi: Vec3, wo: Vec3) -> f32 {
+        if Vec3::dot(wo, hit.n()) <= 0.0 {
+            return 0.0;
+        }
+        fuzzed_pdf(reflect(-wo, hit.n()), scalar_at(&*self.fuzz, hit), wi)
+    }
+}
+
+// Unpolarized Fresnel reflectance of a conductor with the complex index
+// of refraction eta + i k, seen from air
+fn conductor_fresnel(cos_theta: f32, eta: f32, k: f32) -> f32 {
+    let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2.0 * cos2.sqrt() * a;
+    let rs = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    0.5 * (rs + rp)
+}
+
+// A metal made of tiny mirrors oriented by the GGX distribution. Unlike
+// the fuzz of Metal it does not lose or make up energy, besides the light
+// that would bounce between the facets. The color comes from the complex
+// index of refraction per channel, brightening toward grazing angles.
+pub struct RoughConductor {
+    eta: Color,
+    k: Color,
+    roughness: Arc<dyn Texture>,
+}
+
+impl RoughConductor {
+    pub fn new(eta: Color, k: Color, roughness: f32) -> Self {
+        Self {
+            eta,
+            k,
+            roughness: Arc::new(Color(roughness, roughness, roughness)),
+        }
+    }
+
+    pub fn with_roughness(mut self, roughness: Arc<dyn Texture>) -> Self {
+        self.roughness = roughness;
+        self
+    }
+
+    // Measured indices at 650, 550 and 450 nm
+    pub fn gold(roughness: f32) -> Self {
+        Self::new(
+            Color(0.143, 0.374, 1.442),
+            Color(3.983, 2.386, 1.603),
+            roughness,
+        )
+    }
+
+    pub fn copper(roughness: f32) -> Self {
+        Self::new(
+            Color(0.200, 0.924, 1.102),
+            Color(3.912, 2.452, 2.142),
+            roughness,
+        )
+    }
+
+    pub fn aluminium(roughness: f32) -> Self {
+        Self::new(
+            Color(1.657, 0.880, 0.521),
+            Color(9.224, 6.270, 4.837),
+            roughness,
+        )
+    }
+
+    pub fn silver(roughness: f32) -> Self {
+        Self::new(
+            Color(0.155, 0.117, 0.138),
+            Color(4.828, 3.122, 2.147),
+            roughness,
+        )
+    }
+
+    fn fresnel(&self, cos_theta: f32) -> Color {
+        let (eta, k) = (self.eta, self.k);
+        Color(
+            conductor_fresnel(cos_theta, eta.r(), k.r()),
+            conductor_fresnel(cos_theta, eta.g(), k.g()),
+            conductor_fresnel(cos_theta, eta.b(), k.b()),
+        )
+    }
+
+    // The distribution and both directions in the shading frame, None for
+    // a smooth surface or when looking at it from below
+    fn local(&self, hit: &Hit, wi: Vec3, wo: Vec3) -> Option<(Ggx, Vec3, Vec3)> {
+        let ggx = Ggx::from_roughness(scalar_at(&*self.roughness, hit))?;
+        let frame = Frame::new(hit.n());
+        let (wi, wo) = (frame.to_local(wi), frame.to_local(wo));
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        Some((ggx, wi, wo))
+    }
+}
+
+impl Scatterable for RoughConductor {
+    fn scatter(&self, r_in: &Ray, hit: &Hit, rng: &mut dyn RngCore) -> Option<(Color, Ray)> {
+        let cos_theta = Vec3::dot(-r_in.dir, hit.n());
+        if cos_theta <= 0.0 {
+            return None;
+        }
+        let ggx = match Ggx::from_roughness(scalar_at(&*self.roughness, hit)) {
+            Some(ggx) => ggx,
+            None => {
+                let reflected = reflect(r_in.dir, hit.n());
+                let scattered = Ray::new(hit.p(), reflected).with_time(r_in.time);
+                return Some((self.fresnel(cos_theta), scattered));
+            }
+        };
+        // Reflects off a visible facet, the facets hiding the reflection
+        // are all that is left of the microfacet model in the weight
+        let frame = Frame::new(hit.n());
+        let wo = frame.to_local(-r_in.dir);
+        let m = ggx.sample_visible(wo, rng.gen(), rng.gen());
+        let cos_m = Vec3::dot(wo, m);
+        let wi = 2.0 * cos_m * m - wo;
+        if wi.z() <= 0.0 {
+            return None;
+        }
+        let attenuation = (ggx.g2(wo, wi) / ggx.g1(wo)) * self.fresnel(cos_m);
+        let direction = frame.to_world(wi).normalize();
+        Some((
+            attenuation,
+            Ray::new(hit.p(), direction).with_time(r_in.time),
+        ))
+    }
+
+    fn eval(&self, hit: &Hit, wi: Vec3, wo: Vec3) -> Color {
+        let (ggx, wi, wo) = match self.local(hit, wi, wo) {
+            Some(local) if local.1.z() > 0.0 => local,
+            _ => return Color::zero(),
+        };
+        let m = (wi + wo).normalize();
+        (ggx.d(m) * ggx.g2(wo, wi) / (4.0 * wo.z())) * self.fresnel(Vec3::dot(wo, m))
+    }
+
+    fn pdf(&self, hit: &Hit, wi: Vec3, wo: Vec3) -> f32 {
+        let (ggx, wi, wo) = match self.local(hit, wi, wo) {
+            Some(local) => local,
+            None => return 0.0,
+        };
+        let half = wi + wo;
+        if half.len_squared() < 1e-12 {
+            return 0.0;
+        }
+        let m = half.normalize();
+        ggx.visible_pdf(wo, m) / (4.0 * Vec3::dot(wo, m))
     }
 }
 
@@ -113,7 +372,7 @@ fn reflectance(cosine: f32, index_of_refraction: f32) -> f32 {
 }
 
 impl Scatterable for Dielectric {
-    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<(Color, Ray)> {
+    fn scatter(&self, r_in: &Ray, hit: &Hit, rng: &mut dyn RngCore) -> Option<(Color, Ray)> {
         let refraction_ratio = if hit.front_face() {
             1.0 / self.index_of_refraction
         } else {
@@ -126,13 +385,623 @@ impl Scatterable for Dielectric {
         let direction =
             // Depending on the refraction ratio, the light might not be able to refract, and instead reflects
             // Uses Schlick's approximation as the reflection varies with the angle.
-            if rand::random::<f32>() < reflectance(cos_theta, refraction_ratio) {
+            if rng.gen::<f32>() < reflectance(cos_theta, refraction_ratio) {
                 reflect(r_in.dir, hit.n())
             } else if let Some(refracted) = refract(r_in.dir, hit.n(), refraction_ratio) {
                 refracted.normalize()
             } else {
                 reflect(r_in.dir, hit.n())
             };
-        Some((attenuation, Ray::new(hit.p(), direction)))
+        Some((attenuation, Ray::new(hit.p(), direction).with_time(r_in.time)))
+    }
+}
+
+// Unpolarized Fresnel reflectance where light meets a medium with eta
+// times the index of refraction, 1 under total internal reflection
+fn dielectric_fresnel(cos_theta: f32, eta: f32) -> f32 {
+    let cos_i = cos_theta.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+// Frosted glass, made of tiny smooth facets oriented by the GGX distribution
+// that each reflect or refract. Without roughness it is the same as
+// Dielectric, with the exact Fresnel equations instead of Schlick's.
+// Like Dielectric it does not scale radiance by the squared ratio of the
+// indices when crossing the surface.
+pub struct RoughDielectric {
+    index_of_refraction: f32,
+    roughness: Arc<dyn Texture>,
+}
+
+impl RoughDielectric {
+    pub fn new(index_of_refraction: f32, roughness: f32) -> Self {
+        Self {
+            index_of_refraction,
+            roughness: Arc::new(Color(roughness, roughness, roughness)),
+        }
+    }
+
+    pub fn with_roughness(mut self, roughness: Arc<dyn Texture>) -> Self {
+        self.roughness = roughness;
+        self
+    }
+
+    // Index of refraction on the other side over the one on the side of
+    // the normal, which faces the incoming ray
+    fn eta(&self, hit: &Hit) -> f32 {
+        if hit.front_face() {
+            self.index_of_refraction
+        } else {
+            1.0 / self.index_of_refraction
+        }
+    }
+}
+
+// wo refracted through a facet with normal m, None under total internal
+// reflection
+fn refract_through(wo: Vec3, m: Vec3, eta: f32) -> Option<Vec3> {
+    let cos_o = Vec3::dot(wo, m);
+    let sin2_t = (1.0 - cos_o * cos_o) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some((cos_o / eta - cos_t) * m - (1.0 / eta) * wo)
+}
+
+// The facet normal that refracts wo into wi, on the side of wo, and the
+// change of variables from it to wi
+fn refraction_half_vector(wo: Vec3, wi: Vec3, eta: f32) -> Option<(Vec3, f32)> {
+    let half = wo + eta * wi;
+    if half.len_squared() < 1e-12 {
+        return None;
+    }
+    let m = half.normalize();
+    let m = if m.z() < 0.0 { -m } else { m };
+    let (cos_o, cos_i) = (Vec3::dot(wo, m), Vec3::dot(wi, m));
+    if cos_o <= 0.0 || cos_i >= 0.0 {
+        return None;
+    }
+    let denominator = cos_o + eta * cos_i;
+    Some((m, eta * eta * -cos_i / (denominator * denominator)))
+}
+
+impl Scatterable for RoughDielectric {
+    fn scatter(&self, r_in: &Ray, hit: &Hit, rng: &mut dyn RngCore) -> Option<(Color, Ray)> {
+        let eta = self.eta(hit);
+        let frame = Frame::new(hit.n());
+        let wo = frame.to_local(-r_in.dir);
+        let ggx = Ggx::from_roughness(scalar_at(&*self.roughness, hit));
+        let m = match ggx {
+            Some(ggx) => ggx.sample_visible(wo, rng.gen(), rng.gen()),
+            None => Vec3(0.0, 0.0, 1.0),
+        };
+        let cos_m = Vec3::dot(wo, m);
+        let reflected = rng.gen::<f32>() < dielectric_fresnel(cos_m, eta);
+        let wi = if reflected {
+            2.0 * cos_m * m - wo
+        } else {
+            refract_through(wo, m, eta)?
+        };
+        // Facets near the horizon can send light to the wrong side
+        if (wi.z() > 0.0) != reflected {
+            return None;
+        }
+        let weight = match ggx {
+            Some(ggx) => ggx.g2(wo, wi) / ggx.g1(wo),
+            None => 1.0,
+        };
+        let direction = frame.to_world(wi).normalize();
+        Some((
+            Color(weight, weight, weight),
+            Ray::new(hit.p(), direction).with_time(r_in.time),
+        ))
+    }
+
+    fn eval(&self, hit: &Hit, wi: Vec3, wo: Vec3) -> Color {
+        let ggx = match Ggx::from_roughness(scalar_at(&*self.roughness, hit)) {
+            Some(ggx) => ggx,
+            None => return Color::zero(),
+        };
+        let eta = self.eta(hit);
+        let frame = Frame::new(hit.n());
+        let (wi, wo) = (frame.to_local(wi), frame.to_local(wo));
+        if wo.z() <= 0.0 {
+            return Color::zero();
+        }
+        let value = if wi.z() > 0.0 {
+            let m = (wi + wo).normalize();
+            let fresnel = dielectric_fresnel(Vec3::dot(wo, m), eta);
+            fresnel * ggx.d(m) * ggx.g2(wo, wi) / (4.0 * wo.z())
+        } else {
+            match refraction_half_vector(wo, wi, eta) {
+                Some((m, jacobian)) => {
+                    let cos_o = Vec3::dot(wo, m);
+                    let fresnel = dielectric_fresnel(cos_o, eta);
+                    (1.0 - fresnel) * ggx.d(m) * ggx.g2(wo, wi) * cos_o * jacobian / wo.z()
+                }
+                None => 0.0,
+            }
+        };
+        Color(value, value, value)
+    }
+
+    fn pdf(&self, hit: &Hit, wi: Vec3, wo: Vec3) -> f32 {
+        let ggx = match Ggx::from_roughness(scalar_at(&*self.roughness, hit)) {
+            Some(ggx) => ggx,
+            None => return 0.0,
+        };
+        let eta = self.eta(hit);
+        let frame = Frame::new(hit.n());
+        let (wi, wo) = (frame.to_local(wi), frame.to_local(wo));
+        if wi.z() > 0.0 {
+            let half = wi + wo;
+            if half.len_squared() < 1e-12 {
+                return 0.0;
+            }
+            let m = half.normalize();
+            let cos_o = Vec3::dot(wo, m);
+            let fresnel = dielectric_fresnel(cos_o, eta);
+            fresnel * ggx.visible_pdf(wo, m) / (4.0 * cos_o)
+        } else {
+            match refraction_half_vector(wo, wi, eta) {
+                Some((m, jacobian)) => {
+                    let fresnel = dielectric_fresnel(Vec3::dot(wo, m), eta);
+                    (1.0 - fresnel) * ggx.visible_pdf(wo, m) * jacobian
+                }
+                None => 0.0,
+            }
+        }
+    }
+}
+
+pub struct DiffuseLight {
+    emit: Arc<dyn Texture>,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        Self::textured(Arc::new(emit))
+    }
+
+    pub fn textured(emit: Arc<dyn Texture>) -> Self {
+        Self { emit }
+    }
+}
+
+impl Scatterable for DiffuseLight {
+    fn scatter(&self, _: &Ray, _: &Hit, _: &mut dyn RngCore) -> Option<(Color, Ray)> {
+        None
+    }
+
+    fn emitted(&self, hit: &Hit) -> Color {
+        texture_at(&*self.emit, hit)
+    }
+}
+
+// glTF style metallic-roughness surface: a mix of metal and a diffuse base
+// under a clear specular coat, both blurred by the roughness.
+// The base color is multiplied by the vertex color where the mesh has one.
+pub struct MetallicRoughness {
+    base_color: Arc<dyn Texture>,
+    metallic: f32,
+    roughness: Arc<dyn Texture>,
+    emissive: Arc<dyn Texture>,
+}
+
+impl MetallicRoughness {
+    pub fn new(base_color: Color, metallic: f32, roughness: f32, emissive: Color) -> Self {
+        Self::textured(
+            Arc::new(base_color),
+            metallic,
+            Arc::new(Color(roughness, roughness, roughness)),
+            Arc::new(emissive),
+        )
+    }
+
+    pub fn textured(
+        base_color: Arc<dyn Texture>,
+        metallic: f32,
+        roughness: Arc<dyn Texture>,
+        emissive: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            base_color,
+            metallic: metallic.clamp(0.0, 1.0),
+            roughness,
+            emissive,
+        }
+    }
+
+    fn base_color(&self, hit: &Hit) -> Color {
+        let base_color = texture_at(&*self.base_color, hit);
+        match hit.color() {
+            Some(color) => base_color * color,
+            None => base_color,
+        }
+    }
+
+    // Roughness is perceptual, the blur grows with its square
+    fn fuzz(&self, hit: &Hit) -> f32 {
+        let roughness = scalar_at(&*self.roughness, hit).clamp(0.0, 1.0);
+        roughness * roughness
+    }
+
+    // Probability of picking each lobe times its pdf of picking wi:
+    // the metal, the clear coat and the diffuse base. Without roughness
+    // the metal and the coat are mirrors, which only scatter finds, and
+    // the mirror direction itself is left out so its rays count as such.
+    fn lobes(&self, hit: &Hit, wi: Vec3, wo: Vec3) -> Option<(f32, f32, f32)> {
+        let fuzz = self.fuzz(hit);
+        let cos_theta = f32::min(Vec3::dot(wo, hit.n()), 1.0);
+        if cos_theta <= 0.0 {
+            return None;
+        }
+        let reflected = reflect(-wo, hit.n());
+        let glossy = if fuzz > 0.0 {
+            fuzzed_pdf(reflected, fuzz, wi)
+        } else if Vec3::dot(wi, reflected) > 1.0 - 1e-5 {
+            return None;
+        } else {
+            0.0
+        };
+        let coat = (1.0 - self.metallic) * reflectance(cos_theta, 1.5);
+        let diffuse = (1.0 - self.metallic - coat) * f32::max(Vec3::dot(wi, hit.n()), 0.0) / PI;
+        Some((self.metallic * glossy, coat * glossy, diffuse))
+    }
+}
+
+impl Scatterable for MetallicRoughness {
+    fn scatter(&self, r_in: &Ray, hit: &Hit, rng: &mut dyn RngCore) -> Option<(Color, Ray)> {
+        if Vec3::dot(r_in.dir, hit.n()) > 0.0 {
+            return None;
+        }
+        let base_color = self.base_color(hit);
+        let cos_theta = f32::min(Vec3::dot(-r_in.dir, hit.n()), 1.0);
+        let fuzz = self.fuzz(hit);
+
+        // Dielectrics reflect about 4% head-on, like an index of refraction of 1.5
+        let specular = if rng.gen::<f32>() < self.metallic {
+            Some(base_color)
+        } else if rng.gen::<f32>() < reflectance(cos_theta, 1.5) {
+            Some(Color(1.0, 1.0, 1.0))
+        } else {
+            None
+        };
+        let (attenuation, direction) = match specular {
+            Some(tint) => {
+                let reflected = reflect(r_in.dir, hit.n());
+                (tint, reflected + fuzz * Vec3::from(uniform_in_unit_sphere(rng)))
+            }
+            None => (base_color, hit.n() + uniform_on_unit_sphere(rng).into()),
+        };
+        if direction.len() < 1e-7 {
+            return Some((attenuation, Ray::new(hit.p(), hit.n()).with_time(r_in.time)));
+        }
+        let scattered = Ray::new(hit.p(), direction.normalize()).with_time(r_in.time);
+        if Vec3::dot(scattered.dir, hit.n()) <= 0.0 {
+            return None;
+        }
+        Some((attenuation, scattered))
+    }
+
+    fn emitted(&self, hit: &Hit) -> Color {
+        texture_at(&*self.emissive, hit)
+    }
+
+    // The mix of the lobes scatter picks from
+    fn eval(&self, hit: &Hit, wi: Vec3, wo: Vec3) -> Color {
+        if Vec3::dot(wi, hit.n()) <= 0.0 {
+            return Color::zero();
+        }
+        match self.lobes(hit, wi, wo) {
+            Some((metal, coat, diffuse)) => {
+                let base_color = self.base_color(hit);
+                metal * base_color + coat * Color(1.0, 1.0, 1.0) + diffuse * base_color
+            }
+            None => Color::zero(),
+        }
+    }
+
+    fn pdf(&self, hit: &Hit, wi: Vec3, wo: Vec3) -> f32 {
+        match self.lobes(hit, wi, wo) {
+            Some((metal, coat, diffuse)) => metal + coat + diffuse,
+            None => 0.0,
+        }
+    }
+}
+
+// Tilts the shading normal of another material along the slope of a
+// height map. The slope is measured in space rather than in texture
+// coordinates, so solid textures like noise suit it best.
+pub struct Bump {
+    material: Arc<dyn Scatterable>,
+    height: Arc<dyn Texture>,
+    // Height of the map's white in world units
+    strength: f32,
+}
+
+impl Bump {
+    pub fn new(material: Arc<dyn Scatterable>, height: Arc<dyn Texture>, strength: f32) -> Self {
+        Self {
+            material,
+            height,
+            strength,
+        }
+    }
+
+    fn bumped(&self, hit: &Hit) -> Hit {
+        const DELTA: f32 = 1e-3;
+        let (u, v) = hit.uv();
+        let height = |p| self.height.value(u, v, p).luminance();
+        let h = height(hit.p());
+        let slope = |d: Vec3| (height(hit.p() + DELTA * d) - h) / DELTA;
+        let gradient = Vec3(
+            slope(Vec3(1.0, 0.0, 0.0)),
+            slope(Vec3(0.0, 1.0, 0.0)),
+            slope(Vec3(0.0, 0.0, 1.0)),
+        );
+        let n = hit.n();
+        let tangential = gradient - Vec3::dot(gradient, n) * n;
+        hit.clone()
+            .with_normal((n - self.strength * tangential).normalize())
+    }
+}
+
+impl Scatterable for Bump {
+    fn scatter(&self, r_in: &Ray, hit: &Hit, rng: &mut dyn RngCore) -> Option<(Color, Ray)> {
+        self.material.scatter(r_in, &self.bumped(hit), rng)
+    }
+
+    fn emitted(&self, hit: &Hit) -> Color {
+        self.material.emitted(hit)
+    }
+
+    fn eval(&self, hit: &Hit, wi: Vec3, wo: Vec3) -> Color {
+        self.material.eval(&self.bumped(hit), wi, wo)
+    }
+
+    fn pdf(&self, hit: &Hit, wi: Vec3, wo: Vec3) -> f32 {
+        self.material.pdf(&self.bumped(hit), wi, wo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::point::Point3;
+    use crate::texture::Texture;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    // Rises by 1 per unit of x
+    struct Ramp;
+
+    impl Texture for Ramp {
+        fn value(&self, _u: f32, _v: f32, p: Point3) -> Color {
+            Color(p.x(), p.x(), p.x())
+        }
+    }
+
+    #[test]
+    fn bump_tilts_the_normal_against_the_slope() {
+        let mirror: Arc<dyn Scatterable> = Arc::new(Metal::new(Color(1.0, 1.0, 1.0), 0.0));
+        let bump = Bump::new(mirror, Arc::new(Ramp), 1.0);
+        let r = Ray::new(Point3(0.0, 1.0, 0.0), Vec3(0.0, -1.0, 0.0));
+        let hit = Hit::new(r.dir, Point3::zero(), Vec3(0.0, 1.0, 0.0), 1.0);
+        let bumped = bump.bumped(&hit);
+        let expected = Vec3(-1.0, 1.0, 0.0).normalize();
+        assert!(Vec3::almost_eq(bumped.n(), expected, 1e-2));
+        assert_eq!(bumped.p(), hit.p());
+
+        // A flat map leaves the mirror alone
+        let flat = Bump::new(
+            Arc::new(Metal::new(Color(1.0, 1.0, 1.0), 0.0)),
+            Arc::new(Color(0.5, 0.5, 0.5)),
+            1.0,
+        );
+        let mut rng = StdRng::seed_from_u64(0);
+        let (_, scattered) = flat.scatter(&r, &hit, &mut rng).unwrap();
+        assert!(Vec3::almost_eq(scattered.dir, Vec3(0.0, 1.0, 0.0), 1e-5));
+    }
+
+    #[test]
+    fn roughness_follows_the_texture() {
+        let r = Ray::new(Point3(1.0, 1.0, 0.0), Vec3(-1.0, -1.0, 0.0).normalize());
+        let at = |x| Hit::new(r.dir, Point3(x, 0.0, 0.0), Vec3(0.0, 1.0, 0.0), 1.0);
+        let metal = Metal::textured(Arc::new(Color(1.0, 1.0, 1.0)), Arc::new(Ramp));
+        let mut rng = StdRng::seed_from_u64(0);
+        let reflected = Vec3(-1.0, 1.0, 0.0).normalize();
+        for _ in 0..100 {
+            let (_, sharp) = metal.scatter(&r, &at(0.0), &mut rng).unwrap();
+            assert!(Vec3::almost_eq(sharp.dir, reflected, 1e-5));
+        }
+        let blurred = (0..100)
+            .filter_map(|_| metal.scatter(&r, &at(0.5), &mut rng))
+            .any(|(_, s)| !Vec3::almost_eq(s.dir, reflected, 1e-2));
+        assert!(blurred);
+    }
+
+    #[test]
+    fn eval_and_pdf_agree_with_scatter() {
+        let r = Ray::new(Point3(1.0, 1.0, 0.0), Vec3(-1.0, -0.5, 0.0).normalize());
+        let hit = Hit::new(r.dir, Point3::zero(), Vec3(0.0, 1.0, 0.0), 1.0);
+        let materials: Vec<Box<dyn Scatterable>> = vec![
+            Box::new(Lambertian::new(Color(0.6, 0.6, 0.6))),
+            Box::new(Metal::new(Color(0.9, 0.9, 0.9), 0.4)),
+            Box::new(MetallicRoughness::new(
+                Color(0.5, 0.5, 0.5),
+                0.3,
+                0.6,
+                Color::zero(),
+            )),
+            Box::new(RoughConductor::gold(0.6)),
+        ];
+        let mut rng = StdRng::seed_from_u64(3);
+        let n = 200_000;
+        for material in materials.iter() {
+            // Integrating over the sphere with uniform directions
+            let (mut pdf, mut albedo) = (0.0, 0.0);
+            for _ in 0..n {
+                let wi = Vec3::from(uniform_on_unit_sphere(&mut rng));
+                pdf += 4.0 * PI * material.pdf(&hit, wi, -r.dir) / n as f32;
+                albedo += 4.0 * PI * material.eval(&hit, wi, -r.dir).luminance() / n as f32;
+            }
+            assert!((pdf - 1.0).abs() < 0.02, "{}", pdf);
+
+            // The fraction of the light scatter carries on average
+            let scattered = (0..n)
+                .filter_map(|_| material.scatter(&r, &hit, &mut rng))
+                .map(|(attenuation, _)| attenuation.luminance())
+                .sum::<f32>()
+                / n as f32;
+            assert!(
+                (albedo - scattered).abs() < 0.02,
+                "{} {}",
+                albedo,
+                scattered
+            );
+        }
+
+        // Rough glass spreads its pdf over both sides of the surface, and
+        // scatter drops the samples whose facet sends them nowhere, so the
+        // pdf integrates to the fraction of samples it keeps
+        let glass = RoughDielectric::new(1.5, 0.6);
+        let (mut pdf, mut albedo) = (0.0, 0.0);
+        for _ in 0..n {
+            let wi = Vec3::from(uniform_on_unit_sphere(&mut rng));
+            let eval = glass.eval(&hit, wi, -r.dir).luminance();
+            if eval > 0.0 {
+                pdf += 4.0 * PI * glass.pdf(&hit, wi, -r.dir) / n as f32;
+            }
+            albedo += 4.0 * PI * eval / n as f32;
+        }
+        let samples: Vec<Color> = (0..n)
+            .filter_map(|_| glass.scatter(&r, &hit, &mut rng))
+            .map(|(attenuation, _)| attenuation)
+            .collect();
+        let kept = samples.len() as f32 / n as f32;
+        assert!((pdf - kept).abs() < 0.02, "{} {}", pdf, kept);
+        let scattered = samples.iter().map(|c| c.luminance()).sum::<f32>() / n as f32;
+        assert!(
+            (albedo - scattered).abs() < 0.02,
+            "{} {}",
+            albedo,
+            scattered
+        );
+
+        // A mirror cannot be hit by a direction picked elsewhere
+        let mirror = Metal::new(Color(1.0, 1.0, 1.0), 0.0);
+        let reflected = reflect(r.dir, hit.n());
+        assert_eq!(mirror.pdf(&hit, reflected, -r.dir), 0.0);
+        assert_eq!(mirror.eval(&hit, reflected, -r.dir), Color::zero());
+    }
+
+    #[test]
+    fn smooth_metallic_roughness_keeps_its_diffuse_base() {
+        let r = Ray::new(Point3(1.0, 1.0, 0.0), Vec3(-1.0, -1.0, 0.0).normalize());
+        let hit = Hit::new(r.dir, Point3::zero(), Vec3(0.0, 1.0, 0.0), 1.0);
+        let plastic = MetallicRoughness::new(Color(0.5, 0.5, 0.5), 0.0, 0.0, Color::zero());
+        let up = Vec3(0.0, 1.0, 0.0);
+        let f = reflectance(FRAC_1_SQRT_2, 1.5);
+        assert!((plastic.pdf(&hit, up, -r.dir) - (1.0 - f) / PI).abs() < 1e-5);
+        assert!((plastic.eval(&hit, up, -r.dir).r() - 0.5 * (1.0 - f) / PI).abs() < 1e-5);
+        // The coat is a mirror, rays scattered along it are not weighted
+        // against light samples
+        let reflected = reflect(r.dir, hit.n());
+        assert_eq!(plastic.pdf(&hit, reflected, -r.dir), 0.0);
+
+        let chrome = MetallicRoughness::new(Color(0.9, 0.9, 0.9), 1.0, 0.0, Color::zero());
+        assert_eq!(chrome.pdf(&hit, up, -r.dir), 0.0);
+    }
+
+    #[test]
+    fn conductor_fresnel_of_metals() {
+        // Head-on it is the reflectance of the complex index
+        let (eta, k) = (0.2, 3.9);
+        let f0 = ((eta - 1.0f32).powi(2) + k * k) / ((eta + 1.0f32).powi(2) + k * k);
+        assert!((conductor_fresnel(1.0, eta, k) - f0).abs() < 1e-5);
+        assert!((conductor_fresnel(0.0, eta, k) - 1.0).abs() < 1e-5);
+        assert!(conductor_fresnel(0.5, eta, k) > 0.0 && conductor_fresnel(0.5, eta, k) < 1.0);
+
+        let gold = RoughConductor::gold(0.0).fresnel(1.0);
+        assert!(gold.r() > gold.g() && gold.g() > gold.b());
+        let copper = RoughConductor::copper(0.0).fresnel(1.0);
+        assert!(copper.r() > copper.b());
+        let silver = RoughConductor::silver(0.0).fresnel(1.0);
+        let aluminium = RoughConductor::aluminium(0.0).fresnel(1.0);
+        for c in [silver, aluminium].iter() {
+            assert!(c.luminance() > 0.9 && (c.r() - c.b()).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn rough_conductor_conserves_energy() {
+        let r = Ray::new(Point3(0.0, 1.0, 0.0), Vec3(0.0, -1.0, 0.0));
+        let hit = Hit::new(r.dir, Point3::zero(), Vec3(0.0, 1.0, 0.0), 1.0);
+        let mut rng = StdRng::seed_from_u64(4);
+        // A metal that reflects everything, eta 0, only loses what bounces
+        // between the facets, more of it the rougher it is
+        let mut previous = 1.0;
+        for &roughness in [0.0, 0.3, 0.6].iter() {
+            let mirror = RoughConductor::new(Color::zero(), Color(1.0, 1.0, 1.0), roughness);
+            let n = 20000;
+            let albedo = (0..n)
+                .filter_map(|_| mirror.scatter(&r, &hit, &mut rng))
+                .map(|(attenuation, _)| attenuation.luminance())
+                .sum::<f32>()
+                / n as f32;
+            assert!(albedo <= previous + 1e-3 && albedo > 0.8, "{}", albedo);
+            previous = albedo;
+        }
+
+        // Smooth it is a mirror tinted by the Fresnel term
+        let gold = RoughConductor::gold(0.0);
+        let (attenuation, scattered) = gold.scatter(&r, &hit, &mut rng).unwrap();
+        assert!(Vec3::almost_eq(scattered.dir, Vec3(0.0, 1.0, 0.0), 1e-6));
+        assert_eq!(attenuation, gold.fresnel(1.0));
+        assert_eq!(gold.pdf(&hit, scattered.dir, -r.dir), 0.0);
+    }
+
+    #[test]
+    fn rough_dielectric_white_furnace() {
+        // Whatever is not reflected is refracted, only the light that would
+        // bounce between the facets is lost, from inside the glass too
+        let mut rng = StdRng::seed_from_u64(5);
+        for &roughness in [0.0, 0.3, 0.6].iter() {
+            let glass = RoughDielectric::new(1.5, roughness);
+            for &(x, y) in [(0.0, -1.0), (0.6, -0.8), (0.99, -0.141)].iter() {
+                for &outward in [1.0, -1.0].iter() {
+                    let r = Ray::new(Point3(0.0, 1.0, 0.0), Vec3(x, y, 0.0).normalize());
+                    let hit = Hit::new(r.dir, Point3::zero(), Vec3(0.0, outward, 0.0), 1.0);
+                    let n = 20000;
+                    let albedo = (0..n)
+                        .filter_map(|_| glass.scatter(&r, &hit, &mut rng))
+                        .map(|(attenuation, _)| attenuation.luminance())
+                        .sum::<f32>()
+                        / n as f32;
+                    assert!(albedo <= 1.0 + 1e-5, "{}", albedo);
+                    if roughness == 0.0 {
+                        assert!((albedo - 1.0).abs() < 1e-5, "{}", albedo);
+                    } else if roughness == 0.3 && x < 0.9 {
+                        assert!(albedo > 0.97, "{}", albedo);
+                    }
+                }
+            }
+        }
+
+        // The exact Fresnel equations at normal incidence and beyond the
+        // critical angle
+        assert!((dielectric_fresnel(1.0, 1.5) - 0.04).abs() < 1e-6);
+        assert_eq!(dielectric_fresnel(0.5, 1.0 / 1.5), 1.0);
+        assert!(dielectric_fresnel(0.1, 1.5) > dielectric_fresnel(0.9, 1.5));
     }
 }
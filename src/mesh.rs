@@ -0,0 +1,341 @@
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::color::Color;
+use crate::hittable::{Hit, Hittable};
+use crate::material::Scatterable;
+use crate::point::Point3;
+use crate::ray::Ray;
+use crate::triangle::{interpolate_uv, intersect_triangle, shading_normal, triangle_bounding_box};
+use crate::vector::{Cross, Normalize, Vec3};
+use std::sync::Arc;
+
+// Indexed triangle mesh with shared vertex buffers and its own BVH over the faces.
+// Normals, UVs and colors are optional, but if present there is one per vertex.
+pub struct TriangleMesh {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f32, f32)>,
+    colors: Vec<Color>,
+    indices: Vec<[u32; 3]>,
+    materials: Vec<Arc<dyn Scatterable>>,
+    // Index into materials for every face, empty if all faces share materials[0]
+    face_materials: Vec<u32>,
+    bvh: Bvh,
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Point3>,
+        indices: Vec<[u32; 3]>,
+        material: Arc<dyn Scatterable>,
+    ) -> TriangleMesh {
+        // The loaders report meshes without faces as errors before this
+        assert!(
+            !indices.is_empty(),
+            "a TriangleMesh needs at least one face"
+        );
+        assert!(
+            indices
+                .iter()
+                .flatten()
+                .all(|&i| (i as usize) < positions.len()),
+            "vertex index out of range"
+        );
+        let bounds: Vec<Aabb> = indices
+            .iter()
+            .map(|&face| triangle_bounding_box(&Self::face_positions(&positions, face)))
+            .collect();
+        let bvh = Bvh::build(&bounds);
+        TriangleMesh {
+            positions,
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            colors: Vec::new(),
+            indices,
+            materials: vec![material],
+            face_materials: Vec::new(),
+            bvh,
+        }
+    }
+
+    pub fn with_normals(mut self, normals: Vec<Vec3>) -> TriangleMesh {
+        assert_eq!(normals.len(), self.positions.len(), "one normal per vertex");
+        self.normals = normals;
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: Vec<(f32, f32)>) -> TriangleMesh {
+        assert_eq!(uvs.len(), self.positions.len(), "one uv per vertex");
+        self.uvs = uvs;
+        self
+    }
+
+    // Linear colors, interpolated into Hit::color
+    pub fn with_colors(mut self, colors: Vec<Color>) -> TriangleMesh {
+        assert_eq!(colors.len(), self.positions.len(), "one color per vertex");
+        self.colors = colors;
+        self
+    }
+
+    // Replaces the single material with one material per face
+    pub fn with_face_materials(
+        mut self,
+        materials: Vec<Arc<dyn Scatterable>>,
+        face_materials: Vec<u32>,
+    ) -> TriangleMesh {
+        assert_eq!(
+            face_materials.len(),
+            self.indices.len(),
+            "one material per face"
+        );
+        assert!(
+            face_materials
+                .iter()
+                .all(|&m| (m as usize) < materials.len()),
+            "material index out of range"
+        );
+        self.materials = materials;
+        self.face_materials = face_materials;
+        self
+    }
+
+    pub fn positions(&self) -> &[Point3] {
+        &self.positions
+    }
+
+    pub fn normals(&self) -> &[Vec3] {
+        &self.normals
+    }
+
+    pub fn uvs(&self) -> &[(f32, f32)] {
+        &self.uvs
+    }
+
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    pub fn indices(&self) -> &[[u32; 3]] {
+        &self.indices
+    }
+
+    // Number of triangles
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    fn face_positions(positions: &[Point3], [a, b, c]: [u32; 3]) -> [Point3; 3] {
+        [
+            positions[a as usize],
+            positions[b as usize],
+            positions[c as usize],
+        ]
+    }
+
+    fn face_hit(&self, face: usize, r: &Ray, t: f32, b1: f32, b2: f32) -> Hit {
+        let [a, b, c] = self.indices[face];
+        let [p0, p1, p2] = Self::face_positions(&self.positions, self.indices[face]);
+        let face_normal = Vec3::cross(p1 - p0, p2 - p0).normalize();
+        let normals = if self.normals.is_empty() {
+            None
+        } else {
+            Some([
+                self.normals[a as usize],
+                self.normals[b as usize],
+                self.normals[c as usize],
+            ])
+        };
+        let n = shading_normal(face_normal, normals, b1, b2);
+        let (u, v) = if self.uvs.is_empty() {
+            (b1, b2)
+        } else {
+            let uvs = [
+                self.uvs[a as usize],
+                self.uvs[b as usize],
+                self.uvs[c as usize],
+            ];
+            interpolate_uv(uvs, b1, b2)
+        };
+        let hit = Hit::new(r.dir, r.at(t), n, t)
+            .with_uv(u, v)
+            .with_barycentric(b1, b2);
+        if self.colors.is_empty() {
+            return hit;
+        }
+        let [c0, c1, c2] = [
+            self.colors[a as usize],
+            self.colors[b as usize],
+            self.colors[c as usize],
+        ];
+        hit.with_color((1.0 - b1 - b2) * c0 + b1 * c1 + b2 * c2)
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(Hit, &dyn Scatterable)> {
+        // Only the closest face is turned into a Hit
+        let mut closest: Option<(usize, f32, f32, f32)> = None;
+        self.bvh.traverse(r, t_min, t_max, |face, t_closest| {
+            let vertices = Self::face_positions(&self.positions, self.indices[face]);
+            let (t, b1, b2) = intersect_triangle(r, vertices, t_min, t_closest)?;
+            closest = Some((face, t, b1, b2));
+            Some(t)
+        });
+
+        let (face, t, b1, b2) = closest?;
+        let material = match self.face_materials.get(face) {
+            Some(&m) => &self.materials[m as usize],
+            None => &self.materials[0],
+        };
+        Some((self.face_hit(face, r, t, b1, b2), &**material))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bvh
+            .bounding_box()
+            .expect("TriangleMesh::new rejects meshes without faces")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable_vec::HittableVec;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::triangle::Triangle;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn gray() -> Arc<dyn Scatterable> {
+        Arc::new(Lambertian::new(Color(0.5, 0.5, 0.5)))
+    }
+
+    // Unit square in the xy plane split into two triangles
+    fn quad() -> TriangleMesh {
+        TriangleMesh::new(
+            vec![
+                Point3(0.0, 0.0, 0.0),
+                Point3(1.0, 0.0, 0.0),
+                Point3(1.0, 1.0, 0.0),
+                Point3(0.0, 1.0, 0.0),
+            ],
+            vec![[0, 1, 2], [0, 2, 3]],
+            gray(),
+        )
+    }
+
+    fn down_z(x: f32, y: f32) -> Ray {
+        Ray::new(Point3(x, y, 1.0), Vec3(0.0, 0.0, -1.0))
+    }
+
+    #[test]
+    fn mesh_hits_both_faces() {
+        let mesh = quad();
+        assert_eq!(mesh.len(), 2);
+        assert_eq!(
+            mesh.bounding_box(),
+            Aabb::new(Point3::zero(), Point3(1.0, 1.0, 0.0))
+        );
+        for &(x, y) in &[(0.75, 0.25), (0.25, 0.75), (0.5, 0.5)] {
+            let (hit, _) = mesh.hit(&down_z(x, y), 0.0, f32::MAX).unwrap();
+            assert!((hit.t() - 1.0).abs() < 1e-6);
+            assert_eq!(hit.n(), Vec3(0.0, 0.0, 1.0));
+        }
+        assert!(mesh.hit(&down_z(1.5, 0.5), 0.0, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn mesh_interpolates_shared_vertex_attributes() {
+        let mesh = quad()
+            .with_uvs(vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)])
+            .with_normals(vec![Vec3(0.0, 0.0, 1.0); 4]);
+        let (hit, _) = mesh.hit(&down_z(0.25, 0.75), 0.0, f32::MAX).unwrap();
+        let (u, v) = hit.uv();
+        assert!((u - 0.25).abs() < 1e-6 && (v - 0.75).abs() < 1e-6);
+        assert_eq!(hit.color(), None);
+
+        let white = Color(1.0, 1.0, 1.0);
+        let mesh = quad().with_colors(vec![white, Color::zero(), white, Color::zero()]);
+        let (hit, _) = mesh.hit(&down_z(0.25, 0.75), 0.0, f32::MAX).unwrap();
+        let c = hit.color().unwrap();
+        assert!((c.r() - 0.5).abs() < 1e-6 && c.r() == c.g() && c.g() == c.b());
+    }
+
+    #[test]
+    fn mesh_per_face_materials() {
+        let red: Arc<dyn Scatterable> = Arc::new(DiffuseLight::new(Color(1.0, 0.0, 0.0)));
+        let blue: Arc<dyn Scatterable> = Arc::new(DiffuseLight::new(Color(0.0, 0.0, 1.0)));
+        let mesh = quad().with_face_materials(vec![red, blue], vec![1, 0]);
+        let (hit, mat) = mesh.hit(&down_z(0.75, 0.25), 0.0, f32::MAX).unwrap();
+        assert_eq!(mat.emitted(&hit), Color(0.0, 0.0, 1.0));
+        let (hit, mat) = mesh.hit(&down_z(0.25, 0.75), 0.0, f32::MAX).unwrap();
+        assert_eq!(mat.emitted(&hit), Color(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn mesh_matches_separate_triangles() {
+        // A bumpy 16x16 height field
+        let mut rng = StdRng::seed_from_u64(7);
+        let n = 16;
+        let mut positions = Vec::new();
+        for j in 0..=n {
+            for i in 0..=n {
+                let h: f32 = rng.gen_range(-0.2..0.2);
+                positions.push(Point3(i as f32 / n as f32, h, j as f32 / n as f32));
+            }
+        }
+        let mut indices = Vec::new();
+        for j in 0..n {
+            for i in 0..n {
+                let a = j * (n + 1) + i;
+                let (b, c, d) = (a + 1, a + n + 2, a + n + 1);
+                indices.push([a, b, c]);
+                indices.push([a, c, d]);
+            }
+        }
+        let mut triangles = HittableVec::new();
+        for &[a, b, c] in &indices {
+            let p = |i: u32| positions[i as usize];
+            triangles.push(Box::new(Triangle::new(p(a), p(b), p(c), gray())));
+        }
+        let mesh = TriangleMesh::new(positions, indices, gray());
+
+        for _ in 0..1000 {
+            let orig = Point3(
+                rng.gen_range(-0.5..1.5),
+                rng.gen_range(0.5..1.0),
+                rng.gen_range(-0.5..1.5),
+            );
+            let target = Point3(rng.gen(), 0.0, rng.gen());
+            let r = Ray::new(orig, (target - orig).normalize());
+            match (
+                mesh.hit(&r, 1e-3, f32::MAX),
+                triangles.hit(&r, 1e-3, f32::MAX),
+            ) {
+                (Some((a, _)), Some((b, _))) => {
+                    assert_eq!(a.t(), b.t());
+                    assert_eq!(a.barycentric(), b.barycentric());
+                    assert!(Vec3::almost_eq(a.n(), b.n(), 1e-6));
+                }
+                (None, None) => {}
+                _ => panic!("mesh and triangles disagree"),
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn mesh_rejects_out_of_range_indices() {
+        TriangleMesh::new(vec![Point3::zero(); 3], vec![[0, 1, 3]], gray());
+    }
+
+    #[test]
+    #[should_panic]
+    fn mesh_rejects_no_faces() {
+        TriangleMesh::new(vec![Point3::zero(); 3], Vec::new(), gray());
+    }
+}
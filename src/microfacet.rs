@@ -0,0 +1,184 @@
+// The GGX (Trowbridge-Reitz) distribution of microfacet normals with
+// Smith's shadowing-masking. Directions are in a shading frame where the
+// macro surface normal is +z.
+use crate::vector::{orthonormal_basis, Cross, Dot, Normalize, Vec3};
+use std::f32::consts::PI;
+
+// A shading frame around a unit normal
+#[derive(Clone, Copy, Debug)]
+pub struct Frame {
+    t: Vec3,
+    b: Vec3,
+    n: Vec3,
+}
+
+impl Frame {
+    pub fn new(n: Vec3) -> Frame {
+        let (t, b) = orthonormal_basis(n);
+        Frame { t, b, n }
+    }
+
+    pub fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3(
+            Vec3::dot(v, self.t),
+            Vec3::dot(v, self.b),
+            Vec3::dot(v, self.n),
+        )
+    }
+
+    pub fn to_world(&self, v: Vec3) -> Vec3 {
+        v.x() * self.t + v.y() * self.b + v.z() * self.n
+    }
+}
+
+// Below this the surface is a perfect mirror, the distribution becomes
+// too peaked for floating point
+const MIN_ALPHA: f32 = 1e-3;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ggx {
+    // Roughly the slope of the microfacets
+    alpha: f32,
+}
+
+impl Ggx {
+    pub fn new(alpha: f32) -> Ggx {
+        assert!(alpha > 0.0, "alpha must be positive");
+        Ggx { alpha }
+    }
+
+    // Roughness is perceptual, alpha grows with its square.
+    // None when the surface is smooth.
+    pub fn from_roughness(roughness: f32) -> Option<Ggx> {
+        let roughness = roughness.clamp(0.0, 1.0);
+        let alpha = roughness * roughness;
+        if alpha < MIN_ALPHA {
+            None
+        } else {
+            Some(Ggx::new(alpha))
+        }
+    }
+
+    // Density of microfacet normals per solid angle, weighted by the
+    // cosine to the macro normal it integrates to 1
+    pub fn d(&self, m: Vec3) -> f32 {
+        if m.z() <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha * self.alpha;
+        let t = m.z() * m.z() * (a2 - 1.0) + 1.0;
+        a2 / (PI * t * t)
+    }
+
+    // Smith's auxiliary function, the same on both sides of the surface
+    fn lambda(&self, w: Vec3) -> f32 {
+        let cos2 = w.z() * w.z();
+        if cos2 <= 0.0 {
+            return f32::INFINITY;
+        }
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        0.5 * ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0)
+    }
+
+    // Fraction of the microfacets seen from w that are not hidden
+    pub fn g1(&self, w: Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Fraction seen from both directions, with the height correlation
+    // that keeps facets high up visible from both
+    pub fn g2(&self, wo: Vec3, wi: Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // A normal picked among those visible from wo above the surface, with
+    // u1 and u2 uniform in [0, 1) (Heitz, Sampling the GGX Distribution of
+    // Visible Normals, 2018)
+    pub fn sample_visible(&self, wo: Vec3, u1: f32, u2: f32) -> Vec3 {
+        // Stretched so the distribution becomes a hemisphere
+        let v = Vec3(self.alpha * wo.x(), self.alpha * wo.y(), wo.z()).normalize();
+        let len2 = v.x() * v.x() + v.y() * v.y();
+        let t1 = if len2 > 0.0 {
+            (1.0 / len2.sqrt()) * Vec3(-v.y(), v.x(), 0.0)
+        } else {
+            Vec3(1.0, 0.0, 0.0)
+        };
+        let t2 = Vec3::cross(v, t1);
+
+        // A disk point, squeezed onto the part of the hemisphere facing v
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + v.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let h = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * v;
+
+        Vec3(self.alpha * h.x(), self.alpha * h.y(), h.z().max(0.0)).normalize()
+    }
+
+    // Solid angle pdf with which sample_visible picks m
+    pub fn visible_pdf(&self, wo: Vec3, m: Vec3) -> f32 {
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * Vec3::dot(wo, m).max(0.0) * self.d(m) / wo.z()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::uniform_on_unit_sphere;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn frame_round_trips() {
+        let n = Vec3(0.3, -0.5, 0.8).normalize();
+        let frame = Frame::new(n);
+        assert!(Vec3::almost_eq(
+            frame.to_local(n),
+            Vec3(0.0, 0.0, 1.0),
+            1e-6
+        ));
+        let v = Vec3(1.0, 2.0, -3.0);
+        assert!(Vec3::almost_eq(frame.to_world(frame.to_local(v)), v, 1e-5));
+    }
+
+    #[test]
+    fn ggx_densities_are_normalized() {
+        let ggx = Ggx::new(0.5);
+        let wo = Vec3(0.6, 0.0, 0.8);
+        let mut rng = StdRng::seed_from_u64(0);
+        let n = 400_000;
+        let (mut projected, mut visible, mut mean_z) = (0.0, 0.0, 0.0);
+        for _ in 0..n {
+            let m = Vec3::from(uniform_on_unit_sphere(&mut rng));
+            projected += 4.0 * PI * ggx.d(m) * m.z().max(0.0) / n as f32;
+            visible += 4.0 * PI * ggx.visible_pdf(wo, m) / n as f32;
+            mean_z += 4.0 * PI * ggx.visible_pdf(wo, m) * m.z() / n as f32;
+        }
+        assert!((projected - 1.0).abs() < 0.02, "{}", projected);
+        assert!((visible - 1.0).abs() < 0.02, "{}", visible);
+
+        // The sampled normals follow the visible pdf
+        let sampled = (0..n)
+            .map(|_| ggx.sample_visible(wo, rng.gen(), rng.gen()).z())
+            .sum::<f32>()
+            / n as f32;
+        assert!((sampled - mean_z).abs() < 0.01, "{} {}", sampled, mean_z);
+    }
+
+    #[test]
+    fn ggx_masking() {
+        let ggx = Ggx::new(0.3);
+        let up = Vec3(0.0, 0.0, 1.0);
+        assert_eq!(ggx.g1(up), 1.0);
+        assert_eq!(ggx.g1(Vec3(1.0, 0.0, 0.0)), 0.0);
+        let grazing = Vec3(0.99, 0.0, 0.1411).normalize();
+        assert!(ggx.g1(grazing) < ggx.g1(Vec3(0.6, 0.0, 0.8)));
+        assert!(ggx.g2(grazing, up) <= ggx.g1(grazing));
+        assert!(Ggx::from_roughness(0.0).is_none());
+        assert!(Ggx::from_roughness(0.5).is_some());
+    }
+}
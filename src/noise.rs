@@ -0,0 +1,277 @@
+// Procedural noise for textures. Every generator draws its tables from
+// a random number generator, so a scene seed gives repeatable patterns.
+use crate::point::Point3;
+use crate::vector::{Len, Vec3};
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+pub trait Noise: Send + Sync {
+    // Smooth pseudo random value in [-1, 1], varying over unit distances
+    fn noise(&self, p: Point3) -> f32;
+
+    // Fractional Brownian motion: octaves of doubling frequency and
+    // halving amplitude, normalized to stay in [-1, 1]
+    fn fbm(&self, p: Point3, octaves: u32) -> f32 {
+        sum_octaves(p, octaves, |q| self.noise(q))
+    }
+
+    // Like fbm over the absolute noise, in [0, 1]
+    fn turbulence(&self, p: Point3, octaves: u32) -> f32 {
+        sum_octaves(p, octaves, |q| self.noise(q).abs())
+    }
+}
+
+fn sum_octaves<F: Fn(Point3) -> f32>(p: Point3, octaves: u32, octave: F) -> f32 {
+    assert!(octaves > 0, "octaves must be positive");
+    let (mut sum, mut total) = (0.0, 0.0);
+    let (mut frequency, mut weight) = (1.0, 1.0);
+    for _ in 0..octaves {
+        sum += weight * octave(scaled(p, frequency));
+        total += weight;
+        frequency *= 2.0;
+        weight *= 0.5;
+    }
+    sum / total
+}
+
+pub(crate) fn scaled(p: Point3, s: f32) -> Point3 {
+    Point3(p.x() * s, p.y() * s, p.z() * s)
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f32, a: f32, b: f32) -> f32 {
+    a + t * (b - a)
+}
+
+// Hashes integer lattice points with a shuffled permutation of 0..256,
+// the pattern repeats every 256 units
+#[derive(Clone)]
+struct Lattice {
+    // Repeated so lookups need no wrapping
+    perm: [u8; 512],
+}
+
+impl Lattice {
+    fn new<R: Rng + ?Sized>(rng: &mut R) -> Lattice {
+        let mut p: Vec<u8> = (0..=255).collect();
+        p.shuffle(rng);
+        let mut perm = [0; 512];
+        for (i, value) in perm.iter_mut().enumerate() {
+            *value = p[i % 256];
+        }
+        Lattice { perm }
+    }
+
+    fn hash(&self, x: i64, y: i64, z: i64) -> usize {
+        let p = &self.perm;
+        let wrap = |i: i64| i.rem_euclid(256) as usize;
+        p[p[p[wrap(x)] as usize + wrap(y)] as usize + wrap(z)] as usize
+    }
+
+    // Blends a value from each corner of the cell around p
+    fn interpolate<F: Fn(usize, Vec3) -> f32>(&self, p: Point3, corner: F) -> f32 {
+        let (fx, fy, fz) = (p.x().floor(), p.y().floor(), p.z().floor());
+        let (x, y, z) = (p.x() - fx, p.y() - fy, p.z() - fz);
+        let (xi, yi, zi) = (fx as i64, fy as i64, fz as i64);
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+
+        let c = |dx: i64, dy: i64, dz: i64| {
+            let h = self.hash(xi + dx, yi + dy, zi + dz);
+            corner(h, Vec3(x - dx as f32, y - dy as f32, z - dz as f32))
+        };
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, c(0, 0, 0), c(1, 0, 0)),
+                lerp(u, c(0, 1, 0), c(1, 1, 0)),
+            ),
+            lerp(
+                v,
+                lerp(u, c(0, 0, 1), c(1, 0, 1)),
+                lerp(u, c(0, 1, 1), c(1, 1, 1)),
+            ),
+        )
+    }
+}
+
+// Ken Perlin's improved gradient noise, with a quintic fade and
+// gradients taken from the edges of a cube
+#[derive(Clone)]
+pub struct Perlin {
+    lattice: Lattice,
+}
+
+impl Perlin {
+    pub fn new<R: Rng + ?Sized>(rng: &mut R) -> Perlin {
+        Perlin {
+            lattice: Lattice::new(rng),
+        }
+    }
+}
+
+// Dot product with one of the 12 cube edge directions picked by the hash
+fn grad(hash: usize, d: Vec3) -> f32 {
+    let h = hash & 15;
+    let u = if h < 8 { d.x() } else { d.y() };
+    let v = if h < 4 {
+        d.y()
+    } else if h == 12 || h == 14 {
+        d.x()
+    } else {
+        d.z()
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+impl Noise for Perlin {
+    // 0 at every lattice point
+    fn noise(&self, p: Point3) -> f32 {
+        self.lattice.interpolate(p, grad)
+    }
+}
+
+// Random values at the lattice points, smoothly interpolated.
+// Blockier than Perlin noise, the lattice shows through.
+#[derive(Clone)]
+pub struct ValueNoise {
+    lattice: Lattice,
+    values: Vec<f32>,
+}
+
+impl ValueNoise {
+    pub fn new<R: Rng + ?Sized>(rng: &mut R) -> ValueNoise {
+        let lattice = Lattice::new(rng);
+        let values = (0..256).map(|_| rng.gen_range(-1.0..=1.0)).collect();
+        ValueNoise { lattice, values }
+    }
+}
+
+impl Noise for ValueNoise {
+    fn noise(&self, p: Point3) -> f32 {
+        self.lattice.interpolate(p, |h, _| self.values[h])
+    }
+}
+
+// Steven Worley's cellular noise: the distance to the nearest of
+// random feature points, one in every lattice cell
+#[derive(Clone)]
+pub struct Worley {
+    lattice: Lattice,
+    features: Vec<Vec3>,
+}
+
+impl Worley {
+    pub fn new<R: Rng + ?Sized>(rng: &mut R) -> Worley {
+        let lattice = Lattice::new(rng);
+        let features = (0..256)
+            .map(|_| Vec3(rng.gen(), rng.gen(), rng.gen()))
+            .collect();
+        Worley { lattice, features }
+    }
+
+    // Searches the 27 cells around p, which finds the nearest point
+    // unless it is unusually far
+    pub fn distance(&self, p: Point3) -> f32 {
+        let (fx, fy, fz) = (p.x().floor(), p.y().floor(), p.z().floor());
+        let (xi, yi, zi) = (fx as i64, fy as i64, fz as i64);
+        let d = Vec3(p.x() - fx, p.y() - fy, p.z() - fz);
+        let mut nearest = f32::INFINITY;
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let h = self.lattice.hash(xi + dx, yi + dy, zi + dz);
+                    let cell = Vec3(dx as f32, dy as f32, dz as f32);
+                    nearest = nearest.min((cell + self.features[h] - d).len_squared());
+                }
+            }
+        }
+        nearest.sqrt()
+    }
+}
+
+impl Noise for Worley {
+    // Distances beyond 1 are clamped, they are rare
+    fn noise(&self, p: Point3) -> f32 {
+        2.0 * self.distance(p).min(1.0) - 1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn random_points(n: usize) -> Vec<Point3> {
+        let mut rng = StdRng::seed_from_u64(2);
+        (0..n)
+            .map(|_| {
+                Point3(
+                    rng.gen_range(-300.0..300.0),
+                    rng.gen_range(-300.0..300.0),
+                    rng.gen_range(-300.0..300.0),
+                )
+            })
+            .collect()
+    }
+
+    fn generators(seed: u64) -> Vec<Box<dyn Noise>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        vec![
+            Box::new(Perlin::new(&mut rng)),
+            Box::new(ValueNoise::new(&mut rng)),
+            Box::new(Worley::new(&mut rng)),
+        ]
+    }
+
+    #[test]
+    fn noise_stays_in_range_and_is_smooth() {
+        for noise in generators(1) {
+            let (mut min, mut max) = (f32::INFINITY, f32::NEG_INFINITY);
+            for &p in random_points(20000).iter() {
+                let n = noise.noise(p);
+                assert!((-1.0..=1.0).contains(&n), "{}", n);
+                min = min.min(n);
+                max = max.max(n);
+                let step = noise.noise(Point3(p.x() + 1e-3, p.y(), p.z()));
+                assert!((step - n).abs() < 0.01);
+                assert!((-1.0..=1.0).contains(&noise.fbm(p, 6)));
+                assert!((0.0..=1.0).contains(&noise.turbulence(p, 6)));
+            }
+            // The range is used, not just a sliver of it
+            assert!(min < -0.5 && max > 0.5, "{} {}", min, max);
+        }
+    }
+
+    #[test]
+    fn noise_is_deterministic_per_seed() {
+        let (a, b, c) = (generators(7), generators(7), generators(8));
+        let points = random_points(100);
+        for i in 0..a.len() {
+            let values =
+                |g: &dyn Noise| -> Vec<f32> { points.iter().map(|&p| g.fbm(p, 4)).collect() };
+            assert_eq!(values(&*a[i]), values(&*b[i]));
+            assert_ne!(values(&*a[i]), values(&*c[i]));
+        }
+    }
+
+    #[test]
+    fn perlin_vanishes_on_the_lattice() {
+        let perlin = Perlin::new(&mut StdRng::seed_from_u64(1));
+        assert_eq!(perlin.noise(Point3(3.0, -7.0, 12.0)), 0.0);
+    }
+
+    #[test]
+    fn worley_is_zero_at_the_feature_points() {
+        let worley = Worley::new(&mut StdRng::seed_from_u64(3));
+        let h = worley.lattice.hash(4, -2, 9);
+        let f = worley.features[h];
+        let p = Point3(4.0 + f.x(), -2.0 + f.y(), 9.0 + f.z());
+        assert!(worley.distance(p) < 1e-5);
+        assert!(worley.noise(Point3(p.x() + 0.01, p.y(), p.z())) < -0.9);
+    }
+}
@@ -0,0 +1,559 @@
+// Wavefront OBJ meshes with their MTL material libraries
+use crate::color::Color;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal, Scatterable};
+use crate::mesh::TriangleMesh;
+use crate::parse::{parse_token, ParseError};
+use crate::point::Point3;
+use crate::vector::Vec3;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+// A named `g` or `o` section of the file
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjGroup {
+    pub name: String,
+    // Triangles of the mesh that belong to the group
+    pub faces: Range<usize>,
+}
+
+pub struct Obj {
+    pub mesh: TriangleMesh,
+    pub groups: Vec<ObjGroup>,
+}
+
+pub fn load_obj<P: AsRef<Path>>(
+    path: P,
+    default_material: Arc<dyn Scatterable>,
+) -> Result<Obj, ParseError> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| ParseError::io(path, e))?;
+    parse_obj(BufReader::new(file), path, default_material)
+}
+
+// Material libraries are looked up next to `path`.
+// Faces before the first `usemtl` get the default material.
+pub fn parse_obj<R: BufRead>(
+    r: R,
+    path: &Path,
+    default_material: Arc<dyn Scatterable>,
+) -> Result<Obj, ParseError> {
+    let mut positions = Vec::new();
+    let mut tex_coords = Vec::new();
+    let mut normals = Vec::new();
+
+    // OBJ indexes positions, uvs and normals separately,
+    // the mesh needs one index per distinct combination
+    let mut vertices: HashMap<(usize, Option<usize>, Option<usize>), u32> = HashMap::new();
+    let mut mesh_positions = Vec::new();
+    let mut mesh_uvs = Vec::new();
+    let mut mesh_normals = Vec::new();
+    let (mut has_uvs, mut has_normals) = (false, false);
+
+    let mut indices = Vec::new();
+    let mut face_materials = Vec::new();
+    let mut materials = vec![default_material];
+    let mut material_indices: HashMap<String, u32> = HashMap::new();
+    let mut library: HashMap<String, Arc<dyn Scatterable>> = HashMap::new();
+    let mut current_material = 0;
+
+    let mut groups = Vec::new();
+    let mut group_name = String::from("default");
+    let mut group_start = 0;
+
+    for (number, line) in r.lines().enumerate() {
+        let number = number + 1;
+        let line = line.map_err(|e| ParseError::io(path, e))?;
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let mut float = |what| parse_token::<f32>(tokens.next(), what, path, number);
+        match keyword {
+            "v" => positions.push(Point3(float("x")?, float("y")?, float("z")?)),
+            "vt" => {
+                let u = float("u")?;
+                // v is optional, w is ignored
+                let v = match tokens.next() {
+                    Some(v) => parse_token(Some(v), "v", path, number)?,
+                    None => 0.0,
+                };
+                tex_coords.push((u, v));
+            }
+            "vn" => normals.push(Vec3(float("x")?, float("y")?, float("z")?)),
+            "f" => {
+                let mut face = Vec::new();
+                for token in tokens {
+                    let key = parse_face_vertex(
+                        token,
+                        (positions.len(), tex_coords.len(), normals.len()),
+                        path,
+                        number,
+                    )?;
+                    let index = *vertices.entry(key).or_insert_with(|| {
+                        let (p, t, n) = key;
+                        mesh_positions.push(positions[p]);
+                        mesh_uvs.push(t.map_or((0.0, 0.0), |t| tex_coords[t]));
+                        // Zero normals fall back to the face normal
+                        mesh_normals.push(n.map_or(Vec3::zero(), |n| normals[n]));
+                        has_uvs |= t.is_some();
+                        has_normals |= n.is_some();
+                        mesh_positions.len() as u32 - 1
+                    });
+                    face.push(index);
+                }
+                if face.len() < 3 {
+                    return Err(ParseError::new(
+                        path,
+                        number,
+                        "a face needs at least 3 vertices",
+                    ));
+                }
+                // Fan triangulation, fine for the convex polygons modellers export
+                for i in 1..face.len() - 1 {
+                    indices.push([face[0], face[i], face[i + 1]]);
+                    face_materials.push(current_material);
+                }
+            }
+            "g" | "o" => {
+                if indices.len() > group_start {
+                    groups.push(ObjGroup {
+                        name: group_name,
+                        faces: group_start..indices.len(),
+                    });
+                }
+                group_name = tokens.collect::<Vec<_>>().join(" ");
+                group_start = indices.len();
+            }
+            "mtllib" => {
+                let dir = path.parent().unwrap_or_else(|| Path::new(""));
+                for name in tokens {
+                    let mtl_path = dir.join(name);
+                    let file = File::open(&mtl_path).map_err(|e| {
+                        ParseError::new(path, number, format!("cannot open {}: {}", name, e))
+                    })?;
+                    library.extend(parse_mtl(BufReader::new(file), &mtl_path)?);
+                }
+            }
+            "usemtl" => {
+                let name = tokens.collect::<Vec<_>>().join(" ");
+                current_material = match material_indices.get(&name) {
+                    Some(&index) => index,
+                    None => {
+                        let material = library.get(&name).ok_or_else(|| {
+                            ParseError::new(path, number, format!("unknown material '{}'", name))
+                        })?;
+                        materials.push(material.clone());
+                        let index = materials.len() as u32 - 1;
+                        material_indices.insert(name, index);
+                        index
+                    }
+                };
+            }
+            // Smoothing groups, lines, points and the rest do not affect triangles
+            _ => {}
+        }
+    }
+
+    if indices.is_empty() {
+        return Err(ParseError::new(path, 0, "no faces"));
+    }
+    if indices.len() > group_start {
+        groups.push(ObjGroup {
+            name: group_name,
+            faces: group_start..indices.len(),
+        });
+    }
+
+    let mut mesh = TriangleMesh::new(mesh_positions, indices, materials[0].clone());
+    if has_normals {
+        mesh = mesh.with_normals(mesh_normals);
+    }
+    if has_uvs {
+        mesh = mesh.with_uvs(mesh_uvs);
+    }
+    if materials.len() > 1 {
+        mesh = mesh.with_face_materials(materials, face_materials);
+    }
+    Ok(Obj { mesh, groups })
+}
+
+// Resolves `v`, `v/vt`, `v//vn` or `v/vt/vn` to zero-based indices.
+// counts are the numbers of positions, uvs and normals read so far.
+fn parse_face_vertex(
+    token: &str,
+    counts: (usize, usize, usize),
+    path: &Path,
+    line: usize,
+) -> Result<(usize, Option<usize>, Option<usize>), ParseError> {
+    let mut parts = token.split('/');
+    let mut index = |what: &str, count: usize| -> Result<Option<usize>, ParseError> {
+        let part = match parts.next() {
+            Some(part) if !part.is_empty() => part,
+            _ => return Ok(None),
+        };
+        let index: i64 = parse_token(Some(part), what, path, line)?;
+        // Negative indices count back from the last element read
+        let resolved = if index > 0 {
+            index - 1
+        } else {
+            count as i64 + index
+        };
+        if index == 0 || resolved < 0 || resolved >= count as i64 {
+            return Err(ParseError::new(
+                path,
+                line,
+                format!("{} index {} out of range", what, index),
+            ));
+        }
+        Ok(Some(resolved as usize))
+    };
+    let p = index("vertex", counts.0)?
+        .ok_or_else(|| ParseError::new(path, line, format!("invalid face vertex '{}'", token)))?;
+    let t = index("texture coordinate", counts.1)?;
+    let n = index("normal", counts.2)?;
+    Ok((p, t, n))
+}
+
+#[derive(Clone, Debug)]
+struct MtlEntry {
+    kd: Color,
+    ks: Color,
+    ke: Color,
+    ns: f32,
+    ni: f32,
+    d: f32,
+    illum: u32,
+}
+
+impl Default for MtlEntry {
+    fn default() -> Self {
+        MtlEntry {
+            kd: Color(0.8, 0.8, 0.8),
+            ks: Color::zero(),
+            ke: Color::zero(),
+            ns: 0.0,
+            ni: 1.0,
+            d: 1.0,
+            illum: 2,
+        }
+    }
+}
+
+impl MtlEntry {
+    // Picks the closest of the renderer's materials
+    fn to_material(&self) -> Arc<dyn Scatterable> {
+        let max = |c: Color| c.r().max(c.g()).max(c.b());
+        if max(self.ke) > 0.0 {
+            Arc::new(DiffuseLight::new(self.ke))
+        } else if self.d < 1.0 || [4, 6, 7, 9].contains(&self.illum) {
+            // Exporters often leave Ni at 1, which would make glass invisible
+            let ior = if self.ni > 1.0 { self.ni } else { 1.5 };
+            Arc::new(Dielectric::new(ior))
+        } else if self.illum == 3 || max(self.ks) > max(self.kd) {
+            // Sharper Phong highlights become smoother metal
+            let fuzz = f32::sqrt(2.0 / (self.ns.max(0.0) + 2.0));
+            Arc::new(Metal::new(self.ks, fuzz))
+        } else {
+            Arc::new(Lambertian::new(self.kd))
+        }
+    }
+}
+
+pub fn parse_mtl<R: BufRead>(
+    r: R,
+    path: &Path,
+) -> Result<HashMap<String, Arc<dyn Scatterable>>, ParseError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlEntry)> = None;
+    for (number, line) in r.lines().enumerate() {
+        let number = number + 1;
+        let line = line.map_err(|e| ParseError::io(path, e))?;
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        if keyword == "newmtl" {
+            if let Some((name, entry)) = current.take() {
+                materials.insert(name, entry.to_material());
+            }
+            let name = tokens.collect::<Vec<_>>().join(" ");
+            if name.is_empty() {
+                return Err(ParseError::new(path, number, "missing material name"));
+            }
+            current = Some((name, MtlEntry::default()));
+            continue;
+        }
+
+        let entry = match current.as_mut() {
+            Some((_, entry)) => entry,
+            // Only comments and blank lines are allowed before the first newmtl
+            None => {
+                return Err(ParseError::new(
+                    path,
+                    number,
+                    format!("'{}' before newmtl", keyword),
+                ))
+            }
+        };
+        let mut float = |what| parse_token::<f32>(tokens.next(), what, path, number);
+        match keyword {
+            "Kd" => entry.kd = Color(float("red")?, float("green")?, float("blue")?),
+            "Ks" => entry.ks = Color(float("red")?, float("green")?, float("blue")?),
+            "Ke" => entry.ke = Color(float("red")?, float("green")?, float("blue")?),
+            "Ns" => entry.ns = float("specular exponent")?,
+            "Ni" => entry.ni = float("index of refraction")?,
+            "d" => entry.d = float("dissolve")?,
+            "Tr" => entry.d = 1.0 - float("transparency")?,
+            "illum" => {
+                entry.illum = parse_token(tokens.next(), "illumination model", path, number)?
+            }
+            // Texture maps and the rest are not supported
+            _ => {}
+        }
+    }
+    if let Some((name, entry)) = current {
+        materials.insert(name, entry.to_material());
+    }
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::{Hit, Hittable};
+    use crate::ray::Ray;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn gray() -> Arc<dyn Scatterable> {
+        Arc::new(Lambertian::new(Color(0.5, 0.5, 0.5)))
+    }
+
+    fn parse(source: &str) -> Result<Obj, ParseError> {
+        parse_obj(source.as_bytes(), Path::new("test.obj"), gray())
+    }
+
+    fn down_z(x: f32, y: f32) -> Ray {
+        Ray::new(Point3(x, y, 1.0), Vec3(0.0, 0.0, -1.0))
+    }
+
+    #[test]
+    fn obj_triangulates_polygons() {
+        let obj = parse(
+            "# a pentagon and a quad
+             v 0 0 0
+             v 2 0 0
+             v 2 1 0
+             v 1 2 0
+             v 0 1 0
+             f 1 2 3 4 5
+             v 3 0 0
+             v 4 0 0
+             v 4 1 0
+             v 3 1 0
+             f -4 -3 -2 -1
+             ",
+        )
+        .unwrap();
+        assert_eq!(obj.mesh.len(), 5);
+        assert_eq!(obj.mesh.positions().len(), 9);
+        assert_eq!(obj.mesh.indices()[0], [0, 1, 2]);
+        assert_eq!(obj.mesh.indices()[2], [0, 3, 4]);
+        // Negative indices refer to the last four vertices
+        assert_eq!(obj.mesh.indices()[3], [5, 6, 7]);
+        assert!(obj.mesh.hit(&down_z(1.0, 1.5), 0.0, f32::MAX).is_some());
+        assert!(obj.mesh.hit(&down_z(3.5, 0.5), 0.0, f32::MAX).is_some());
+        assert!(obj.mesh.normals().is_empty() && obj.mesh.uvs().is_empty());
+    }
+
+    #[test]
+    fn obj_shares_vertices_with_attributes() {
+        let obj = parse(
+            "v 0 0 0
+             v 1 0 0
+             v 1 1 0
+             v 0 1 0
+             vt 0 0
+             vt 1 0
+             vt 1 1
+             vt 0 1
+             vn 0 0 1
+             f 1/1/1 2/2/1 3/3/1
+             f 1/1/1 3/3/1 4/4/1
+             f 1//1 3//1 4//1
+             ",
+        )
+        .unwrap();
+        // The last face uses the positions without uvs, which are new vertices
+        assert_eq!(obj.mesh.positions().len(), 7);
+        assert_eq!(obj.mesh.normals().len(), 7);
+        let (hit, _) = obj.mesh.hit(&down_z(0.75, 0.25), 0.0, f32::MAX).unwrap();
+        let (u, v) = hit.uv();
+        assert!((u - 0.75).abs() < 1e-6 && (v - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn obj_groups() {
+        let obj = parse(
+            "v 0 0 0
+             v 1 0 0
+             v 0 1 0
+             f 1 2 3
+             g left side
+             f 1 2 3
+             f 1 2 3
+             o empty
+             o right
+             f 3 2 1
+             ",
+        )
+        .unwrap();
+        let names: Vec<(&str, Range<usize>)> = obj
+            .groups
+            .iter()
+            .map(|g| (g.name.as_str(), g.faces.clone()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![("default", 0..1), ("left side", 1..3), ("right", 3..4)]
+        );
+    }
+
+    #[test]
+    fn obj_errors_report_the_line() {
+        let cases = [
+            ("v 0 0 0\nv 1 0\n", "test.obj:2: missing z"),
+            ("v 0 0 0\nv 1 x 0\n", "test.obj:2: invalid y 'x'"),
+            ("v nan 0 0\nf 1 1 1\n", "test.obj:1: invalid x 'nan'"),
+            ("v 0 0 0\nvn 0 inf 0\n", "test.obj:2: invalid y 'inf'"),
+            (
+                "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n",
+                "test.obj:4: vertex index 4 out of range",
+            ),
+            (
+                "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 -4\n",
+                "test.obj:4: vertex index -4 out of range",
+            ),
+            (
+                "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1/1 2/1 3/1\n",
+                "test.obj:4: texture coordinate index 1 out of range",
+            ),
+            (
+                "v 0 0 0\nv 1 0 0\n\nf 1 2\n",
+                "test.obj:4: a face needs at least 3 vertices",
+            ),
+            (
+                "v 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl missing\nf 1 2 3\n",
+                "test.obj:4: unknown material 'missing'",
+            ),
+            ("v 0 0 0\n", "test.obj: no faces"),
+        ];
+        for (source, message) in cases.iter() {
+            assert_eq!(parse(source).err().unwrap().to_string(), *message);
+        }
+    }
+
+    fn parse_library(source: &str) -> HashMap<String, Arc<dyn Scatterable>> {
+        parse_mtl(source.as_bytes(), Path::new("test.mtl")).unwrap()
+    }
+
+    fn hit_from_above() -> (Ray, Hit) {
+        let r = Ray::new(Point3(0.0, 1.0, 0.0), Vec3(0.0, -1.0, 0.0));
+        let hit = Hit::new(r.dir, Point3::zero(), Vec3(0.0, 1.0, 0.0), 1.0);
+        (r, hit)
+    }
+
+    #[test]
+    fn mtl_maps_onto_materials() {
+        let library = parse_library(
+            "newmtl paint
+             Kd 0.1 0.2 0.3
+             Ks 0.05 0.05 0.05
+
+             newmtl chrome # shiny
+             Kd 0.0 0.0 0.0
+             Ks 0.9 0.8 0.7
+             Ns 1000000
+
+             newmtl glass
+             Kd 1 1 1
+             Ni 1.45
+             d 0.1
+
+             newmtl lamp
+             Ke 5 5 4
+             ",
+        );
+        assert_eq!(library.len(), 4);
+        let (r, hit) = hit_from_above();
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let (attenuation, _) = library["paint"].scatter(&r, &hit, &mut rng).unwrap();
+        assert_eq!(attenuation, Color(0.1, 0.2, 0.3));
+
+        let chrome = &library["chrome"];
+        // Nothing is scattered away from the mirror direction
+        let off_mirror = Vec3(0.6, 0.8, 0.0);
+        assert!(chrome.pdf(&hit, off_mirror, hit.n()) < 1e-6);
+        let (attenuation, scattered) = chrome.scatter(&r, &hit, &mut rng).unwrap();
+        assert_eq!(attenuation, Color(0.9, 0.8, 0.7));
+        assert!(Vec3::almost_eq(scattered.dir, Vec3(0.0, 1.0, 0.0), 1e-2));
+
+        let glass = &library["glass"];
+        assert_eq!(glass.pdf(&hit, off_mirror, hit.n()), 0.0);
+        let (attenuation, _) = glass.scatter(&r, &hit, &mut rng).unwrap();
+        assert_eq!(attenuation, Color(1.0, 1.0, 1.0));
+
+        assert_eq!(library["lamp"].emitted(&hit), Color(5.0, 5.0, 4.0));
+    }
+
+    #[test]
+    fn mtl_errors_report_the_line() {
+        let err = parse_mtl("Kd 1 1 1\n".as_bytes(), Path::new("a.mtl"))
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "a.mtl:1: 'Kd' before newmtl");
+        let err = parse_mtl("newmtl a\nKd 1 1\n".as_bytes(), Path::new("a.mtl"))
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "a.mtl:2: missing blue");
+    }
+
+    #[test]
+    fn load_obj_with_material_library() {
+        let dir = std::env::temp_dir().join(format!("obj-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("lights.mtl"), "newmtl light\nKe 2 2 2\n").unwrap();
+        std::fs::write(
+            dir.join("scene.obj"),
+            "mtllib lights.mtl
+             v 0 0 0
+             v 1 0 0
+             v 0 1 0
+             v 1 1 0
+             f 1 2 3
+             usemtl light
+             f 2 4 3
+             ",
+        )
+        .unwrap();
+        let obj = load_obj(dir.join("scene.obj"), gray());
+        let missing = load_obj(dir.join("missing.obj"), gray());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let obj = obj.unwrap();
+        let (hit, mat) = obj.mesh.hit(&down_z(0.25, 0.25), 0.0, f32::MAX).unwrap();
+        assert_eq!(mat.emitted(&hit), Color::zero());
+        let (hit, mat) = obj.mesh.hit(&down_z(0.75, 0.75), 0.0, f32::MAX).unwrap();
+        assert_eq!(mat.emitted(&hit), Color(2.0, 2.0, 2.0));
+        assert!(missing.is_err());
+    }
+}
@@ -0,0 +1,116 @@
+// Errors shared by the scene and asset loaders
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub file: PathBuf,
+    // 1-based, 0 when the error is not tied to a line
+    pub line: usize,
+    pub msg: String,
+}
+
+impl ParseError {
+    pub fn new<S: Into<String>>(file: &Path, line: usize, msg: S) -> ParseError {
+        ParseError {
+            file: file.to_path_buf(),
+            line,
+            msg: msg.into(),
+        }
+    }
+
+    pub fn io(file: &Path, err: io::Error) -> ParseError {
+        ParseError::new(file, 0, err.to_string())
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line > 0 {
+            write!(f, "{}:{}: {}", self.file.display(), self.line, self.msg)
+        } else {
+            write!(f, "{}: {}", self.file.display(), self.msg)
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+// Numbers the loaders read. Floats must be finite, NaN or infinite
+// coordinates would only fail much later, deep in the renderer.
+pub trait Number: FromStr {
+    fn is_valid(&self) -> bool {
+        true
+    }
+}
+
+impl Number for f32 {
+    fn is_valid(&self) -> bool {
+        self.is_finite()
+    }
+}
+
+impl Number for f64 {
+    fn is_valid(&self) -> bool {
+        self.is_finite()
+    }
+}
+
+impl Number for u8 {}
+impl Number for u32 {}
+impl Number for u64 {}
+impl Number for usize {}
+impl Number for i32 {}
+impl Number for i64 {}
+
+// Parses a token, naming what was expected on failure
+pub fn parse_token<T: Number>(
+    token: Option<&str>,
+    what: &str,
+    file: &Path,
+    line: usize,
+) -> Result<T, ParseError> {
+    let token = token.ok_or_else(|| ParseError::new(file, line, format!("missing {}", what)))?;
+    token
+        .parse()
+        .ok()
+        .filter(T::is_valid)
+        .ok_or_else(|| ParseError::new(file, line, format!("invalid {} '{}'", what, token)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_error_display() {
+        let path = Path::new("scene.obj");
+        assert_eq!(
+            ParseError::new(path, 12, "bad face").to_string(),
+            "scene.obj:12: bad face"
+        );
+        let err = io::Error::new(io::ErrorKind::NotFound, "not found");
+        assert_eq!(
+            ParseError::io(path, err).to_string(),
+            "scene.obj: not found"
+        );
+    }
+
+    #[test]
+    fn parse_token_reports_what_failed() {
+        let path = Path::new("a.txt");
+        assert_eq!(parse_token::<f32>(Some("1.5"), "x", path, 3).unwrap(), 1.5);
+        let err = parse_token::<f32>(Some("abc"), "x", path, 3).unwrap_err();
+        assert_eq!(err.to_string(), "a.txt:3: invalid x 'abc'");
+        for token in ["nan", "inf", "-inf", "1e39"] {
+            let err = parse_token::<f32>(Some(token), "x", path, 3).unwrap_err();
+            assert_eq!(err.to_string(), format!("a.txt:3: invalid x '{}'", token));
+        }
+        assert!(parse_token::<f64>(Some("NaN"), "x", path, 3).is_err());
+        let err = parse_token::<u32>(None, "count", path, 4).unwrap_err();
+        assert_eq!(err.to_string(), "a.txt:4: missing count");
+    }
+}
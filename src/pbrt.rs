@@ -0,0 +1,1127 @@
+// Subset of the pbrt-v3 scene format: transforms, the perspective camera,
+// film and sampler settings, spheres and triangle meshes, the matte, metal,
+// mirror and glass materials, area and infinite lights and Include.
+// Anything else is skipped with a warning instead of failing the import.
+//
+// pbrt is left-handed, when the camera would see the scene mirrored the
+// whole scene is mirrored along x so renders match pbrt's images.
+use crate::background::{Background, EnvironmentMap, SolidColor};
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::hittable_vec::HittableVec;
+use crate::image::Framebuffer;
+use crate::light::{LightList, SphereLight};
+use crate::material::{
+    Dielectric, DiffuseLight, Lambertian, Metal, RoughConductor, RoughDielectric, Scatterable,
+};
+use crate::mesh::TriangleMesh;
+use crate::parse::{parse_token, ParseError};
+use crate::ply::load_ply;
+use crate::point::Point3;
+use crate::render::RenderSettings;
+use crate::scene_file::SceneFile;
+use crate::scenes::Scene;
+use crate::sphere::Sphere;
+use crate::vector::{Cross, Dot, Len, Mat4, Normalize, Vec3};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// Includes nested deeper than this are taken to be recursive
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+pub struct Pbrt {
+    pub scene_file: SceneFile,
+    // Parts of the file that were skipped or approximated
+    pub warnings: Vec<ParseError>,
+}
+
+pub fn load_pbrt<P: AsRef<Path>>(path: P) -> Result<Pbrt, ParseError> {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path).map_err(|e| ParseError::io(path, e))?;
+    parse_pbrt(&text, path)
+}
+
+// Included files are looked up next to the file that includes them
+pub fn parse_pbrt(text: &str, path: &Path) -> Result<Pbrt, ParseError> {
+    let mut parser = Parser::new(text, path)?;
+    while let Some(token) = parser.next() {
+        match token {
+            Token::Ident(directive) => parser.directive(&directive)?,
+            _ => return Err(parser.error("expected a directive")),
+        }
+    }
+    parser.finish()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Number(f64),
+    Open,
+    Close,
+}
+
+// Tokens with their line numbers
+fn tokenize(text: &str, path: &Path) -> Result<Vec<(Token, usize)>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    let mut line = 1;
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            c if c.is_whitespace() => {}
+            '#' => {
+                while chars.peek().is_some_and(|&(_, c)| c != '\n') {
+                    chars.next();
+                }
+            }
+            '[' => tokens.push((Token::Open, line)),
+            ']' => tokens.push((Token::Close, line)),
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\n')) | None => {
+                            return Err(ParseError::new(path, line, "unterminated string"))
+                        }
+                        Some((_, c)) => s.push(c),
+                    }
+                }
+                tokens.push((Token::Str(s), line));
+            }
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(i, c)) = chars.peek() {
+                    if c.is_whitespace() || "[]\"#".contains(c) {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                let word = &text[start..end];
+                let token = if c.is_ascii_digit() || "+-.".contains(c) {
+                    let number: f64 = parse_token(Some(word), "number", path, line)?;
+                    // Kept as f64 so large indices stay exact, but every
+                    // number must also fit the f32 geometry is made of
+                    if !(number as f32).is_finite() {
+                        let msg = format!("number '{}' is out of range", word);
+                        return Err(ParseError::new(path, line, msg));
+                    }
+                    Token::Number(number)
+                } else {
+                    Token::Ident(word.to_string())
+                };
+                tokens.push((token, line));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Number(f64),
+    Str(String),
+}
+
+// A `"type name" values` parameter of a directive
+struct Param {
+    ty: String,
+    name: String,
+    values: Vec<Value>,
+}
+
+struct Params(Vec<Param>);
+
+impl Params {
+    fn get(&self, name: &str) -> Option<&Param> {
+        self.0.iter().find(|p| p.name == name)
+    }
+
+    fn numbers(&self, name: &str) -> Option<Vec<f64>> {
+        let param = self.get(name)?;
+        param
+            .values
+            .iter()
+            .map(|v| match v {
+                Value::Number(n) => Some(*n),
+                Value::Str(_) => None,
+            })
+            .collect()
+    }
+
+    fn float(&self, name: &str, default: f32) -> f32 {
+        match self.numbers(name).as_deref() {
+            Some([n]) => *n as f32,
+            _ => default,
+        }
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        match self.get(name)?.values.as_slice() {
+            [Value::Str(s)] => Some(s),
+            _ => None,
+        }
+    }
+
+    fn bool(&self, name: &str, default: bool) -> bool {
+        match self.string(name) {
+            Some("true") => true,
+            Some("false") => false,
+            _ => default,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct GraphicsState {
+    ctm: Mat4,
+    material: Arc<dyn Scatterable>,
+    area_light: Option<Color>,
+    reverse_orientation: bool,
+}
+
+struct Stream {
+    file: usize,
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+struct Parser {
+    files: Vec<PathBuf>,
+    streams: Vec<Stream>,
+    // File and line of the last token read
+    location: (usize, usize),
+    warnings: Vec<ParseError>,
+
+    state: GraphicsState,
+    attribute_stack: Vec<GraphicsState>,
+    transform_stack: Vec<Mat4>,
+    named_materials: HashMap<String, Arc<dyn Scatterable>>,
+    coordinate_systems: HashMap<String, Mat4>,
+    // Shapes inside ObjectBegin are only drawn through instances
+    object_depth: usize,
+
+    camera: Option<Camera>,
+    camera_params: Option<(Mat4, Params)>,
+    // Applied to everything in world space, see the top of the file
+    mirror: Mat4,
+    settings: RenderSettings,
+    world: HittableVec,
+    lights: LightList,
+    background: Option<Box<dyn Background>>,
+}
+
+impl Parser {
+    fn new(text: &str, path: &Path) -> Result<Parser, ParseError> {
+        Ok(Parser {
+            files: vec![path.to_path_buf()],
+            streams: vec![Stream {
+                file: 0,
+                tokens: tokenize(text, path)?,
+                pos: 0,
+            }],
+            location: (0, 0),
+            warnings: Vec::new(),
+            state: GraphicsState {
+                ctm: Mat4::identity(),
+                material: Arc::new(Lambertian::new(Color(0.5, 0.5, 0.5))),
+                area_light: None,
+                reverse_orientation: false,
+            },
+            attribute_stack: Vec::new(),
+            transform_stack: Vec::new(),
+            named_materials: HashMap::new(),
+            coordinate_systems: HashMap::new(),
+            object_depth: 0,
+            camera: None,
+            camera_params: None,
+            mirror: Mat4::identity(),
+            // pbrt's defaults
+            settings: RenderSettings {
+                image_width: 640,
+                image_height: 480,
+                samples_per_pixel: 16,
+                depth: 5,
+                seed: 0,
+                threads: 0,
+            },
+            world: HittableVec::new(),
+            lights: LightList::new(),
+            background: None,
+        })
+    }
+
+    fn error<S: Into<String>>(&self, msg: S) -> ParseError {
+        ParseError::new(&self.files[self.location.0], self.location.1, msg)
+    }
+
+    fn warn<S: Into<String>>(&mut self, msg: S) {
+        let warning = self.error(msg);
+        self.warnings.push(warning);
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        let stream = self.streams.last()?;
+        stream.tokens.get(stream.pos).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        loop {
+            let stream = self.streams.last_mut()?;
+            if let Some((token, line)) = stream.tokens.get(stream.pos).cloned() {
+                stream.pos += 1;
+                self.location = (stream.file, line);
+                return Some(token);
+            }
+            self.streams.pop();
+            // Continue with the including file, unless this was the last one
+            if self.streams.is_empty() {
+                return None;
+            }
+        }
+    }
+
+    fn string(&mut self, what: &str) -> Result<String, ParseError> {
+        match self.next() {
+            Some(Token::Str(s)) => Ok(s),
+            _ => Err(self.error(format!("expected {}", what))),
+        }
+    }
+
+    // `count` numbers, optionally in brackets
+    fn numbers(&mut self, count: usize) -> Result<Vec<f32>, ParseError> {
+        let bracketed = self.peek() == Some(&Token::Open);
+        if bracketed {
+            self.next();
+        }
+        let mut numbers = Vec::with_capacity(count);
+        for _ in 0..count {
+            match self.next() {
+                Some(Token::Number(n)) => numbers.push(n as f32),
+                _ => return Err(self.error(format!("expected {} numbers", count))),
+            }
+        }
+        if bracketed && self.next() != Some(Token::Close) {
+            return Err(self.error("expected ']'"));
+        }
+        Ok(numbers)
+    }
+
+    fn params(&mut self) -> Result<Params, ParseError> {
+        let mut params = Vec::new();
+        while let Some(Token::Str(_)) = self.peek() {
+            let declaration = self.string("a parameter")?;
+            let (ty, name) = match declaration.split_whitespace().collect::<Vec<_>>()[..] {
+                [ty, name] => (ty.to_string(), name.to_string()),
+                _ => {
+                    return Err(
+                        self.error(format!("invalid parameter declaration '{}'", declaration))
+                    )
+                }
+            };
+            let mut values = Vec::new();
+            match self.next() {
+                Some(Token::Open) => loop {
+                    match self.next() {
+                        Some(Token::Close) => break,
+                        Some(Token::Number(n)) => values.push(Value::Number(n)),
+                        Some(Token::Str(s)) | Some(Token::Ident(s)) => values.push(Value::Str(s)),
+                        _ => return Err(self.error(format!("unterminated values of '{}'", name))),
+                    }
+                },
+                Some(Token::Number(n)) => values.push(Value::Number(n)),
+                Some(Token::Str(s)) => values.push(Value::Str(s)),
+                _ => return Err(self.error(format!("missing value of '{}'", name))),
+            }
+            params.push(Param { ty, name, values });
+        }
+        Ok(Params(params))
+    }
+
+    // Skips the arguments of an unsupported directive
+    fn skip_arguments(&mut self) {
+        while let Some(token) = self.peek() {
+            if let Token::Ident(_) = token {
+                break;
+            }
+            self.next();
+        }
+    }
+
+    // RGB colors, spectra and textures are not supported
+    fn color(&mut self, params: &Params, name: &str, default: Color) -> Color {
+        let param = match params.get(name) {
+            Some(param) => param,
+            None => return default,
+        };
+        match (param.ty.as_str(), params.numbers(name).as_deref()) {
+            ("rgb", Some(&[r, g, b])) | ("color", Some(&[r, g, b])) => {
+                Color(r as f32, g as f32, b as f32)
+            }
+            (ty, _) => {
+                let msg = format!("'{} {}' is not supported, using the default", ty, name);
+                self.warn(msg);
+                default
+            }
+        }
+    }
+
+    fn transform(&mut self, m: Mat4) {
+        self.state.ctm = self.state.ctm * m;
+    }
+
+    fn directive(&mut self, directive: &str) -> Result<(), ParseError> {
+        match directive {
+            "Identity" => self.state.ctm = Mat4::identity(),
+            "Translate" => {
+                let t = self.numbers(3)?;
+                self.transform(Mat4::translation(Vec3(t[0], t[1], t[2])));
+            }
+            "Scale" => {
+                let s = self.numbers(3)?;
+                self.transform(Mat4::scaling(Vec3(s[0], s[1], s[2])));
+            }
+            "Rotate" => {
+                let r = self.numbers(4)?;
+                let axis = Vec3(r[1], r[2], r[3]);
+                if !r[0].is_finite() || is_degenerate(axis) {
+                    return Err(self.error("degenerate Rotate"));
+                }
+                self.transform(Mat4::rotation(r[0], axis));
+            }
+            "LookAt" => {
+                let v = self.numbers(9)?;
+                let look_at = look_at(
+                    Point3(v[0], v[1], v[2]),
+                    Point3(v[3], v[4], v[5]),
+                    Vec3(v[6], v[7], v[8]),
+                )
+                .ok_or_else(|| self.error("degenerate LookAt"))?;
+                self.transform(look_at);
+            }
+            "Transform" | "ConcatTransform" => {
+                let values = self.numbers(16)?;
+                // Matrices are written column after column
+                let m = Mat4::from_column_major(&values.try_into().unwrap());
+                if directive == "Transform" {
+                    self.state.ctm = m;
+                } else {
+                    self.transform(m);
+                }
+            }
+            "CoordinateSystem" => {
+                let name = self.string("a coordinate system name")?;
+                self.coordinate_systems.insert(name, self.state.ctm);
+            }
+            "CoordSysTransform" => {
+                let name = self.string("a coordinate system name")?;
+                match self.coordinate_systems.get(&name) {
+                    Some(&m) => self.state.ctm = m,
+                    None => self.warn(format!("unknown coordinate system '{}'", name)),
+                }
+            }
+            "ReverseOrientation" => {
+                self.state.reverse_orientation = !self.state.reverse_orientation
+            }
+            "TransformBegin" => self.transform_stack.push(self.state.ctm),
+            "TransformEnd" => match self.transform_stack.pop() {
+                Some(ctm) => self.state.ctm = ctm,
+                None => self.warn("unmatched TransformEnd"),
+            },
+            "AttributeBegin" => self.attribute_stack.push(self.state.clone()),
+            "AttributeEnd" => match self.attribute_stack.pop() {
+                Some(state) => self.state = state,
+                None => self.warn("unmatched AttributeEnd"),
+            },
+            "ObjectBegin" => {
+                self.string("an object name")?;
+                self.warn("object instancing is not supported, the object is skipped");
+                self.attribute_stack.push(self.state.clone());
+                self.object_depth += 1;
+            }
+            "ObjectEnd" => {
+                if let Some(state) = self.attribute_stack.pop() {
+                    self.state = state;
+                }
+                self.object_depth = self.object_depth.saturating_sub(1);
+            }
+            "Camera" => {
+                let ty = self.string("a camera type")?;
+                let params = self.params()?;
+                if ty != "perspective" {
+                    self.warn(format!(
+                        "'{}' cameras are not supported, using a perspective camera",
+                        ty
+                    ));
+                }
+                let camera_to_world = self
+                    .state
+                    .ctm
+                    .inverse()
+                    .ok_or_else(|| self.error("the camera transform is not invertible"))?;
+                self.coordinate_systems
+                    .insert("camera".to_string(), camera_to_world);
+                self.camera_params = Some((camera_to_world, params));
+            }
+            "Film" => {
+                self.string("a film type")?;
+                let params = self.params()?;
+                self.settings.image_width = params.float("xresolution", 640.0) as usize;
+                self.settings.image_height = params.float("yresolution", 480.0) as usize;
+                if self.settings.image_width == 0 || self.settings.image_height == 0 {
+                    return Err(self.error("the image must not be empty"));
+                }
+            }
+            "Sampler" => {
+                self.string("a sampler type")?;
+                let params = self.params()?;
+                self.settings.samples_per_pixel = params.float("pixelsamples", 16.0) as u32;
+            }
+            "Integrator" => {
+                let ty = self.string("an integrator type")?;
+                let params = self.params()?;
+                if ty != "path" {
+                    self.warn(format!(
+                        "the '{}' integrator is not supported, using a path tracer",
+                        ty
+                    ));
+                }
+                self.settings.depth = params.float("maxdepth", 5.0) as u32;
+            }
+            "WorldBegin" => {
+                self.begin_camera()?;
+                self.state.ctm = Mat4::identity();
+                self.coordinate_systems
+                    .insert("world".to_string(), Mat4::identity());
+            }
+            "WorldEnd" => {}
+            "Material" => {
+                let ty = self.string("a material type")?;
+                let params = self.params()?;
+                self.state.material = self.material(&ty, &params);
+            }
+            "MakeNamedMaterial" => {
+                let name = self.string("a material name")?;
+                let params = self.params()?;
+                let ty = params.string("type").unwrap_or("matte").to_string();
+                let material = self.material(&ty, &params);
+                self.named_materials.insert(name, material);
+            }
+            "NamedMaterial" => {
+                let name = self.string("a material name")?;
+                match self.named_materials.get(&name) {
+                    Some(material) => self.state.material = material.clone(),
+                    None => self.warn(format!("unknown material '{}'", name)),
+                }
+            }
+            "AreaLightSource" => {
+                let ty = self.string("an area light type")?;
+                let params = self.params()?;
+                if ty == "diffuse" {
+                    let l = self.color(&params, "L", Color(1.0, 1.0, 1.0));
+                    let scale = self.color(&params, "scale", Color(1.0, 1.0, 1.0));
+                    self.state.area_light = Some(l * scale);
+                } else {
+                    self.warn(format!("'{}' area lights are not supported", ty));
+                }
+            }
+            "LightSource" => {
+                let ty = self.string("a light type")?;
+                let params = self.params()?;
+                self.light(&ty, &params)?;
+            }
+            "Shape" => {
+                let ty = self.string("a shape type")?;
+                let params = self.params()?;
+                if self.object_depth == 0 {
+                    self.shape(&ty, &params)?;
+                }
+            }
+            "Include" => {
+                let name = self.string("a file name")?;
+                if self.streams.len() > MAX_INCLUDE_DEPTH {
+                    return Err(self.error("Include nested too deeply"));
+                }
+                let dir = self.files[self.location.0]
+                    .parent()
+                    .unwrap_or_else(|| Path::new(""));
+                let path = dir.join(&name);
+                let text = std::fs::read_to_string(&path)
+                    .map_err(|e| self.error(format!("cannot read {}: {}", name, e)))?;
+                let tokens = tokenize(&text, &path)?;
+                self.files.push(path);
+                self.streams.push(Stream {
+                    file: self.files.len() - 1,
+                    tokens,
+                    pos: 0,
+                });
+            }
+            _ => {
+                self.warn(format!("unsupported directive '{}'", directive));
+                self.skip_arguments();
+            }
+        }
+        Ok(())
+    }
+
+    // Places the camera before the world is read, that is when it is
+    // known whether the scene has to be mirrored
+    fn begin_camera(&mut self) -> Result<(), ParseError> {
+        let (camera_to_world, params) = self
+            .camera_params
+            .take()
+            .unwrap_or_else(|| (Mat4::identity(), Params(Vec::new())));
+        let look_from = camera_to_world.transform_point(Point3::zero());
+        let direction = camera_to_world.transform_vector(Vec3(0.0, 0.0, 1.0));
+        let up = camera_to_world.transform_vector(Vec3(0.0, 1.0, 0.0));
+        let right = camera_to_world.transform_vector(Vec3(1.0, 0.0, 0.0));
+        // Camera::new puts the image x axis along up x -direction
+        if Vec3::dot(Vec3::cross(up, -direction), right) < 0.0 {
+            self.mirror = Mat4::scaling(Vec3(-1.0, 1.0, 1.0));
+        }
+
+        let aspect_ratio = self.settings.image_width as f32 / self.settings.image_height as f32;
+        // pbrt's field of view spans the shorter image axis
+        let fov = params.float("fov", 90.0).to_radians();
+        let vfov = if aspect_ratio < 1.0 {
+            2.0 * ((fov / 2.0).tan() / aspect_ratio).atan()
+        } else {
+            fov
+        };
+        self.camera = Some(Camera::new(
+            self.mirror.transform_point(look_from),
+            self.mirror.transform_point(look_from + direction),
+            self.mirror.transform_vector(up),
+            vfov.to_degrees(),
+            aspect_ratio,
+            2.0 * params.float("lensradius", 0.0),
+            params.float("focaldistance", 1e6),
+        ));
+        Ok(())
+    }
+
+    fn material(&mut self, ty: &str, params: &Params) -> Arc<dyn Scatterable> {
+        match ty {
+            "matte" => Arc::new(Lambertian::new(self.color(
+                params,
+                "Kd",
+                Color(0.5, 0.5, 0.5),
+            ))),
+            "mirror" => Arc::new(Metal::new(
+                self.color(params, "Kr", Color(0.9, 0.9, 0.9)),
+                0.0,
+            )),
+            "glass" => {
+                let eta = params.float("eta", params.float("index", 1.5));
+                let roughness = microfacet_roughness(params, 0.0);
+                if roughness > 0.0 {
+                    Arc::new(RoughDielectric::new(eta, roughness))
+                } else {
+                    Arc::new(Dielectric::new(eta))
+                }
+            }
+            "metal" => {
+                // Copper, pbrt's default
+                let eta = self.color(params, "eta", Color(0.2004, 0.9240, 1.1022));
+                let k = self.color(params, "k", Color(3.9129, 2.4528, 2.1422));
+                let roughness = microfacet_roughness(params, 0.01);
+                Arc::new(RoughConductor::new(eta, k, roughness))
+            }
+            _ => {
+                self.warn(format!(
+                    "'{}' materials are not supported, using a matte material",
+                    ty
+                ));
+                Arc::new(Lambertian::new(self.color(
+                    params,
+                    "Kd",
+                    Color(0.5, 0.5, 0.5),
+                )))
+            }
+        }
+    }
+
+    fn light(&mut self, ty: &str, params: &Params) -> Result<(), ParseError> {
+        if ty != "infinite" {
+            self.warn(format!(
+                "'{}' lights are not supported, only area and infinite lights are",
+                ty
+            ));
+            return Ok(());
+        }
+        if self.background.is_some() {
+            self.warn("only one infinite light is supported, using the last one");
+        }
+        let l = self.color(params, "L", Color(1.0, 1.0, 1.0));
+        let scale = self.color(params, "scale", Color(1.0, 1.0, 1.0));
+        let background: Box<dyn Background> = match params.string("mapname") {
+            Some(name) => {
+                let dir = self.files[self.location.0]
+                    .parent()
+                    .unwrap_or_else(|| Path::new(""));
+                let image = Framebuffer::<Color>::load(dir.join(name))
+                    .map_err(|e| self.error(format!("cannot load {}: {}", name, e)))?;
+                self.warn("environment maps keep this renderer's orientation, not pbrt's");
+                Box::new(EnvironmentMap::new(image.map(|&c| c * l * scale)))
+            }
+            None => Box::new(SolidColor::new(l * scale)),
+        };
+        self.background = Some(background);
+        Ok(())
+    }
+
+    fn shape(&mut self, ty: &str, params: &Params) -> Result<(), ParseError> {
+        let material: Arc<dyn Scatterable> = match self.state.area_light {
+            Some(l) => Arc::new(DiffuseLight::new(l)),
+            None => self.state.material.clone(),
+        };
+        let world = self.mirror * self.state.ctm;
+        match ty {
+            "sphere" => {
+                if ["zmin", "zmax", "phimax"]
+                    .iter()
+                    .any(|name| params.get(name).is_some())
+                {
+                    self.warn("partial spheres are not supported, using the whole sphere");
+                }
+                let scales: Vec<f32> = [
+                    Vec3(1.0, 0.0, 0.0),
+                    Vec3(0.0, 1.0, 0.0),
+                    Vec3(0.0, 0.0, 1.0),
+                ]
+                .iter()
+                .map(|&axis| world.transform_vector(axis).len())
+                .collect();
+                let scale = scales.iter().sum::<f32>() / 3.0;
+                if scales.iter().any(|s| (s - scale).abs() > 1e-3 * scale) {
+                    self.warn("spheres do not support non-uniform scaling, using the mean scale");
+                }
+                let radius = params.float("radius", 1.0) * scale;
+                if radius > 0.0 {
+                    let center = world.transform_point(Point3::zero());
+                    let extent = Vec3(radius, radius, radius);
+                    if !is_finite(center - extent) || !is_finite(center + extent) {
+                        return Err(self.error("sphere is out of range"));
+                    }
+                    if self.state.area_light.is_some() {
+                        let light = SphereLight::new(center, radius, material.clone());
+                        self.lights.push(Box::new(light));
+                    }
+                    self.world
+                        .push(Box::new(Sphere::new(center, radius, material)));
+                }
+            }
+            "trianglemesh" => {
+                let positions: Vec<Point3> = params
+                    .numbers("P")
+                    .filter(|p| !p.is_empty() && p.len() % 3 == 0)
+                    .ok_or_else(|| self.error("a triangle mesh needs 'point P'"))?
+                    .chunks(3)
+                    .map(|p| Point3(p[0] as f32, p[1] as f32, p[2] as f32))
+                    .collect();
+                let indices = match params.numbers("indices") {
+                    Some(indices) if indices.len() % 3 == 0 => indices
+                        .chunks(3)
+                        .map(|t| [t[0] as u32, t[1] as u32, t[2] as u32])
+                        .collect(),
+                    None if positions.len() == 3 => vec![[0, 1, 2]],
+                    _ => return Err(self.error("a triangle mesh needs 'integer indices'")),
+                };
+                let normals: Option<Vec<Vec3>> = params.numbers("N").map(|n| {
+                    n.chunks(3)
+                        .map(|n| Vec3(n[0] as f32, n[1] as f32, n[2] as f32))
+                        .collect()
+                });
+                let uvs: Option<Vec<(f32, f32)>> = ["uv", "st"]
+                    .iter()
+                    .find_map(|name| params.numbers(name))
+                    .map(|t| t.chunks(2).map(|t| (t[0] as f32, t[1] as f32)).collect());
+                let mesh = self.mesh(&world, positions, indices, normals, uvs, material)?;
+                self.world.push(Box::new(mesh));
+            }
+            "plymesh" => {
+                let name = params
+                    .string("filename")
+                    .ok_or_else(|| self.error("a PLY mesh needs 'string filename'"))?;
+                let dir = self.files[self.location.0]
+                    .parent()
+                    .unwrap_or_else(|| Path::new(""));
+                let ply = load_ply(dir.join(name), material.clone())
+                    .map_err(|e| self.error(e.to_string()))?;
+                let mesh = self.mesh(
+                    &world,
+                    ply.positions().to_vec(),
+                    ply.indices().to_vec(),
+                    Some(ply.normals().to_vec()).filter(|n| !n.is_empty()),
+                    Some(ply.uvs().to_vec()).filter(|t| !t.is_empty()),
+                    material,
+                )?;
+                self.world.push(Box::new(mesh));
+            }
+            _ => self.warn(format!("'{}' shapes are not supported", ty)),
+        }
+        Ok(())
+    }
+
+    // Moves the mesh to world space, keeping pbrt's orientation of the normals
+    fn mesh(
+        &self,
+        world: &Mat4,
+        positions: Vec<Point3>,
+        mut indices: Vec<[u32; 3]>,
+        normals: Option<Vec<Vec3>>,
+        uvs: Option<Vec<(f32, f32)>>,
+        material: Arc<dyn Scatterable>,
+    ) -> Result<TriangleMesh, ParseError> {
+        let count = positions.len();
+        if indices.iter().flatten().any(|&i| i as usize >= count) {
+            return Err(self.error("triangle mesh index out of range"));
+        }
+        if indices.is_empty() {
+            return Err(self.error("triangle mesh has no triangles"));
+        }
+        if (world.linear_determinant() < 0.0) != self.state.reverse_orientation {
+            for triangle in &mut indices {
+                triangle.swap(1, 2);
+            }
+        }
+        let positions: Vec<Point3> = positions
+            .into_iter()
+            .map(|p| world.transform_point(p))
+            .collect();
+        if !positions.iter().all(|&p| is_finite(p)) {
+            return Err(self.error("triangle mesh is out of range"));
+        }
+        let mut mesh = TriangleMesh::new(positions, indices, material);
+        if let (Some(normals), Some(inverse)) = (normals, world.inverse()) {
+            if normals.len() != count {
+                return Err(self.error("triangle mesh has the wrong number of normals"));
+            }
+            let normal_matrix = inverse.transpose();
+            let normals = normals
+                .into_iter()
+                .map(|n| normal_matrix.transform_vector(n).normalize())
+                .collect();
+            mesh = mesh.with_normals(normals);
+        }
+        if let Some(uvs) = uvs {
+            if uvs.len() != count {
+                return Err(self.error("triangle mesh has the wrong number of uvs"));
+            }
+            mesh = mesh.with_uvs(uvs);
+        }
+        Ok(mesh)
+    }
+
+    fn finish(mut self) -> Result<Pbrt, ParseError> {
+        if self.camera.is_none() {
+            self.begin_camera()?;
+        }
+        if !self.attribute_stack.is_empty() {
+            self.warn("missing AttributeEnd at the end of the file");
+        }
+        let background = self
+            .background
+            .unwrap_or_else(|| Box::new(SolidColor::black()));
+        Ok(Pbrt {
+            scene_file: SceneFile {
+                scene: Scene::new(self.world, background).with_lights(self.lights),
+                camera: self.camera.unwrap(),
+                settings: self.settings,
+            },
+            warnings: self.warnings,
+        })
+    }
+}
+
+// Finite numbers can overflow once transformed
+fn is_finite(p: Point3) -> bool {
+    p.x().is_finite() && p.y().is_finite() && p.z().is_finite()
+}
+
+// Too short to normalize, NaN lengths included
+#[allow(clippy::neg_cmp_op_on_partial_ord)]
+fn is_degenerate(v: Vec3) -> bool {
+    !(v.len() >= 1e-6)
+}
+
+// pbrt's LookAt, the transform from world to camera space
+fn look_at(eye: Point3, target: Point3, up: Vec3) -> Option<Mat4> {
+    let direction = target - eye;
+    if is_degenerate(direction) || is_degenerate(up) {
+        return None;
+    }
+    let direction = direction.normalize();
+    let right = Vec3::cross(up.normalize(), direction);
+    if is_degenerate(right) {
+        return None;
+    }
+    let right = right.normalize();
+    let new_up = Vec3::cross(direction, right);
+    let camera_to_world = Mat4([
+        [right.x(), new_up.x(), direction.x(), eye.x()],
+        [right.y(), new_up.y(), direction.y(), eye.y()],
+        [right.z(), new_up.z(), direction.z(), eye.z()],
+        [0.0, 0.0, 0.0, 1.0],
+    ]);
+    camera_to_world.inverse()
+}
+
+// pbrt's roughness of the metal and glass materials as the perceptual
+// roughness of RoughConductor and RoughDielectric
+fn microfacet_roughness(params: &Params, default: f32) -> f32 {
+    let roughness = match (params.get("uroughness"), params.get("vroughness")) {
+        (Some(_), Some(_)) => {
+            0.5 * (params.float("uroughness", default) + params.float("vroughness", default))
+        }
+        _ => params.float("roughness", default),
+    };
+    // Remapped roughness is roughly the square of the microfacet slope
+    // alpha, which is itself the square of our roughness
+    let alpha = if params.bool("remaproughness", true) {
+        roughness.max(0.0).sqrt()
+    } else {
+        roughness.max(0.0)
+    };
+    alpha.sqrt().min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::ray::Ray;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn parse(text: &str) -> Result<Pbrt, ParseError> {
+        parse_pbrt(text, Path::new("test.pbrt"))
+    }
+
+    #[test]
+    fn pbrt_tokens() {
+        let tokens: Vec<Token> =
+            tokenize("Shape \"sphere\" # comment\n[ -1.5 2e1 ]", Path::new("a"))
+                .unwrap()
+                .into_iter()
+                .map(|(token, _)| token)
+                .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Ident("Shape".to_string()),
+                Token::Str("sphere".to_string()),
+                Token::Open,
+                Token::Number(-1.5),
+                Token::Number(20.0),
+                Token::Close,
+            ]
+        );
+        let err = tokenize("\n\"abc", Path::new("a")).unwrap_err();
+        assert_eq!(err.to_string(), "a:2: unterminated string");
+    }
+
+    #[test]
+    fn pbrt_scene() {
+        let pbrt = parse(
+            r#"
+            LookAt 0 0 0  0 0 1  0 1 0
+            Camera "perspective" "float fov" [ 45 ]
+            Film "image" "integer xresolution" [ 200 ] "integer yresolution" [ 100 ]
+            Sampler "halton" "integer pixelsamples" 64
+            Integrator "path" "integer maxdepth" [ 7 ]
+            WorldBegin
+            LightSource "infinite" "rgb L" [ 0.1 0.2 0.3 ]
+            AttributeBegin
+              Translate 1 0 5
+              Material "matte" "rgb Kd" [ 0.8 0.1 0.1 ]
+              Shape "sphere" "float radius" 0.5
+            AttributeEnd
+            AttributeBegin
+              AreaLightSource "diffuse" "rgb L" [ 4 4 4 ]
+              Translate 0 0 10
+              Scale 2 2 2
+              Shape "trianglemesh" "integer indices" [ 0 1 2 ]
+                  "point P" [ -1 -1 0  1 -1 0  0 1 0 ]
+            AttributeEnd
+            WorldEnd
+            "#,
+        )
+        .unwrap();
+        assert!(pbrt.warnings.is_empty());
+        let scene_file = &pbrt.scene_file;
+        let settings = &scene_file.settings;
+        assert_eq!((settings.image_width, settings.image_height), (200, 100));
+        assert_eq!(settings.samples_per_pixel, 64);
+        assert_eq!(settings.depth, 7);
+        let background = scene_file.scene.background.color(Vec3(0.0, 1.0, 0.0));
+        assert_eq!(background, Color(0.1, 0.2, 0.3));
+
+        // The sphere is on the right of pbrt's image, so it must be on the right here too
+        let world = &scene_file.scene.world;
+        let mut rng = StdRng::seed_from_u64(0);
+        let camera = &scene_file.camera;
+        let right = camera.get_ray(&mut rng, 0.62, 0.5);
+        let left = camera.get_ray(&mut rng, 0.38, 0.5);
+        assert!(world.hit(&right, 0.001, f32::INFINITY).is_some());
+        assert!(world.hit(&left, 0.001, f32::INFINITY).is_none());
+
+        // The scaled light is still facing the camera
+        let center = camera.get_ray(&mut rng, 0.5, 0.5);
+        let (hit, material) = world.hit(&center, 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t() - 10.0).abs() < 1e-4);
+        assert_eq!(material.emitted(&hit), Color(4.0, 4.0, 4.0));
+    }
+
+    #[test]
+    fn pbrt_rough_glass_and_metal() {
+        let pbrt = parse(
+            r#"
+            LookAt 0 0 0  0 0 1  0 1 0
+            Camera "perspective"
+            WorldBegin
+            AttributeBegin
+              Translate 0 0 5
+              Material "glass" "float uroughness" 0.01 "float vroughness" 0.01
+              Shape "sphere"
+            AttributeEnd
+            AttributeBegin
+              Translate 0 0 -5
+              Material "glass"
+              Shape "sphere"
+            AttributeEnd
+            AttributeBegin
+              Translate 5 0 0
+              Material "metal"
+              Shape "sphere"
+            AttributeEnd
+            "#,
+        )
+        .unwrap();
+        assert!(pbrt.warnings.is_empty());
+        let world = &pbrt.scene_file.scene.world;
+        // Only rough surfaces spread light around the mirror direction
+        let glossy = |dir: Vec3| {
+            let r = Ray::new(Point3::zero(), dir);
+            world
+                .hit(&r, 0.001, f32::INFINITY)
+                .is_some_and(|(hit, material)| material.pdf(&hit, -dir, -dir) > 0.0)
+        };
+        assert!(glossy(Vec3(0.0, 0.0, 1.0)));
+        assert!(!glossy(Vec3(0.0, 0.0, -1.0)));
+        // Wherever the mirroring of pbrt's handedness put the metal
+        assert!(glossy(Vec3(1.0, 0.0, 0.0)) || glossy(Vec3(-1.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn pbrt_include_and_warnings() {
+        let dir = std::env::temp_dir().join("raytracer_pbrt_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("geometry.pbrt"),
+            "Material \"plastic\"\nShape \"sphere\"\nShape \"cylinder\"\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("broken.pbrt"), "\nShape \"trianglemesh\"\n").unwrap();
+        let scene = dir.join("scene.pbrt");
+        std::fs::write(
+            &scene,
+            "PixelFilter \"gaussian\" \"float xwidth\" 2\n\
+             WorldBegin\n\
+             LightSource \"point\" \"rgb I\" [1 1 1]\n\
+             Include \"geometry.pbrt\"\n\
+             Shape \"sphere\" \"float radius\" 2\n",
+        )
+        .unwrap();
+        let pbrt = load_pbrt(&scene);
+        std::fs::write(&scene, "WorldBegin\nInclude \"broken.pbrt\"\n").unwrap();
+        let broken = load_pbrt(&scene).err().map(|e| e.to_string());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let pbrt = pbrt.unwrap();
+        assert_eq!(pbrt.scene_file.scene.world.len(), 2);
+        let geometry = dir.join("geometry.pbrt");
+        let warnings: Vec<String> = pbrt.warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            vec![
+                format!("{}:1: unsupported directive 'PixelFilter'", scene.display()),
+                format!(
+                    "{}:3: 'point' lights are not supported, only area and infinite lights are",
+                    scene.display()
+                ),
+                format!(
+                    "{}:1: 'plastic' materials are not supported, using a matte material",
+                    geometry.display()
+                ),
+                format!(
+                    "{}:3: 'cylinder' shapes are not supported",
+                    geometry.display()
+                ),
+            ]
+        );
+        assert_eq!(
+            broken.unwrap(),
+            format!(
+                "{}:2: a triangle mesh needs 'point P'",
+                dir.join("broken.pbrt").display()
+            )
+        );
+    }
+
+    #[test]
+    fn pbrt_errors() {
+        let cases = [
+            ("WorldBegin\n\"sphere\"", "test.pbrt:2: expected a directive"),
+            ("Translate 1 2", "test.pbrt:1: expected 3 numbers"),
+            ("Translate 1 -inf 2", "test.pbrt:1: invalid number '-inf'"),
+            ("Translate 1 1e39 2", "test.pbrt:1: number '1e39' is out of range"),
+            (
+                "Scale 1e30 1 1 Shape \"trianglemesh\" \"point P\" [1e30 0 0 -1e30 0 0 0 1 0] \"integer indices\" [0 1 2]",
+                "test.pbrt:1: triangle mesh is out of range",
+            ),
+            (
+                "Scale 1e30 1e30 1e30 Shape \"sphere\" \"float radius\" 1e30",
+                "test.pbrt:1: sphere is out of range",
+            ),
+            (
+                "Shape \"trianglemesh\" \"point P\" [1e300 0 0 -1e300 0 0 0 1 0] \"integer indices\" [0 1 2]",
+                "test.pbrt:1: number '1e300' is out of range",
+            ),
+            ("LookAt 0 0 0 0 0 1 0 0 1", "test.pbrt:1: degenerate LookAt"),
+            ("LookAt 1 2 3 1 2 3 0 1 0", "test.pbrt:1: degenerate LookAt"),
+            ("Rotate 90 0 0 0", "test.pbrt:1: degenerate Rotate"),
+            (
+                "Shape \"sphere\" \"radius\" 1",
+                "test.pbrt:1: invalid parameter declaration 'radius'",
+            ),
+            (
+                "Shape \"sphere\" \"float radius\" [ 1",
+                "test.pbrt:1: unterminated values of 'radius'",
+            ),
+            (
+                "Shape \"trianglemesh\" \"point P\" [0 0 0 1 0 0 0 1 0 1 1 0]",
+                "test.pbrt:1: a triangle mesh needs 'integer indices'",
+            ),
+            (
+                "Shape \"trianglemesh\" \"integer indices\" [0 1 3]\n\"point P\" [0 0 0 1 0 0 0 1 0]",
+                "test.pbrt:2: triangle mesh index out of range",
+            ),
+            ("Include \"missing.pbrt\"", "test.pbrt:1: cannot read missing.pbrt"),
+        ];
+        for (text, message) in cases.iter() {
+            let err = parse(text).err().unwrap().to_string();
+            assert!(
+                err.starts_with(message),
+                "{} does not start with {}",
+                err,
+                message
+            );
+        }
+    }
+}
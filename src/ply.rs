@@ -0,0 +1,630 @@
+// Stanford PLY meshes in ASCII and binary encodings
+use crate::color::Color;
+use crate::image::gamma_decode;
+use crate::material::Scatterable;
+use crate::mesh::TriangleMesh;
+use crate::parse::{parse_token, ParseError};
+use crate::point::Point3;
+use crate::vector::Vec3;
+use std::convert::TryInto;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Scalar> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Property {
+    Scalar(String, Scalar),
+    // Name, count type and item type
+    List(String, Scalar, Scalar),
+}
+
+#[derive(Clone, Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+struct Header {
+    format: Format,
+    elements: Vec<Element>,
+    // Bytes and lines the header takes up
+    len: usize,
+    lines: usize,
+}
+
+fn scalar_type(
+    name: Option<&str>,
+    what: &str,
+    path: &Path,
+    line: usize,
+) -> Result<Scalar, ParseError> {
+    let name = name.unwrap_or("");
+    Scalar::parse(name)
+        .ok_or_else(|| ParseError::new(path, line, format!("invalid {} '{}'", what, name)))
+}
+
+fn parse_header(data: &[u8], path: &Path) -> Result<Header, ParseError> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut pos = 0;
+    let mut number = 0;
+    loop {
+        number += 1;
+        let end = data[pos..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| ParseError::new(path, number, "missing end_header"))?;
+        let line = std::str::from_utf8(&data[pos..pos + end])
+            .map_err(|_| ParseError::new(path, number, "header is not text"))?;
+        pos += end + 1;
+
+        let mut tokens = line.split_whitespace();
+        let keyword = tokens.next().unwrap_or("");
+        if number == 1 {
+            if keyword != "ply" {
+                return Err(ParseError::new(path, number, "not a PLY file"));
+            }
+            continue;
+        }
+        match keyword {
+            "format" => {
+                format = Some(match tokens.next() {
+                    Some("ascii") => Format::Ascii,
+                    Some("binary_little_endian") => Format::BinaryLittleEndian,
+                    Some("binary_big_endian") => Format::BinaryBigEndian,
+                    _ => return Err(ParseError::new(path, number, "unknown PLY format")),
+                });
+            }
+            "element" => {
+                let name = tokens
+                    .next()
+                    .ok_or_else(|| ParseError::new(path, number, "missing element name"))?;
+                let count = parse_token(tokens.next(), "element count", path, number)?;
+                elements.push(Element {
+                    name: name.to_string(),
+                    count,
+                    properties: Vec::new(),
+                });
+            }
+            "property" => {
+                let element = elements.last_mut().ok_or_else(|| {
+                    ParseError::new(path, number, "property outside of an element")
+                })?;
+                let property = match tokens.next() {
+                    Some("list") => {
+                        let count_type =
+                            scalar_type(tokens.next(), "list count type", path, number)?;
+                        let item_type = scalar_type(tokens.next(), "list item type", path, number)?;
+                        Property::List(
+                            tokens.next().unwrap_or("").to_string(),
+                            count_type,
+                            item_type,
+                        )
+                    }
+                    ty => {
+                        let ty = scalar_type(ty, "property type", path, number)?;
+                        Property::Scalar(tokens.next().unwrap_or("").to_string(), ty)
+                    }
+                };
+                element.properties.push(property);
+            }
+            "end_header" => break,
+            "comment" | "obj_info" | "" => {}
+            _ => {
+                return Err(ParseError::new(
+                    path,
+                    number,
+                    format!("unknown header line '{}'", keyword),
+                ))
+            }
+        }
+    }
+    let format = format.ok_or_else(|| ParseError::new(path, number, "missing format"))?;
+    Ok(Header {
+        format,
+        elements,
+        len: pos,
+        lines: number,
+    })
+}
+
+// Reads the values of the body one at a time, whatever the encoding
+enum Body<'a> {
+    Ascii {
+        lines: std::str::Lines<'a>,
+        tokens: std::str::SplitWhitespace<'a>,
+        number: usize,
+    },
+    Binary {
+        data: &'a [u8],
+        pos: usize,
+        big_endian: bool,
+    },
+}
+
+impl<'a> Body<'a> {
+    fn read(&mut self, ty: Scalar, path: &Path) -> Result<f64, ParseError> {
+        match self {
+            Body::Ascii {
+                lines,
+                tokens,
+                number,
+            } => loop {
+                if let Some(token) = tokens.next() {
+                    let value: f64 = parse_token(Some(token), "number", path, *number)?;
+                    return Ok(value);
+                }
+                let line = lines
+                    .next()
+                    .ok_or_else(|| ParseError::new(path, *number, "unexpected end of file"))?;
+                *tokens = line.split_whitespace();
+                *number += 1;
+            },
+            Body::Binary {
+                data,
+                pos,
+                big_endian,
+            } => {
+                let bytes = data
+                    .get(*pos..*pos + ty.size())
+                    .ok_or_else(|| ParseError::new(path, 0, "unexpected end of binary data"))?;
+                *pos += ty.size();
+                macro_rules! convert {
+                    ($t:ty) => {{
+                        let bytes = bytes.try_into().unwrap();
+                        if *big_endian {
+                            <$t>::from_be_bytes(bytes) as f64
+                        } else {
+                            <$t>::from_le_bytes(bytes) as f64
+                        }
+                    }};
+                }
+                let value = match ty {
+                    Scalar::I8 => bytes[0] as i8 as f64,
+                    Scalar::U8 => bytes[0] as f64,
+                    Scalar::I16 => convert!(i16),
+                    Scalar::U16 => convert!(u16),
+                    Scalar::I32 => convert!(i32),
+                    Scalar::U32 => convert!(u32),
+                    Scalar::F32 => convert!(f32),
+                    Scalar::F64 => convert!(f64),
+                };
+                if !value.is_finite() {
+                    return Err(ParseError::new(path, 0, "non-finite number in binary data"));
+                }
+                Ok(value)
+            }
+        }
+    }
+
+    fn line(&self) -> usize {
+        match self {
+            Body::Ascii { number, .. } => *number,
+            Body::Binary { .. } => 0,
+        }
+    }
+}
+
+// Integer colors are gamma encoded like 8-bit images, float colors are linear
+fn color_component(value: f64, ty: Scalar) -> f32 {
+    match ty {
+        Scalar::U8 => gamma_decode(&[value as u8, 0, 0]).r(),
+        Scalar::U16 => (value as f32 / 65535.0).powi(2),
+        _ => value as f32,
+    }
+}
+
+pub fn load_ply<P: AsRef<Path>>(
+    path: P,
+    material: Arc<dyn Scatterable>,
+) -> Result<TriangleMesh, ParseError> {
+    let path = path.as_ref();
+    let data = std::fs::read(path).map_err(|e| ParseError::io(path, e))?;
+    parse_ply(&data, path, material)
+}
+
+// Reads vertex positions, normals, colors and texture coordinates and the
+// face lists, polygons are split into triangle fans. Other elements are skipped.
+pub fn parse_ply(
+    data: &[u8],
+    path: &Path,
+    material: Arc<dyn Scatterable>,
+) -> Result<TriangleMesh, ParseError> {
+    let header = parse_header(data, path)?;
+    let body_data = &data[header.len..];
+    let mut body = match header.format {
+        Format::Ascii => {
+            let text = std::str::from_utf8(body_data)
+                .map_err(|_| ParseError::new(path, header.lines, "ASCII body is not text"))?;
+            Body::Ascii {
+                lines: text.lines(),
+                tokens: "".split_whitespace(),
+                number: header.lines,
+            }
+        }
+        format => Body::Binary {
+            data: body_data,
+            pos: 0,
+            big_endian: format == Format::BinaryBigEndian,
+        },
+    };
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut colors = Vec::new();
+    let mut uvs = Vec::new();
+    let mut indices = Vec::new();
+    for element in &header.elements {
+        let is_vertex = element.name == "vertex";
+        let is_face = element.name == "face";
+        // Bounded by the data, as rows without properties would read nothing
+        if element.count > body_data.len() {
+            return Err(ParseError::new(
+                path,
+                body.line(),
+                format!("invalid element count {}", element.count),
+            ));
+        }
+        for _ in 0..element.count {
+            // x y z nx ny nz red green blue u v
+            let mut vertex = [0.0f32; 11];
+            let mut present = [false; 11];
+            for property in &element.properties {
+                match property {
+                    Property::Scalar(name, ty) => {
+                        let value = body.read(*ty, path)?;
+                        let slot = match name.as_str() {
+                            "x" => 0,
+                            "y" => 1,
+                            "z" => 2,
+                            "nx" => 3,
+                            "ny" => 4,
+                            "nz" => 5,
+                            "red" | "r" => 6,
+                            "green" | "g" => 7,
+                            "blue" | "b" => 8,
+                            "u" | "s" | "texture_u" => 9,
+                            "v" | "t" | "texture_v" => 10,
+                            _ => continue,
+                        };
+                        vertex[slot] = if (6..9).contains(&slot) {
+                            color_component(value, *ty)
+                        } else {
+                            value as f32
+                        };
+                        if !vertex[slot].is_finite() {
+                            return Err(ParseError::new(
+                                path,
+                                body.line(),
+                                format!("number {:e} is out of range", value),
+                            ));
+                        }
+                        present[slot] = true;
+                    }
+                    Property::List(name, count_type, item_type) => {
+                        // Every item takes at least a byte, whatever the encoding
+                        let count = body.read(*count_type, path)?;
+                        if count < 0.0 || count > body_data.len() as f64 {
+                            return Err(ParseError::new(
+                                path,
+                                body.line(),
+                                format!("invalid list length {}", count),
+                            ));
+                        }
+                        let count = count as usize;
+                        let mut items = Vec::with_capacity(count);
+                        for _ in 0..count {
+                            items.push(body.read(*item_type, path)?);
+                        }
+                        let is_face_list = name == "vertex_indices" || name == "vertex_index";
+                        if !is_face || !is_face_list {
+                            continue;
+                        }
+                        if count < 3 {
+                            return Err(ParseError::new(
+                                path,
+                                body.line(),
+                                "a face needs at least 3 vertices",
+                            ));
+                        }
+                        for i in 1..count - 1 {
+                            let face = [items[0], items[i], items[i + 1]];
+                            let mut triangle = [0u32; 3];
+                            for (index, &value) in triangle.iter_mut().zip(&face) {
+                                if value < 0.0 || value as usize >= positions.len() {
+                                    return Err(ParseError::new(
+                                        path,
+                                        body.line(),
+                                        format!("vertex index {} out of range", value),
+                                    ));
+                                }
+                                *index = value as u32;
+                            }
+                            indices.push(triangle);
+                        }
+                    }
+                }
+            }
+            if is_vertex {
+                if !(present[0] && present[1] && present[2]) {
+                    return Err(ParseError::new(path, 0, "vertex without x, y and z"));
+                }
+                positions.push(Point3(vertex[0], vertex[1], vertex[2]));
+                if present[3] && present[4] && present[5] {
+                    normals.push(Vec3(vertex[3], vertex[4], vertex[5]));
+                }
+                if present[6] && present[7] && present[8] {
+                    colors.push(Color(vertex[6], vertex[7], vertex[8]));
+                }
+                if present[9] && present[10] {
+                    uvs.push((vertex[9], vertex[10]));
+                }
+            }
+        }
+    }
+
+    if indices.is_empty() {
+        return Err(ParseError::new(path, 0, "no faces"));
+    }
+    let vertex_count = positions.len();
+    let mut mesh = TriangleMesh::new(positions, indices, material);
+    // The properties are the same for every vertex, so these are all or nothing
+    if normals.len() == vertex_count {
+        mesh = mesh.with_normals(normals);
+    }
+    if colors.len() == vertex_count {
+        mesh = mesh.with_colors(colors);
+    }
+    if uvs.len() == vertex_count {
+        mesh = mesh.with_uvs(uvs);
+    }
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::material::Lambertian;
+    use crate::ray::Ray;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn vertex_colored() -> Arc<dyn Scatterable> {
+        Arc::new(Lambertian::with_vertex_colors(Color(0.5, 0.5, 0.5)))
+    }
+
+    fn down_z(x: f32, y: f32) -> Ray {
+        Ray::new(Point3(x, y, 1.0), Vec3(0.0, 0.0, -1.0))
+    }
+
+    const HEADER: &str = "element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+";
+
+    // The unit square as one quad, colored black, red, white and green
+    fn ascii_square() -> String {
+        format!(
+            "ply\nformat ascii 1.0\ncomment made by hand\n{}{}",
+            HEADER, "0 0 0 0 0 0\n1 0 0 255 0 0\n1 1 0 255 255 255\n0 1 0 0 255 0\n4 0 1 2 3\n"
+        )
+    }
+
+    fn binary_square(big_endian: bool) -> Vec<u8> {
+        let format = if big_endian {
+            "binary_big_endian"
+        } else {
+            "binary_little_endian"
+        };
+        let mut data = format!("ply\nformat {} 1.0\n{}", format, HEADER).into_bytes();
+        let vertices = [
+            ([0.0f32, 0.0, 0.0], [0u8, 0, 0]),
+            ([1.0, 0.0, 0.0], [255, 0, 0]),
+            ([1.0, 1.0, 0.0], [255, 255, 255]),
+            ([0.0, 1.0, 0.0], [0, 255, 0]),
+        ];
+        for (position, color) in vertices.iter() {
+            for &v in position {
+                data.extend_from_slice(&if big_endian {
+                    v.to_be_bytes()
+                } else {
+                    v.to_le_bytes()
+                });
+            }
+            data.extend_from_slice(color);
+        }
+        data.push(4);
+        for i in 0..4i32 {
+            data.extend_from_slice(&if big_endian {
+                i.to_be_bytes()
+            } else {
+                i.to_le_bytes()
+            });
+        }
+        data
+    }
+
+    fn check_square(mesh: &TriangleMesh) {
+        assert_eq!(mesh.len(), 2);
+        assert_eq!(mesh.positions()[2], Point3(1.0, 1.0, 0.0));
+        assert_eq!(mesh.colors()[1], Color(1.0, 0.0, 0.0));
+
+        // Colors are interpolated and used as the albedo
+        let albedo = |x, y| {
+            let r = down_z(x, y);
+            let (hit, mat) = mesh.hit(&r, 0.0, f32::MAX).unwrap();
+            let mut rng = StdRng::seed_from_u64(0);
+            mat.scatter(&r, &hit, &mut rng).unwrap().0
+        };
+        let corner = albedo(1.0, 0.0);
+        assert!((corner.r() - 1.0).abs() < 1e-5 && corner.g() < 1e-5);
+        let albedo = albedo(0.5, 0.25);
+        assert!((albedo.r() - 0.5).abs() < 1e-5);
+        assert!((albedo.g() - 0.25).abs() < 1e-5);
+        assert!((albedo.b() - 0.25).abs() < 1e-5);
+    }
+
+    #[test]
+    fn ply_ascii() {
+        let mesh = parse_ply(
+            ascii_square().as_bytes(),
+            Path::new("a.ply"),
+            vertex_colored(),
+        );
+        check_square(&mesh.unwrap());
+    }
+
+    #[test]
+    fn ply_binary_both_endians() {
+        for &big_endian in &[false, true] {
+            let data = binary_square(big_endian);
+            let mesh = parse_ply(&data, Path::new("b.ply"), vertex_colored()).unwrap();
+            check_square(&mesh);
+        }
+    }
+
+    #[test]
+    fn ply_normals_and_skipped_elements() {
+        let source = "ply
+format ascii 1.0
+element vertex 3
+property double x
+property double y
+property double z
+property float nx
+property float ny
+property float nz
+property float confidence
+element face 1
+property uchar flags
+property list uchar uint vertex_index
+element edge 1
+property int vertex1
+property int vertex2
+end_header
+0 0 0 0 0 1 0.5
+1 0 0 0 0 1 0.5
+0 1 0 0 0 1 0.5
+7 3 0 1 2
+0 1
+";
+        let mesh = parse_ply(source.as_bytes(), Path::new("n.ply"), vertex_colored()).unwrap();
+        assert_eq!(mesh.len(), 1);
+        assert_eq!(mesh.normals().len(), 3);
+        assert!(mesh.colors().is_empty());
+        // Without vertex colors the material falls back to its albedo
+        let r = down_z(0.25, 0.25);
+        let (hit, mat) = mesh.hit(&r, 0.0, f32::MAX).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let (attenuation, _) = mat.scatter(&r, &hit, &mut rng).unwrap();
+        assert_eq!(attenuation, Color(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn ply_errors() {
+        let parse = |source: &[u8]| {
+            parse_ply(source, Path::new("e.ply"), vertex_colored())
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(parse(b"obj\n"), "e.ply:1: not a PLY file");
+        assert_eq!(
+            parse(b"ply\nformat ascii 1.0\nelement vertex 3\n"),
+            "e.ply:4: missing end_header"
+        );
+        assert_eq!(
+            parse(b"ply\nformat ascii 1.0\nelement vertex 1\nproperty vec3 x\nend_header\n"),
+            "e.ply:4: invalid property type 'vec3'"
+        );
+        let bad_index = ascii_square().replace("4 0 1 2 3", "4 0 1 2 9");
+        assert_eq!(
+            parse(bad_index.as_bytes()),
+            "e.ply:18: vertex index 9 out of range"
+        );
+        let bad_number = ascii_square().replace("1 1 0 255", "1 x 0 255");
+        assert_eq!(parse(bad_number.as_bytes()), "e.ply:16: invalid number 'x'");
+        let nan = ascii_square().replace("1 1 0 255", "1 nan 0 255");
+        assert_eq!(parse(nan.as_bytes()), "e.ply:16: invalid number 'nan'");
+        let huge = ascii_square().replace("1 1 0 255", "1 1e300 0 255");
+        assert_eq!(
+            parse(huge.as_bytes()),
+            "e.ply:16: number 1e300 is out of range"
+        );
+        assert_eq!(
+            parse(b"ply\nformat ascii 1.0\nelement empty 4000000000\nend_header\n"),
+            "e.ply:4: invalid element count 4000000000"
+        );
+
+        let mut truncated = binary_square(false);
+        truncated.truncate(truncated.len() - 2);
+        assert_eq!(parse(&truncated), "e.ply: unexpected end of binary data");
+
+        let mut long_list = binary_square(false);
+        let face = long_list.len() - 17;
+        long_list[face] = 255;
+        assert_eq!(parse(&long_list), "e.ply: invalid list length 255");
+        let negative = ascii_square().replace("4 0 1 2 3", "-4 0 1 2 3");
+        assert_eq!(
+            parse(negative.as_bytes()),
+            "e.ply:18: invalid list length -4"
+        );
+
+        let mut infinite = binary_square(false);
+        let start = infinite.len() - 17 - 4 * 15;
+        infinite[start..start + 4].copy_from_slice(&f32::INFINITY.to_le_bytes());
+        assert_eq!(parse(&infinite), "e.ply: non-finite number in binary data");
+    }
+}
@@ -1,5 +1,5 @@
 use crate::vector::Vec3;
-use std::ops::{Add, AddAssign, Neg, Sub};
+use std::ops::{Add, AddAssign, Index, Neg, Sub};
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Point3(pub f32, pub f32, pub f32);
@@ -22,6 +22,18 @@ impl Point3 {
     }
 }
 
+impl Index<usize> for Point3 {
+    type Output = f32;
+    fn index(&self, axis: usize) -> &f32 {
+        match axis {
+            0 => &self.0,
+            1 => &self.1,
+            2 => &self.2,
+            _ => panic!("axis must be in 0..3"),
+        }
+    }
+}
+
 impl AddAssign<Point3> for Point3 {
     fn add_assign(&mut self, rhs: Point3) {
         self.0 += rhs.0;
@@ -86,6 +98,20 @@ mod tests {
         assert_eq!(a.z(), a.2);
     }
 
+    #[test]
+    fn point3_index() {
+        let a = Point3(1.0, 2.0, 3.0);
+        assert_eq!(a[0], a.0);
+        assert_eq!(a[1], a.1);
+        assert_eq!(a[2], a.2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn point3_index_out_of_bounds() {
+        let _ = Point3(1.0, 2.0, 3.0)[3];
+    }
+
     #[test]
     fn point3_add() {
         let a = Point3(1.0, 1.0, 1.0);
@@ -5,6 +5,8 @@ use crate::vector::{Normalize, Vec3};
 pub struct Ray {
     pub orig: Point3,
     pub dir: Vec3,
+    // Moment within the camera shutter interval, for moving objects
+    pub time: f32,
 }
 
 impl Ray {
@@ -16,9 +18,15 @@ impl Ray {
         Ray {
             orig: origin,
             dir: direction,
+            time: 0.0,
         }
     }
 
+    pub fn with_time(mut self, time: f32) -> Ray {
+        self.time = time;
+        self
+    }
+
     pub fn at(self, t: f32) -> Point3 {
         self.orig + t * self.dir
     }
@@ -0,0 +1,671 @@
+use crate::background::Background;
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::hittable::{Hit, Hittable};
+use crate::image::Framebuffer;
+use crate::light::LightList;
+use crate::material::Scatterable;
+use crate::ray::Ray;
+use crate::scenes::Scene;
+use crate::vector::Vec3;
+use rand::distributions::{Distribution, Uniform};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+const TILE_SIZE: usize = 16;
+
+pub struct RenderSettings {
+    pub image_width: usize,
+    pub image_height: usize,
+    pub samples_per_pixel: u32,
+    pub depth: u32,
+    pub seed: u64,
+    // 0 means one thread per available core
+    pub threads: usize,
+}
+
+// Balances two sampling strategies, weighting the one that drew the sample
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
+// Radiance scattered toward wo straight from a sampled background
+// direction, weighted against finding the same direction by scattering
+fn direct_background(
+    world: &dyn Hittable,
+    background: &dyn Background,
+    mat: &dyn Scatterable,
+    hit: &Hit,
+    wo: Vec3,
+    time: f32,
+    rng: &mut dyn RngCore,
+) -> Color {
+    let (dir, radiance, light_pdf) = match background.sample(rng) {
+        Some(sample) => sample,
+        None => return Color::zero(),
+    };
+    let bsdf_pdf = mat.pdf(hit, dir, wo);
+    if bsdf_pdf <= 0.0 || light_pdf <= 0.0 {
+        return Color::zero();
+    }
+    let shadow_ray = Ray::new(hit.p(), dir).with_time(time);
+    if world.hit(&shadow_ray, 1e-3, f32::MAX).is_some() {
+        return Color::zero();
+    }
+    let weight = power_heuristic(light_pdf, bsdf_pdf);
+    (weight / light_pdf) * mat.eval(hit, dir, wo) * radiance
+}
+
+// Radiance scattered toward wo straight from a sampled light,
+// unless something casts a shadow
+fn direct_light(
+    world: &dyn Hittable,
+    lights: &LightList,
+    mat: &dyn Scatterable,
+    hit: &Hit,
+    wo: Vec3,
+    time: f32,
+    rng: &mut dyn RngCore,
+) -> Color {
+    let sample = match lights.sample(hit.p(), time, rng) {
+        Some(sample) => sample,
+        None => return Color::zero(),
+    };
+    let bsdf_pdf = mat.pdf(hit, sample.dir, wo);
+    if bsdf_pdf <= 0.0 || sample.pdf <= 0.0 {
+        return Color::zero();
+    }
+    let shadow_ray = Ray::new(hit.p(), sample.dir).with_time(time);
+    if world
+        .hit(&shadow_ray, 1e-3, sample.distance - 1e-3)
+        .is_some()
+    {
+        return Color::zero();
+    }
+    let weight = power_heuristic(sample.pdf, bsdf_pdf);
+    (weight / sample.pdf) * mat.eval(hit, sample.dir, wo) * sample.radiance
+}
+
+// Bounces after which paths may be ended early, the first ones
+// carry most of the image and are always traced
+const ROULETTE_DEPTH: u32 = 3;
+
+// Follows a path for up to depth surfaces, adding up what reaches each
+// of them weighted by the fraction of it that makes it back to the camera
+pub fn ray_color(
+    world: &dyn Hittable,
+    lights: &LightList,
+    background: &dyn Background,
+    r: &Ray,
+    depth: u32,
+    rng: &mut dyn RngCore,
+) -> Color {
+    let mut color = Color::zero();
+    let mut throughput = Color(1.0, 1.0, 1.0);
+    let mut ray = *r;
+    // The solid angle pdf with which the previous surface scattered ray
+    // after it already sampled the lights and the background directly
+    let mut bsdf_pdf: Option<f32> = None;
+    for bounce in 0..depth {
+        let (hit, mat) = match world.hit(&ray, 1e-3, f32::MAX) {
+            Some(hit) => hit,
+            None => {
+                let mut background_color = background.color(ray.dir);
+                if let Some(pdf) = bsdf_pdf {
+                    background_color =
+                        power_heuristic(pdf, background.pdf(ray.dir)) * background_color;
+                }
+                color += throughput * background_color;
+                break;
+            }
+        };
+        let mut emitted = mat.emitted(&hit);
+        if let Some(pdf) = bsdf_pdf.filter(|_| emitted != Color::zero()) {
+            emitted = power_heuristic(pdf, lights.pdf(ray.orig, hit.p(), ray.time)) * emitted;
+        }
+        color += throughput * emitted;
+        // The last surface of a path only shows what it emits
+        if bounce + 1 == depth {
+            break;
+        }
+
+        // Mirrors and glass have a pdf of 0 everywhere and skip this,
+        // the lights are only found through them
+        let wo = -ray.dir;
+        color += throughput * direct_light(world, lights, mat, &hit, wo, ray.time, rng);
+        color += throughput * direct_background(world, background, mat, &hit, wo, ray.time, rng);
+
+        let (attenuation, scattered) = match mat.scatter(&ray, &hit, rng) {
+            Some(scattered) => scattered,
+            None => break,
+        };
+        bsdf_pdf = Some(mat.pdf(&hit, scattered.dir, wo)).filter(|&pdf| pdf > 0.0);
+        throughput *= attenuation;
+        ray = scattered;
+
+        // Russian roulette: dim paths are ended at random and the survivors
+        // brightened to make up for them, which keeps the mean unchanged
+        if bounce + 1 >= ROULETTE_DEPTH {
+            let survival = throughput
+                .r()
+                .max(throughput.g())
+                .max(throughput.b())
+                .min(1.0);
+            if rng.gen::<f32>() >= survival {
+                break;
+            }
+            throughput /= survival;
+        }
+    }
+    color
+}
+
+// Every pixel gets its own random stream, so the result does not
+// depend on which thread rendered the pixel or in what order.
+fn pixel_rng(seed: u64, i: usize, j: usize) -> StdRng {
+    // SplitMix64 finalizer to decorrelate neighbouring pixel seeds
+    let mut z = seed ^ ((j as u64) << 32 | i as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    StdRng::seed_from_u64(z ^ (z >> 31))
+}
+
+fn render_pixel(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+    i: usize,
+    j: usize,
+) -> Color {
+    let mut rng = pixel_rng(settings.seed, i, j);
+    let distribution = Uniform::from(-0.5..=0.5);
+    let mut pixel_color = Color::zero();
+    for _ in 0..settings.samples_per_pixel {
+        let di = distribution.sample(&mut rng);
+        let dj = distribution.sample(&mut rng);
+        let u = (i as f32 + di) / (settings.image_width - 1) as f32;
+        let v = (j as f32 + dj) / (settings.image_height - 1) as f32;
+
+        let r = camera.get_ray(&mut rng, u, v);
+
+        pixel_color += ray_color(
+            &scene.world,
+            &scene.lights,
+            scene.background.as_ref(),
+            &r,
+            settings.depth,
+            &mut rng,
+        );
+    }
+    pixel_color / settings.samples_per_pixel as f32
+}
+
+// Renders the image in square tiles on a pool of threads.
+// The result is linear radiance, not clamped or gamma corrected.
+pub fn render(scene: &Scene, camera: &Camera, settings: &RenderSettings) -> Framebuffer<Color> {
+    let (width, height) = (settings.image_width, settings.image_height);
+    let tiles_x = width.div_ceil(TILE_SIZE);
+    let tiles_y = height.div_ceil(TILE_SIZE);
+    let threads = if settings.threads == 0 {
+        thread::available_parallelism().map_or(1, |n| n.get())
+    } else {
+        settings.threads
+    };
+
+    let next_tile = AtomicUsize::new(0);
+    let mut image = Framebuffer::new(width, height, Color::zero());
+    thread::scope(|s| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut rendered = Vec::new();
+                    loop {
+                        let tile = next_tile.fetch_add(1, Ordering::Relaxed);
+                        if tile >= tiles_x * tiles_y {
+                            break;
+                        }
+                        let x0 = (tile % tiles_x) * TILE_SIZE;
+                        let y0 = (tile / tiles_x) * TILE_SIZE;
+                        for y in y0..usize::min(y0 + TILE_SIZE, height) {
+                            for x in x0..usize::min(x0 + TILE_SIZE, width) {
+                                // image rows go from the top, j goes from the bottom
+                                let color =
+                                    render_pixel(scene, camera, settings, x, height - 1 - y);
+                                rendered.push((x, y, color));
+                            }
+                        }
+                    }
+                    rendered
+                })
+            })
+            .collect();
+
+        for worker in workers {
+            for (x, y, color) in worker.join().expect("render thread panicked") {
+                image.set(x, y, color);
+            }
+        }
+    });
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::{EnvironmentMap, Gradient, SolidColor};
+    use crate::hittable_vec::HittableVec;
+    use crate::light::SphereLight;
+    use crate::material::{DiffuseLight, Lambertian, Metal, MetallicRoughness, RoughDielectric};
+    use crate::point::Point3;
+    use crate::scenes::{cornell_box, cornell_box_camera, random_scene, Scene};
+    use crate::sphere::Sphere;
+    use crate::vector::{Normalize, Vec3};
+    use std::sync::Arc;
+
+    // The recursive path tracer ray_color replaced, always following
+    // paths to the full depth. bsdf_pdf is as in ray_color.
+    fn trace(
+        world: &dyn Hittable,
+        lights: &LightList,
+        background: &dyn Background,
+        r: &Ray,
+        depth: u32,
+        bsdf_pdf: Option<f32>,
+        rng: &mut dyn RngCore,
+    ) -> Color {
+        if depth == 0 {
+            return Color::zero();
+        }
+        if let Some((hit, mat)) = world.hit(r, 1e-3, f32::MAX) {
+            let mut color = mat.emitted(&hit);
+            if let Some(pdf) = bsdf_pdf.filter(|_| color != Color::zero()) {
+                color = power_heuristic(pdf, lights.pdf(r.orig, hit.p(), r.time)) * color;
+            }
+            // Only when the scattered ray could still reach an emitter.
+            // Mirrors and glass have a pdf of 0 everywhere and skip this,
+            // the lights are only found through them.
+            let wo = -r.dir;
+            if depth > 1 {
+                color += direct_light(world, lights, mat, &hit, wo, r.time, rng);
+                color += direct_background(world, background, mat, &hit, wo, r.time, rng);
+            }
+            if let Some((attenuation, scattered)) = mat.scatter(r, &hit, rng) {
+                let pdf = Some(mat.pdf(&hit, scattered.dir, wo)).filter(|&pdf| pdf > 0.0);
+                color +=
+                    attenuation * trace(world, lights, background, &scattered, depth - 1, pdf, rng);
+            }
+            color
+        } else {
+            let color = background.color(r.dir);
+            match bsdf_pdf {
+                Some(pdf) => power_heuristic(pdf, background.pdf(r.dir)) * color,
+                None => color,
+            }
+        }
+    }
+
+    fn mirror_scene() -> Scene {
+        // Only perfect mirrors, so the image depends on the camera samples alone
+        let mut world = HittableVec::new();
+        let mirror = Arc::new(Metal::new(Color(0.8, 0.8, 0.8), 0.0));
+        world.push(Box::new(Sphere::new(
+            Point3(0.0, -100.5, -1.0),
+            100.0,
+            mirror.clone(),
+        )));
+        world.push(Box::new(Sphere::new(Point3(0.0, 0.0, -1.0), 0.5, mirror)));
+        Scene::new(world, Box::new(Gradient::sky()))
+    }
+
+    fn settings(threads: usize, seed: u64) -> RenderSettings {
+        RenderSettings {
+            image_width: 37,
+            image_height: 21,
+            samples_per_pixel: 4,
+            depth: 10,
+            seed,
+            threads,
+        }
+    }
+
+    fn camera() -> Camera {
+        Camera::new(
+            Point3(0.0, 0.0, 1.0),
+            Point3(0.0, 0.0, -1.0),
+            Vec3(0.0, 1.0, 0.0),
+            90.0,
+            37.0 / 21.0,
+            0.1,
+            2.0,
+        )
+    }
+
+    #[test]
+    fn render_multi_threaded_matches_single_threaded() {
+        let world = mirror_scene();
+        let single = render(&world, &camera(), &settings(1, 3));
+        let multi = render(&world, &camera(), &settings(4, 3));
+        assert_eq!(single.pixels().len(), 37 * 21);
+        assert_eq!(single, multi);
+    }
+
+    #[test]
+    fn render_random_scene_is_reproducible() {
+        // Every material kind draws random numbers here
+        let render_scene = || {
+            let world = random_scene(11);
+            let camera = Camera::new(
+                Point3(13.0, 2.0, 3.0),
+                Point3::zero(),
+                Vec3(0.0, 1.0, 0.0),
+                20.0,
+                37.0 / 21.0,
+                0.1,
+                10.0,
+            );
+            render(&world, &camera, &settings(3, 5))
+        };
+        let a = render_scene();
+        let b = render_scene();
+        let bits = |image: &[Color]| -> Vec<u32> {
+            image
+                .iter()
+                .flat_map(|c| [c.r().to_bits(), c.g().to_bits(), c.b().to_bits()])
+                .collect()
+        };
+        assert_eq!(bits(a.pixels()), bits(b.pixels()));
+    }
+
+    #[test]
+    fn render_cornell_box_is_lit_by_emitters() {
+        let world = cornell_box();
+        let camera = cornell_box_camera(1.0);
+        let settings = RenderSettings {
+            image_width: 16,
+            image_height: 16,
+            samples_per_pixel: 16,
+            depth: 8,
+            seed: 1,
+            threads: 2,
+        };
+        let image = render(&world, &camera, &settings);
+        let mean = image.pixels().iter().fold(Color::zero(), |acc, &c| acc + c)
+            / image.pixels().len() as f32;
+        assert!(mean.r() > 0.01 && mean.g() > 0.01 && mean.b() > 0.01);
+
+        // Without the emitters nothing lights the box
+        let mut dark = HittableVec::new();
+        dark.push(Box::new(Sphere::new(
+            Point3(0.5, 0.5, 0.5),
+            0.2,
+            Arc::new(Lambertian::new(Color(0.8, 0.8, 0.8))),
+        )));
+        let image = render(
+            &Scene::new(dark, Box::new(SolidColor::black())),
+            &camera,
+            &settings,
+        );
+        assert!(image.pixels().iter().all(|&c| c == Color::zero()));
+    }
+
+    #[test]
+    fn ray_color_sees_scene_background() {
+        let world = HittableVec::new();
+        let up = Ray::new(Point3::zero(), Vec3(0.0, 1.0, 0.0));
+        let mut rng = StdRng::seed_from_u64(0);
+        let sky = Gradient::sky();
+        assert_eq!(
+            ray_color(&world, &LightList::new(), &sky, &up, 5, &mut rng),
+            Color(0.5, 0.7, 1.0)
+        );
+        // Nothing is seen once the depth is exhausted
+        assert_eq!(
+            ray_color(&world, &LightList::new(), &sky, &up, 0, &mut rng),
+            Color::zero()
+        );
+    }
+
+    // Hides the sampling routines so only scattered rays find the background
+    struct Unsampled(EnvironmentMap);
+
+    impl Background for Unsampled {
+        fn color(&self, dir: Vec3) -> Color {
+            self.0.color(dir)
+        }
+    }
+
+    // Mean and variance of the luminance of samples drawn with a seeded rng
+    fn estimate(seed: u64, samples: usize, mut f: impl FnMut(&mut StdRng) -> Color) -> (f64, f64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (mut sum, mut sum_sq) = (0.0, 0.0);
+        for _ in 0..samples {
+            let l = f(&mut rng).luminance() as f64;
+            sum += l;
+            sum_sq += l * l;
+        }
+        let mean = sum / samples as f64;
+        (mean, sum_sq / samples as f64 - mean * mean)
+    }
+
+    #[test]
+    fn environment_sampling_matches_scattering() {
+        let mut image = Framebuffer::new(32, 16, Color(0.2, 0.3, 0.5));
+        image.set(9, 4, Color(200.0, 180.0, 160.0));
+        let sampled = EnvironmentMap::new(image.clone());
+        let unsampled = Unsampled(EnvironmentMap::new(image));
+
+        let mut world = HittableVec::new();
+        world.push(Box::new(Sphere::new(
+            Point3(0.0, -1000.0, 0.0),
+            1000.0,
+            Arc::new(Lambertian::new(Color(0.5, 0.5, 0.5))),
+        )));
+        let r = Ray::new(Point3(0.0, 1.0, 0.0), Vec3(0.0, -1.0, 0.0));
+
+        // A single bounce off the ground
+        let bounce = |background: &dyn Background| {
+            estimate(4, 200_000, |rng| {
+                ray_color(&world, &LightList::new(), background, &r, 2, rng)
+            })
+        };
+        let (mean, variance) = bounce(&sampled);
+        let (reference, reference_variance) = bounce(&unsampled);
+        assert!(
+            (mean - reference).abs() < 0.03 * reference,
+            "{} {}",
+            mean,
+            reference
+        );
+        assert!(variance < 0.1 * reference_variance, "{} {}", variance, reference_variance);
+    }
+
+    #[test]
+    fn light_sampling_matches_scattering() {
+        // A lamp over a diffuse floor
+        let lamp = Arc::new(DiffuseLight::new(Color(10.0, 10.0, 10.0)));
+        let mut world = HittableVec::new();
+        world.push(Box::new(Sphere::new(
+            Point3(0.0, -1000.0, 0.0),
+            1000.0,
+            Arc::new(Lambertian::new(Color(0.5, 0.5, 0.5))),
+        )));
+        world.push(Box::new(Sphere::new(Point3(1.0, 2.0, 0.0), 0.3, lamp.clone())));
+        let mut lights = LightList::new();
+        lights.push(Box::new(SphereLight::new(Point3(1.0, 2.0, 0.0), 0.3, lamp)));
+        let black = SolidColor::black();
+        let r = Ray::new(Point3(0.0, 1.0, 0.0), Vec3(0.0, -1.0, 0.0));
+
+        let paths = |lights: &LightList| {
+            estimate(5, 200_000, |rng| {
+                ray_color(&world, lights, &black, &r, 3, rng)
+            })
+        };
+        let (mean, variance) = paths(&lights);
+        let (reference, reference_variance) = paths(&LightList::new());
+        assert!(
+            (mean - reference).abs() < 0.05 * reference,
+            "{} {}",
+            mean,
+            reference
+        );
+        assert!(variance < 0.01 * reference_variance, "{} {}", variance, reference_variance);
+
+        // Right below the lamp the irradiance is pi L (r / d)^2,
+        // a diffuse surface reflects albedo / pi of it
+        let expected = 0.5 * 10.0 * (0.3f64 / 2.0).powi(2);
+        let r = Ray::new(Point3(1.0, 1.0, 0.0), Vec3(0.0, -1.0, 0.0));
+        let mut rng = StdRng::seed_from_u64(6);
+        let direct = (0..10_000)
+            .map(|_| ray_color(&world, &lights, &black, &r, 2, &mut rng).luminance() as f64)
+            .sum::<f64>()
+            / 10_000.0;
+        assert!((direct - expected).abs() < 0.02 * expected, "{} {}", direct, expected);
+    }
+
+    #[test]
+    fn glossy_light_sampling_matches_scattering() {
+        // A blurred mirror catching a lamp, where neither sampling
+        // strategy is good everywhere
+        let lamp = Arc::new(DiffuseLight::new(Color(4.0, 4.0, 4.0)));
+        let mut world = HittableVec::new();
+        world.push(Box::new(Sphere::new(
+            Point3(0.0, -1000.0, 0.0),
+            1000.0,
+            Arc::new(Metal::new(Color(0.8, 0.8, 0.8), 0.3)),
+        )));
+        world.push(Box::new(Sphere::new(Point3(1.5, 1.5, 0.0), 0.5, lamp.clone())));
+        let mut lights = LightList::new();
+        lights.push(Box::new(SphereLight::new(Point3(1.5, 1.5, 0.0), 0.5, lamp)));
+        let black = SolidColor::black();
+        let r = Ray::new(Point3(-1.0, 1.0, 0.0), Vec3(1.0, -1.0, 0.0).normalize());
+
+        let paths = |lights: &LightList| {
+            estimate(7, 200_000, |rng| {
+                ray_color(&world, lights, &black, &r, 2, rng)
+            })
+        };
+        let (mean, variance) = paths(&lights);
+        let (reference, reference_variance) = paths(&LightList::new());
+        assert!(
+            (mean - reference).abs() < 0.02 * reference,
+            "{} {}",
+            mean,
+            reference
+        );
+        assert!(variance < 0.5 * reference_variance, "{} {}", variance, reference_variance);
+    }
+
+    #[test]
+    fn smooth_coat_light_sampling_matches_scattering() {
+        // A lamp seen in the mirror coat of a plastic floor that also
+        // lights its diffuse base
+        let lamp = Arc::new(DiffuseLight::new(Color(4.0, 4.0, 4.0)));
+        let plastic = MetallicRoughness::new(Color(0.5, 0.5, 0.5), 0.0, 0.0, Color::zero());
+        let mut world = HittableVec::new();
+        world.push(Box::new(Sphere::new(
+            Point3(0.0, -1000.0, 0.0),
+            1000.0,
+            Arc::new(plastic),
+        )));
+        world.push(Box::new(Sphere::new(Point3(1.5, 1.5, 0.0), 0.5, lamp.clone())));
+        let mut lights = LightList::new();
+        lights.push(Box::new(SphereLight::new(Point3(1.5, 1.5, 0.0), 0.5, lamp)));
+        let black = SolidColor::black();
+        let r = Ray::new(Point3(-1.0, 1.0, 0.0), Vec3(1.0, -1.0, 0.0).normalize());
+
+        let paths = |lights: &LightList| {
+            estimate(10, 200_000, |rng| {
+                ray_color(&world, lights, &black, &r, 2, rng)
+            })
+        };
+        let (mean, variance) = paths(&lights);
+        let (reference, reference_variance) = paths(&LightList::new());
+        assert!(
+            (mean - reference).abs() < 0.02 * reference,
+            "{} {}",
+            mean,
+            reference
+        );
+        assert!(variance < reference_variance, "{} {}", variance, reference_variance);
+    }
+
+    #[test]
+    fn russian_roulette_keeps_the_mean() {
+        let scene = cornell_box();
+        let camera = cornell_box_camera(1.0);
+        let mut rng = StdRng::seed_from_u64(8);
+        let rays: Vec<Ray> = (0..4)
+            .map(|i| camera.get_ray(&mut rng, 0.2 + 0.2 * i as f32, 0.4))
+            .collect();
+        let background = scene.background.as_ref();
+
+        // Mean and squared standard error of a luminance estimator
+        let estimate = |color: &dyn Fn(&Ray, &mut StdRng) -> Color| {
+            let mut rng = StdRng::seed_from_u64(9);
+            let n = 5000;
+            let (mut sum, mut sum_sq) = (0.0, 0.0);
+            for r in rays.iter() {
+                for _ in 0..n {
+                    let l = color(r, &mut rng).luminance() as f64;
+                    sum += l;
+                    sum_sq += l * l;
+                }
+            }
+            let n = (n * rays.len()) as f64;
+            let mean = sum / n;
+            (mean, (sum_sq / n - mean * mean) / n)
+        };
+        let (mean, error) =
+            estimate(&|r, rng| ray_color(&scene.world, &scene.lights, background, r, 50, rng));
+        let (reference, reference_error) =
+            estimate(&|r, rng| trace(&scene.world, &scene.lights, background, r, 50, None, rng));
+        assert!(
+            (mean - reference).abs() < 4.0 * (error + reference_error).sqrt(),
+            "{} {}",
+            mean,
+            reference
+        );
+    }
+
+    #[test]
+    fn glass_in_a_white_furnace() {
+        // Under the same light from everywhere glass can only look as bright
+        // as the light, rough glass a little darker for the light bouncing
+        // between its facets
+        let white = SolidColor::new(Color(1.0, 1.0, 1.0));
+        let mut previous = 1.0;
+        for &(roughness, lowest) in [(0.0, 1.0), (0.3, 0.95), (0.6, 0.7)].iter() {
+            let glass = Arc::new(RoughDielectric::new(1.5, roughness));
+            let mut world = HittableVec::new();
+            world.push(Box::new(Sphere::new(Point3::zero(), 1.0, glass)));
+            let mut rng = StdRng::seed_from_u64(10);
+            let n = 20000;
+            let mean = (0..n)
+                .map(|i| {
+                    // Across the whole sphere, out to its grazing edge
+                    let y = 0.2 * i as f32 / n as f32;
+                    let r = Ray::new(Point3(0.0, 0.0, 5.0), Vec3(0.0, y, -1.0).normalize());
+                    ray_color(&world, &LightList::new(), &white, &r, 50, &mut rng).luminance()
+                })
+                .sum::<f32>()
+                / n as f32;
+            assert!(mean <= previous + 1e-4 && mean >= lowest - 1e-4, "{}", mean);
+            previous = mean;
+        }
+    }
+
+    #[test]
+    fn render_depends_on_seed() {
+        let world = mirror_scene();
+        let a = render(&world, &camera(), &settings(2, 1));
+        let b = render(&world, &camera(), &settings(2, 2));
+        assert_ne!(a, b);
+    }
+}
@@ -0,0 +1,191 @@
+// Piecewise-constant distributions for importance sampling tabulated functions
+
+// Samples x in [0, 1) proportionally to a step function with equal-width steps
+pub struct Distribution1D {
+    func: Vec<f32>,
+    cdf: Vec<f32>,
+    integral: f32,
+}
+
+impl Distribution1D {
+    pub fn new(func: Vec<f32>) -> Self {
+        assert!(!func.is_empty(), "distribution needs at least one value");
+        assert!(
+            func.iter().all(|&f| f >= 0.0 && f.is_finite()),
+            "distribution values must be finite and non-negative"
+        );
+        let n = func.len();
+        let mut cdf = Vec::with_capacity(n + 1);
+        cdf.push(0.0);
+        for (i, &f) in func.iter().enumerate() {
+            cdf.push(cdf[i] + f / n as f32);
+        }
+        let integral = cdf[n];
+        if integral > 0.0 {
+            for c in &mut cdf {
+                *c /= integral;
+            }
+        } else {
+            // Nothing to prefer, fall back to uniform sampling
+            for (i, c) in cdf.iter_mut().enumerate() {
+                *c = i as f32 / n as f32;
+            }
+        }
+        cdf[n] = 1.0;
+        Self {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.func.is_empty()
+    }
+
+    // Integral of the step function over [0, 1]
+    pub fn integral(&self) -> f32 {
+        self.integral
+    }
+
+    // Maps a uniform u in [0, 1) to (x, pdf(x), index of the step holding x)
+    pub fn sample(&self, u: f32) -> (f32, f32, usize) {
+        let n = self.len();
+        // Last step whose cdf starts at or before u, skipping empty steps
+        let offset = (self.cdf.partition_point(|&c| c <= u) - 1).min(n - 1);
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        let du = if width > 0.0 {
+            ((u - self.cdf[offset]) / width).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let x = ((offset as f32 + du) / n as f32).min(1.0 - f32::EPSILON);
+        (x, self.pdf_at(offset), offset)
+    }
+
+    pub fn pdf(&self, x: f32) -> f32 {
+        let n = self.len();
+        self.pdf_at(((x * n as f32) as usize).min(n - 1))
+    }
+
+    fn pdf_at(&self, index: usize) -> f32 {
+        if self.integral > 0.0 {
+            self.func[index] / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+// Samples (u, v) in [0, 1)^2 proportionally to a row-major grid of values,
+// first picking the row v from the marginal and then u within the row
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func: &[f32], width: usize, height: usize) -> Self {
+        assert_eq!(func.len(), width * height, "grid size mismatch");
+        let conditional: Vec<Distribution1D> = func
+            .chunks(width)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|d| d.integral()).collect());
+        Self {
+            conditional,
+            marginal,
+        }
+    }
+
+    pub fn integral(&self) -> f32 {
+        self.marginal.integral()
+    }
+
+    // Returns (u, v) and their joint pdf
+    pub fn sample(&self, u0: f32, u1: f32) -> ((f32, f32), f32) {
+        let (v, pdf_v, row) = self.marginal.sample(u1);
+        let (u, pdf_u, _) = self.conditional[row].sample(u0);
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f32, v: f32) -> f32 {
+        let height = self.conditional.len();
+        let row = ((v * height as f32) as usize).min(height - 1);
+        self.marginal.pdf(v) * self.conditional[row].pdf(u)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn distribution_1d_sample_follows_function() {
+        let d = Distribution1D::new(vec![1.0, 0.0, 3.0]);
+        assert_eq!(d.integral(), 4.0 / 3.0);
+
+        // The empty middle step is never chosen
+        let (x, pdf, index) = d.sample(0.25);
+        assert_eq!(index, 2);
+        assert!((x - 2.0 / 3.0).abs() < 1e-6);
+        assert_eq!(pdf, 2.25);
+
+        let (x, pdf, index) = d.sample(0.125);
+        assert_eq!(index, 0);
+        assert!((x - 1.0 / 6.0).abs() < 1e-6);
+        assert_eq!(pdf, 0.75);
+        assert_eq!(d.pdf(0.5), 0.0);
+
+        let (x, _, index) = d.sample(0.999_999);
+        assert_eq!(index, 2);
+        assert!(x < 1.0);
+    }
+
+    #[test]
+    fn distribution_1d_all_zero_is_uniform() {
+        let d = Distribution1D::new(vec![0.0; 4]);
+        let (x, pdf, index) = d.sample(0.6);
+        assert_eq!((index, pdf), (2, 1.0));
+        assert!((x - 0.6).abs() < 1e-6);
+    }
+
+    #[test]
+    fn distribution_2d_integrates_to_one() {
+        let (width, height) = (7, 5);
+        let func: Vec<f32> = (0..width * height)
+            .map(|i| ((i * 37) % 11) as f32 * 0.5)
+            .collect();
+        let d = Distribution2D::new(&func, width, height);
+
+        // Midpoint rule at the cell centers is exact for a step function
+        let mut total = 0.0;
+        for y in 0..height {
+            for x in 0..width {
+                let (u, v) = (
+                    (x as f32 + 0.5) / width as f32,
+                    (y as f32 + 0.5) / height as f32,
+                );
+                let pdf = d.pdf(u, v);
+                assert!((pdf - func[y * width + x] / d.integral()).abs() < 1e-4);
+                total += pdf / (width * height) as f32;
+            }
+        }
+        assert!((total - 1.0).abs() < 1e-5, "{}", total);
+
+        // Sampled points land in non-empty cells and report the matching pdf
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..1000 {
+            let ((u, v), pdf) = d.sample(rng.gen(), rng.gen());
+            assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+            assert!(pdf > 0.0);
+            assert!((pdf - d.pdf(u, v)).abs() < 1e-4 * pdf);
+        }
+    }
+}
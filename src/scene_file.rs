@@ -0,0 +1,880 @@
+// Line based scene description, one statement per line:
+//
+//   # comment
+//   image 1200 800
+//   samples 500
+//   depth 50
+//   seed 0
+//   threads 0
+//   camera from 13 2 3 at 0 0 0 up 0 1 0 fov 20 aperture 0.1 focus 10 shutter 0 1
+//   background sky | color r g b | gradient r g b r g b | map sky.hdr
+//   texture tiles checker 0.5 0.2 0.3 0.1 0.9 0.9 0.9
+//   texture wood image wood.png [repeat | clamp | mirror]
+//   texture stone marble 4 [seed]
+//   texture clouds noise perlin 2 [fbm 5 | turbulence 5] [colors r g b r g b]
+//   material ground lambertian 0.5 0.5 0.5
+//   material steel metal 0.7 0.6 0.5 0.1
+//   material gilt conductor gold 0.3
+//   material brass conductor 0.44 0.53 1.1 3.7 2.8 2.0 0.3
+//   material glass dielectric 1.5
+//   material frosted rough_dielectric 1.5 0.3
+//   material lamp light 4 4 4
+//   material paint metallic_roughness 0.8 0.1 0.1 0.0 0.5 [emissive r g b]
+//   material rough_ground bump ground clouds 0.05
+//   sphere 0 -1000 0 1000 ground
+//   moving_sphere 0 1 0 0 1.5 0 0.2 ground
+//   triangle 0 0 0 1 0 0 0 1 0 steel
+//   mesh teapot.obj [material]
+//   preset random_scene 0 | cornell_box
+//
+// Camera keywords other than from and at are optional, the focus distance
+// defaults to the distance between them and the shutter to the instant 0.
+// Moving spheres go from the first center at time 0 to the second at time 1.
+// Spheres of light materials are also sampled directly as lights.
+// Conductors are gold, copper, aluminium or silver, or take the complex
+// index of refraction as eta r g b then k r g b, then the roughness.
+// Material and checker colors can also name a texture defined earlier,
+// and so can the metal fuzz and the metallic-roughness, conductor and
+// rough dielectric roughness.
+// Noise is one of perlin, value or worley. Noise textures take their
+// random tables from the scene seed in the order they are defined, so
+// the seed statement goes before them. Marble has its own seed, 0 unless given.
+// Paths are relative to the scene file.
+use crate::background::{Background, EnvironmentMap, Gradient, SolidColor};
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::gltf::load_gltf;
+use crate::hittable_vec::HittableVec;
+use crate::light::{LightList, SphereLight};
+use crate::material::{
+    Bump, Dielectric, DiffuseLight, Lambertian, Metal, MetallicRoughness, RoughConductor,
+    RoughDielectric, Scatterable,
+};
+use crate::noise::{Noise, Perlin, ValueNoise, Worley};
+use crate::obj::load_obj;
+use crate::parse::{parse_token, Number, ParseError};
+use crate::ply::load_ply;
+use crate::point::Point3;
+use crate::render::RenderSettings;
+use crate::scenes::{cornell_box, random_scene, Scene};
+use crate::sphere::{MovingSphere, Sphere};
+use crate::texture::{
+    CheckerTexture, ImageTexture, MarbleTexture, NoisePattern, NoiseTexture, Texture, WrapMode,
+};
+use crate::triangle::Triangle;
+use crate::vector::{Cross, Len, Vec3};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::str::SplitWhitespace;
+use std::sync::Arc;
+
+// Everything needed to render the file
+pub struct SceneFile {
+    pub scene: Scene,
+    pub camera: Camera,
+    pub settings: RenderSettings,
+}
+
+struct CameraSpec {
+    look_from: Point3,
+    look_at: Point3,
+    vup: Vec3,
+    vfov: f32,
+    aperture: f32,
+    focus_dist: Option<f32>,
+    shutter: (f32, f32),
+}
+
+type Textures = HashMap<String, Arc<dyn Texture>>;
+
+// The tokens of one statement
+struct Statement<'a> {
+    tokens: SplitWhitespace<'a>,
+    path: &'a Path,
+    line: usize,
+}
+
+impl<'a> Statement<'a> {
+    fn error<S: Into<String>>(&self, msg: S) -> ParseError {
+        ParseError::new(self.path, self.line, msg)
+    }
+
+    fn parse<T: Number>(&mut self, what: &str) -> Result<T, ParseError> {
+        parse_token(self.tokens.next(), what, self.path, self.line)
+    }
+
+    fn word(&mut self, what: &str) -> Result<&'a str, ParseError> {
+        self.tokens
+            .next()
+            .ok_or_else(|| self.error(format!("missing {}", what)))
+    }
+
+    fn point(&mut self) -> Result<Point3, ParseError> {
+        Ok(Point3(self.parse("x")?, self.parse("y")?, self.parse("z")?))
+    }
+
+    fn vector(&mut self) -> Result<Vec3, ParseError> {
+        Ok(Vec3(self.parse("x")?, self.parse("y")?, self.parse("z")?))
+    }
+
+    fn color(&mut self) -> Result<Color, ParseError> {
+        Ok(Color(
+            self.parse("red")?,
+            self.parse("green")?,
+            self.parse("blue")?,
+        ))
+    }
+
+    // The texture named by the next token unless it is a number
+    fn texture_name(
+        &mut self,
+        textures: &Textures,
+    ) -> Result<Option<Arc<dyn Texture>>, ParseError> {
+        match self.tokens.clone().next() {
+            Some(name) if name.parse::<f32>().is_err() => {
+                self.tokens.next();
+                let texture = textures
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| self.error(format!("unknown texture '{}'", name)))?;
+                Ok(Some(texture))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // A texture name or a constant color
+    fn texture(&mut self, textures: &Textures) -> Result<Arc<dyn Texture>, ParseError> {
+        match self.texture_name(textures)? {
+            Some(texture) => Ok(texture),
+            None => Ok(Arc::new(self.color()?)),
+        }
+    }
+
+    // A texture name or a constant gray
+    fn scalar(&mut self, textures: &Textures, what: &str) -> Result<Arc<dyn Texture>, ParseError> {
+        match self.texture_name(textures)? {
+            Some(texture) => Ok(texture),
+            None => {
+                let value = self.parse(what)?;
+                Ok(Arc::new(Color(value, value, value)))
+            }
+        }
+    }
+
+    // Statements must not have leftover tokens, they are most likely typos
+    fn end(&mut self) -> Result<(), ParseError> {
+        match self.tokens.next() {
+            Some(token) => Err(self.error(format!("unexpected '{}'", token))),
+            None => Ok(()),
+        }
+    }
+}
+
+pub fn load_scene<P: AsRef<Path>>(path: P) -> Result<SceneFile, ParseError> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| ParseError::io(path, e))?;
+    parse_scene(BufReader::new(file), path)
+}
+
+pub fn parse_scene<R: BufRead>(r: R, path: &Path) -> Result<SceneFile, ParseError> {
+    let mut settings = RenderSettings {
+        image_width: 1200,
+        image_height: 800,
+        samples_per_pixel: 500,
+        depth: 50,
+        seed: 0,
+        threads: 0,
+    };
+    let mut camera: Option<CameraSpec> = None;
+    let mut world = HittableVec::new();
+    let mut background: Box<dyn Background> = Box::new(SolidColor::black());
+    let mut textures = Textures::new();
+    let mut noise_rng = StdRng::seed_from_u64(settings.seed);
+    let mut materials: HashMap<String, Arc<dyn Scatterable>> = HashMap::new();
+    // Spheres of these are also sampled as lights
+    let mut light_materials = HashSet::new();
+    let mut lights = LightList::new();
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    for (number, line) in r.lines().enumerate() {
+        let line = line.map_err(|e| ParseError::io(path, e))?;
+        let line = line.split('#').next().unwrap_or("");
+        let mut s = Statement {
+            tokens: line.split_whitespace(),
+            path,
+            line: number + 1,
+        };
+        let keyword = match s.tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        match keyword {
+            "image" => {
+                settings.image_width = s.parse("width")?;
+                settings.image_height = s.parse("height")?;
+                if settings.image_width == 0 || settings.image_height == 0 {
+                    return Err(s.error("the image must not be empty"));
+                }
+            }
+            "samples" => settings.samples_per_pixel = s.parse("samples per pixel")?,
+            "depth" => settings.depth = s.parse("depth")?,
+            "seed" => {
+                settings.seed = s.parse("seed")?;
+                noise_rng = StdRng::seed_from_u64(settings.seed);
+            }
+            "threads" => settings.threads = s.parse("thread count")?,
+            "camera" => camera = Some(parse_camera(&mut s)?),
+            "background" => {
+                background = match s.word("background type")? {
+                    "sky" => Box::new(Gradient::sky()),
+                    "color" => Box::new(SolidColor::new(s.color()?)),
+                    "gradient" => Box::new(Gradient::new(s.color()?, s.color()?)),
+                    "map" => {
+                        let name = s.word("environment map")?;
+                        let map = EnvironmentMap::load(dir.join(name))
+                            .map_err(|e| s.error(format!("cannot load {}: {}", name, e)))?;
+                        Box::new(map)
+                    }
+                    other => return Err(s.error(format!("unknown background '{}'", other))),
+                }
+            }
+            "texture" => {
+                let name = s.word("texture name")?;
+                if textures.contains_key(name) {
+                    return Err(s.error(format!("texture '{}' is already defined", name)));
+                }
+                let texture = parse_texture(&mut s, &textures, &mut noise_rng, dir)?;
+                textures.insert(name.to_string(), texture);
+            }
+            "material" => {
+                let name = s.word("material name")?;
+                if materials.contains_key(name) {
+                    return Err(s.error(format!("material '{}' is already defined", name)));
+                }
+                if s.tokens.clone().next() == Some("light") {
+                    light_materials.insert(name.to_string());
+                }
+                let material = parse_material(&mut s, &textures, &materials)?;
+                materials.insert(name.to_string(), material);
+            }
+            "sphere" => {
+                let center = s.point()?;
+                let radius: f32 = s.parse("radius")?;
+                if radius <= 0.0 {
+                    return Err(s.error("the radius must be positive"));
+                }
+                let is_light = s
+                    .tokens
+                    .clone()
+                    .next()
+                    .is_some_and(|name| light_materials.contains(name));
+                let material = find_material(&materials, &mut s)?;
+                if is_light {
+                    lights.push(Box::new(SphereLight::new(center, radius, material.clone())));
+                }
+                world.push(Box::new(Sphere::new(center, radius, material)));
+            }
+            "moving_sphere" => {
+                let (center0, center1) = (s.point()?, s.point()?);
+                let radius: f32 = s.parse("radius")?;
+                if radius <= 0.0 {
+                    return Err(s.error("the radius must be positive"));
+                }
+                let material = find_material(&materials, &mut s)?;
+                world.push(Box::new(MovingSphere::new(
+                    center0, center1, 0.0, 1.0, radius, material,
+                )));
+            }
+            "triangle" => {
+                let (p0, p1, p2) = (s.point()?, s.point()?, s.point()?);
+                let material = find_material(&materials, &mut s)?;
+                world.push(Box::new(Triangle::new(p0, p1, p2, material)));
+            }
+            "mesh" => {
+                let name = s.word("mesh file")?;
+                let material = match s.tokens.clone().next() {
+                    Some(_) => find_material(&materials, &mut s)?,
+                    None => Arc::new(Lambertian::new(Color(0.5, 0.5, 0.5))),
+                };
+                let file = dir.join(name);
+                // Errors inside the mesh file keep their own location
+                let nested = |e: ParseError| s.error(e.to_string());
+                let extension = file
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .unwrap_or("")
+                    .to_ascii_lowercase();
+                match extension.as_str() {
+                    "obj" => world.push(Box::new(load_obj(&file, material).map_err(nested)?.mesh)),
+                    "ply" => world.push(Box::new(load_ply(&file, material).map_err(nested)?)),
+                    "gltf" | "glb" => {
+                        let gltf = load_gltf(&file, material).map_err(nested)?;
+                        world.append(gltf.world);
+                        // The first camera of the file is used when the scene has none
+                        if let (None, Some(c)) = (&camera, gltf.cameras.first()) {
+                            camera = Some(CameraSpec {
+                                look_from: c.look_from,
+                                look_at: c.look_at,
+                                vup: c.vup,
+                                vfov: c.vfov,
+                                aperture: 0.0,
+                                focus_dist: None,
+                                shutter: (0.0, 0.0),
+                            });
+                        }
+                    }
+                    _ => return Err(s.error(format!("unsupported mesh format '{}'", name))),
+                }
+            }
+            "preset" => {
+                let preset = match s.word("preset name")? {
+                    "random_scene" => random_scene(s.parse("seed")?),
+                    "cornell_box" => cornell_box(),
+                    other => return Err(s.error(format!("unknown preset '{}'", other))),
+                };
+                world.append(preset.world);
+                lights.append(preset.lights);
+                background = preset.background;
+            }
+            other => return Err(s.error(format!("unknown statement '{}'", other))),
+        }
+        s.end()?;
+    }
+
+    let camera = camera.ok_or_else(|| ParseError::new(path, 0, "the scene has no camera"))?;
+    let aspect_ratio = settings.image_width as f32 / settings.image_height as f32;
+    let camera = Camera::new(
+        camera.look_from,
+        camera.look_at,
+        camera.vup,
+        camera.vfov,
+        aspect_ratio,
+        camera.aperture,
+        camera
+            .focus_dist
+            .unwrap_or_else(|| (camera.look_from - camera.look_at).len()),
+    )
+    .with_shutter(camera.shutter.0, camera.shutter.1);
+    Ok(SceneFile {
+        scene: Scene::new(world, background).with_lights(lights),
+        camera,
+        settings,
+    })
+}
+
+fn parse_camera(s: &mut Statement) -> Result<CameraSpec, ParseError> {
+    let (mut look_from, mut look_at) = (None, None);
+    let mut camera = CameraSpec {
+        look_from: Point3::zero(),
+        look_at: Point3::zero(),
+        vup: Vec3(0.0, 1.0, 0.0),
+        vfov: 40.0,
+        aperture: 0.0,
+        focus_dist: None,
+        shutter: (0.0, 0.0),
+    };
+    while let Some(key) = s.tokens.next() {
+        match key {
+            "from" => look_from = Some(s.point()?),
+            "at" => look_at = Some(s.point()?),
+            "up" => camera.vup = s.vector()?,
+            "fov" => camera.vfov = s.parse("field of view")?,
+            "aperture" => camera.aperture = s.parse("aperture")?,
+            "focus" => camera.focus_dist = Some(s.parse("focus distance")?),
+            "shutter" => {
+                camera.shutter = (s.parse("shutter open")?, s.parse("shutter close")?);
+                if camera.shutter.0 > camera.shutter.1 {
+                    return Err(s.error("the shutter must open before it closes"));
+                }
+            }
+            other => return Err(s.error(format!("unknown camera parameter '{}'", other))),
+        }
+    }
+    camera.look_from = look_from.ok_or_else(|| s.error("missing camera position 'from'"))?;
+    camera.look_at = look_at.ok_or_else(|| s.error("missing camera target 'at'"))?;
+    let direction = camera.look_at - camera.look_from;
+    if direction.len() < 1e-6 {
+        return Err(s.error("the camera looks at its own position"));
+    }
+    // A zero up vector is parallel to everything
+    if Vec3::cross(camera.vup, direction).len() <= 1e-6 * camera.vup.len() * direction.len() {
+        return Err(s.error("the camera up vector is parallel to its view direction"));
+    }
+    if !(camera.vfov > 0.0 && camera.vfov < 180.0) {
+        return Err(s.error("the field of view must be between 0 and 180 degrees"));
+    }
+    Ok(camera)
+}
+
+fn parse_texture(
+    s: &mut Statement,
+    textures: &Textures,
+    noise_rng: &mut StdRng,
+    dir: &Path,
+) -> Result<Arc<dyn Texture>, ParseError> {
+    Ok(match s.word("texture type")? {
+        "checker" => {
+            let size: f32 = s.parse("size")?;
+            if size <= 0.0 {
+                return Err(s.error("the checker size must be positive"));
+            }
+            let even = s.texture(textures)?;
+            Arc::new(CheckerTexture::new(size, even, s.texture(textures)?))
+        }
+        "image" => {
+            let name = s.word("image file")?;
+            let wrap = match s.tokens.next() {
+                Some("repeat") | None => WrapMode::Repeat,
+                Some("clamp") => WrapMode::Clamp,
+                Some("mirror") => WrapMode::Mirror,
+                Some(other) => return Err(s.error(format!("unknown wrap mode '{}'", other))),
+            };
+            let texture = ImageTexture::load(dir.join(name), wrap)
+                .map_err(|e| s.error(format!("cannot load {}: {}", name, e)))?;
+            Arc::new(texture)
+        }
+        "marble" => {
+            let scale = s.parse("scale")?;
+            let seed = match s.tokens.clone().next() {
+                Some(_) => s.parse("seed")?,
+                None => 0,
+            };
+            let noise = Arc::new(Perlin::new(&mut StdRng::seed_from_u64(seed)));
+            Arc::new(MarbleTexture::new(noise, scale))
+        }
+        "noise" => {
+            let noise: Arc<dyn Noise> = match s.word("noise type")? {
+                "perlin" => Arc::new(Perlin::new(noise_rng)),
+                "value" => Arc::new(ValueNoise::new(noise_rng)),
+                "worley" => Arc::new(Worley::new(noise_rng)),
+                other => return Err(s.error(format!("unknown noise type '{}'", other))),
+            };
+            let scale = s.parse("scale")?;
+            let mut pattern = NoisePattern::Plain;
+            let mut colors = None;
+            while let Some(key) = s.tokens.next() {
+                match key {
+                    "fbm" | "turbulence" => {
+                        let octaves = s.parse("octaves")?;
+                        if octaves == 0 {
+                            return Err(s.error("octaves must be positive"));
+                        }
+                        pattern = if key == "fbm" {
+                            NoisePattern::Fbm(octaves)
+                        } else {
+                            NoisePattern::Turbulence(octaves)
+                        };
+                    }
+                    "colors" => colors = Some((s.color()?, s.color()?)),
+                    other => return Err(s.error(format!("unknown noise parameter '{}'", other))),
+                }
+            }
+            let texture = NoiseTexture::new(noise, pattern, scale);
+            match colors {
+                Some((low, high)) => Arc::new(texture.with_colors(low, high)),
+                None => Arc::new(texture),
+            }
+        }
+        other => return Err(s.error(format!("unknown texture type '{}'", other))),
+    })
+}
+
+fn parse_material(
+    s: &mut Statement,
+    textures: &Textures,
+    materials: &HashMap<String, Arc<dyn Scatterable>>,
+) -> Result<Arc<dyn Scatterable>, ParseError> {
+    Ok(match s.word("material type")? {
+        "lambertian" => Arc::new(Lambertian::textured(s.texture(textures)?)),
+        "metal" => Arc::new(Metal::textured(
+            s.texture(textures)?,
+            s.scalar(textures, "fuzz")?,
+        )),
+        "conductor" => {
+            let conductor = match s.tokens.clone().next() {
+                Some(name) if name.parse::<f32>().is_err() => {
+                    s.tokens.next();
+                    match name {
+                        "gold" => RoughConductor::gold(0.0),
+                        "copper" => RoughConductor::copper(0.0),
+                        "aluminium" => RoughConductor::aluminium(0.0),
+                        "silver" => RoughConductor::silver(0.0),
+                        other => return Err(s.error(format!("unknown metal '{}'", other))),
+                    }
+                }
+                _ => RoughConductor::new(s.color()?, s.color()?, 0.0),
+            };
+            Arc::new(conductor.with_roughness(s.scalar(textures, "roughness")?))
+        }
+        "dielectric" => Arc::new(Dielectric::new(s.parse("index of refraction")?)),
+        "rough_dielectric" => {
+            let glass = RoughDielectric::new(s.parse("index of refraction")?, 0.0);
+            Arc::new(glass.with_roughness(s.scalar(textures, "roughness")?))
+        }
+        "light" => Arc::new(DiffuseLight::textured(s.texture(textures)?)),
+        "metallic_roughness" => {
+            let base_color = s.texture(textures)?;
+            let metallic = s.parse("metallic")?;
+            let roughness = s.scalar(textures, "roughness")?;
+            let emissive = match s.tokens.next() {
+                Some("emissive") => s.texture(textures)?,
+                Some(other) => return Err(s.error(format!("unexpected '{}'", other))),
+                None => Arc::new(Color::zero()),
+            };
+            Arc::new(MetallicRoughness::textured(
+                base_color, metallic, roughness, emissive,
+            ))
+        }
+        "bump" => {
+            let material = find_material(materials, s)?;
+            let height = s.scalar(textures, "height")?;
+            Arc::new(Bump::new(material, height, s.parse("strength")?))
+        }
+        other => return Err(s.error(format!("unknown material type '{}'", other))),
+    })
+}
+
+fn find_material(
+    materials: &HashMap<String, Arc<dyn Scatterable>>,
+    s: &mut Statement,
+) -> Result<Arc<dyn Scatterable>, ParseError> {
+    let name = s.word("material")?;
+    materials
+        .get(name)
+        .cloned()
+        .ok_or_else(|| s.error(format!("unknown material '{}'", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::ray::Ray;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn parse(text: &str) -> Result<SceneFile, ParseError> {
+        parse_scene(text.as_bytes(), Path::new("test.scene"))
+    }
+
+    #[test]
+    fn scene_file_statements() {
+        let scene_file = parse(
+            "# two spheres under the sky
+            image 64 32
+            samples 4
+            depth 8
+            seed 7
+            threads 2
+
+            camera from 0 0 5 at 0 0 0 fov 30 shutter 0.5 0.5
+            background sky
+            texture tiles checker 2 0.8 0.1 0.1 0.2 0.2 0.2
+            material red lambertian tiles
+            material lamp light 4 4 4
+            material paint metallic_roughness 0.5 0.5 0.5 0 1 emissive 1 2 3
+            sphere 0 0 0 1 red # the subject
+            sphere 0 10 0 2 lamp
+            moving_sphere 0 -10 0 4 -10 0 1 red
+            triangle -1 -1 -3 1 -1 -3 0 1 -3 paint
+            ",
+        )
+        .unwrap();
+        let settings = &scene_file.settings;
+        assert_eq!((settings.image_width, settings.image_height), (64, 32));
+        assert_eq!(settings.samples_per_pixel, 4);
+        assert_eq!(settings.depth, 8);
+        assert_eq!(settings.seed, 7);
+        assert_eq!(settings.threads, 2);
+
+        let world = &scene_file.scene.world;
+        assert_eq!(world.len(), 4);
+        assert_eq!(scene_file.scene.lights.len(), 1);
+        let r = Ray::new(Point3(0.0, 0.0, 5.0), Vec3(0.0, 0.0, -1.0));
+        let (hit, material) = world.hit(&r, 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t() - 4.0).abs() < 1e-5);
+        let mut rng = StdRng::seed_from_u64(0);
+        let (attenuation, _) = material.scatter(&r, &hit, &mut rng).unwrap();
+        assert_eq!(attenuation, Color(0.8, 0.1, 0.1));
+        let r = Ray::new(Point3(0.0, -5.0, 0.0), Vec3(0.0, 1.0, 0.0));
+        let (hit, material) = world.hit(&r, 0.001, f32::INFINITY).unwrap();
+        let (attenuation, _) = material.scatter(&r, &hit, &mut rng).unwrap();
+        assert_eq!(attenuation, Color(0.2, 0.2, 0.2));
+        let r = Ray::new(Point3(0.0, 0.0, -2.0), Vec3(0.0, 0.0, -1.0));
+        let (hit, material) = world.hit(&r, 0.001, f32::INFINITY).unwrap();
+        assert_eq!(material.emitted(&hit), Color(1.0, 2.0, 3.0));
+        // The moving sphere is halfway when the shutter opens
+        let camera_ray = scene_file
+            .camera
+            .get_ray(&mut StdRng::seed_from_u64(0), 0.5, 0.5);
+        assert_eq!(camera_ray.time, 0.5);
+        let r = Ray::new(Point3(2.0, 0.0, 0.0), Vec3(0.0, -1.0, 0.0));
+        assert!(world.hit(&r, 0.001, f32::INFINITY).is_none());
+        assert!(world.hit(&r.with_time(0.5), 0.001, f32::INFINITY).is_some());
+        let up = scene_file.scene.background.color(Vec3(0.0, 1.0, 0.0));
+        assert_eq!(up, Gradient::sky().color(Vec3(0.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn scene_file_noise_follows_the_seed() {
+        let albedo = |seed: u64| {
+            let scene_file = parse(&format!(
+                "seed {}
+                camera from 0 0 5 at 0 0 0
+                texture clouds noise value 3 fbm 4 colors 0 0 0 1 0.5 0.25
+                texture spots noise worley 2
+                material ground lambertian clouds
+                material rough metal 1 1 1 spots
+                material bumpy bump ground clouds 0.1
+                sphere 0 0 0 1 bumpy
+                sphere 0 0 -10 1 rough",
+                seed
+            ))
+            .unwrap();
+            let r = Ray::new(Point3(0.3, 0.2, 5.0), Vec3(0.0, 0.0, -1.0));
+            let (hit, material) = scene_file
+                .scene
+                .world
+                .hit(&r, 0.001, f32::INFINITY)
+                .unwrap();
+            let mut rng = StdRng::seed_from_u64(0);
+            material.scatter(&r, &hit, &mut rng).unwrap().0
+        };
+        let color = albedo(3);
+        assert_eq!(color, albedo(3));
+        assert_ne!(color, albedo(4));
+        assert!((color.g() - color.r() / 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn scene_file_marble_takes_a_seed() {
+        let albedo = |texture: &str| {
+            let scene_file = parse(&format!(
+                "camera from 0 0 5 at 0 0 0
+                texture stone {}
+                material ground lambertian stone
+                sphere 0 0 0 1 ground",
+                texture
+            ))
+            .unwrap();
+            let r = Ray::new(Point3(0.3, 0.2, 5.0), Vec3(0.0, 0.0, -1.0));
+            let (hit, material) = scene_file
+                .scene
+                .world
+                .hit(&r, 0.001, f32::INFINITY)
+                .unwrap();
+            let mut rng = StdRng::seed_from_u64(0);
+            (material.scatter(&r, &hit, &mut rng).unwrap().0, hit.p())
+        };
+        for &(texture, seed) in &[("marble 4", 0), ("marble 4 7", 7)] {
+            let (color, p) = albedo(texture);
+            let noise = Arc::new(Perlin::new(&mut StdRng::seed_from_u64(seed)));
+            assert_eq!(color, MarbleTexture::new(noise, 4.0).value(0.0, 0.0, p));
+        }
+        assert_ne!(albedo("marble 4").0, albedo("marble 4 7").0);
+    }
+
+    #[test]
+    fn scene_file_conductors() {
+        let scene_file = parse(
+            "camera from 0 0 5 at 0 0 0
+            texture scratches noise perlin 4
+            material gilt conductor gold 0
+            material copper conductor 0.2 0.924 1.102 3.912 2.452 2.142 scratches
+            sphere 0 0 0 1 gilt
+            sphere 0 0 -10 1 copper",
+        )
+        .unwrap();
+        let world = &scene_file.scene.world;
+        let mut rng = StdRng::seed_from_u64(0);
+        let r = Ray::new(Point3(0.0, 0.0, 5.0), Vec3(0.0, 0.0, -1.0));
+        let (hit, material) = world.hit(&r, 0.001, f32::INFINITY).unwrap();
+        let (attenuation, scattered) = material.scatter(&r, &hit, &mut rng).unwrap();
+        assert!(attenuation.r() > attenuation.g() && attenuation.g() > attenuation.b());
+        assert!(Vec3::almost_eq(scattered.dir, Vec3(0.0, 0.0, 1.0), 1e-6));
+        assert_eq!(material.pdf(&hit, scattered.dir, -r.dir), 0.0);
+
+        // Rough where the noise is, so lights are sampled there
+        let r = Ray::new(Point3(0.0, 0.0, -5.0), Vec3(0.0, 0.0, -1.0));
+        let (hit, material) = world.hit(&r, 0.001, f32::INFINITY).unwrap();
+        assert!(material.pdf(&hit, -r.dir, -r.dir) > 0.0);
+    }
+
+    #[test]
+    fn scene_file_rough_dielectric() {
+        let scene_file = parse(
+            "camera from 0 0 5 at 0 0 0
+            material frosted rough_dielectric 1.5 0.4
+            sphere 0 0 0 1 frosted",
+        )
+        .unwrap();
+        let r = Ray::new(Point3(0.0, 0.0, 5.0), Vec3(0.0, 0.0, -1.0));
+        let (hit, material) = scene_file
+            .scene
+            .world
+            .hit(&r, 0.001, f32::INFINITY)
+            .unwrap();
+        // Refracts as well as reflects, into a blur around each direction
+        assert!(material.pdf(&hit, r.dir, -r.dir) > 0.0);
+        assert!(material.pdf(&hit, -r.dir, -r.dir) > 0.0);
+        assert!(parse("material frosted rough_dielectric 1.5").is_err());
+    }
+
+    #[test]
+    fn scene_file_presets_and_camera_defaults() {
+        let scene_file = parse(
+            "camera from 0.5 0.5 -1.44 at 0.5 0.5 0
+            preset cornell_box",
+        )
+        .unwrap();
+        assert_eq!(scene_file.settings.image_width, 1200);
+        assert_eq!(scene_file.scene.world.len(), cornell_box().world.len());
+        assert_eq!(scene_file.scene.lights.len(), 1);
+        assert_eq!(
+            scene_file.scene.background.color(Vec3(0.0, 1.0, 0.0)),
+            Color::zero()
+        );
+    }
+
+    #[test]
+    fn scene_file_loads_meshes_relative_to_the_scene() {
+        let dir = std::env::temp_dir().join("raytracer_scene_file_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("quad.obj"),
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("broken.obj"), "v 0 0 0\nf 1 1\n").unwrap();
+        let scene = dir.join("scene.txt");
+        std::fs::write(&scene, "camera from 0 0 1 at 0 0 0\nmesh quad.obj\n").unwrap();
+        let loaded = load_scene(&scene).map(|s| s.scene.world.len());
+        std::fs::write(&scene, "camera from 0 0 1 at 0 0 0\n\nmesh broken.obj\n").unwrap();
+        let broken = load_scene(&scene).err().map(|e| e.to_string());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded.unwrap(), 1);
+        assert_eq!(
+            broken.unwrap(),
+            format!(
+                "{}:3: {}:2: a face needs at least 3 vertices",
+                scene.display(),
+                dir.join("broken.obj").display()
+            )
+        );
+    }
+
+    #[test]
+    fn scene_file_errors_point_at_the_line() {
+        let camera = "camera from 0 0 1 at 0 0 0";
+        let cases = [
+            (
+                "frobnicate 3",
+                "test.scene:1: unknown statement 'frobnicate'",
+            ),
+            ("image 100", "test.scene:1: missing height"),
+            ("image 100 x", "test.scene:1: invalid height 'x'"),
+            ("samples 4 8", "test.scene:1: unexpected '8'"),
+            (
+                "camera at 0 0 0",
+                "test.scene:1: missing camera position 'from'",
+            ),
+            (
+                "camera from 0 0 1 at 0 0 0 zoom 2",
+                "test.scene:1: unknown camera parameter 'zoom'",
+            ),
+            (
+                "\n\nsphere 0 0 0 1 red",
+                "test.scene:3: unknown material 'red'",
+            ),
+            (
+                "sphere 0 0 0 -1 red",
+                "test.scene:1: the radius must be positive",
+            ),
+            (
+                "material a metal 1 1 1 0\nmaterial a dielectric 1.5",
+                "test.scene:2: material 'a' is already defined",
+            ),
+            (
+                "material a plastic",
+                "test.scene:1: unknown material type 'plastic'",
+            ),
+            (
+                "material a conductor brass 0.1",
+                "test.scene:1: unknown metal 'brass'",
+            ),
+            (
+                "background plaid",
+                "test.scene:1: unknown background 'plaid'",
+            ),
+            (
+                "mesh teapot.3ds",
+                "test.scene:1: unsupported mesh format 'teapot.3ds'",
+            ),
+            ("preset teapot", "test.scene:1: unknown preset 'teapot'"),
+            (
+                "material a lambertian wood",
+                "test.scene:1: unknown texture 'wood'",
+            ),
+            (
+                "texture a image wood.png spiral",
+                "test.scene:1: unknown wrap mode 'spiral'",
+            ),
+            (
+                "texture a checker 0 1 1 1 0 0 0",
+                "test.scene:1: the checker size must be positive",
+            ),
+            (
+                "texture a noise simplex 2",
+                "test.scene:1: unknown noise type 'simplex'",
+            ),
+            (
+                "texture a noise perlin 2 fbm 0",
+                "test.scene:1: octaves must be positive",
+            ),
+            ("image 0 10", "test.scene:1: the image must not be empty"),
+            (
+                "camera from 1 2 3 at 1 2 3",
+                "test.scene:1: the camera looks at its own position",
+            ),
+            (
+                "camera from 0 1 0 at 0 0 0",
+                "test.scene:1: the camera up vector is parallel to its view direction",
+            ),
+            (
+                "camera from 0 0 1 at 0 0 0 up 0 0 0",
+                "test.scene:1: the camera up vector is parallel to its view direction",
+            ),
+            (
+                "camera from 0 0 1 at 0 0 0 fov 180",
+                "test.scene:1: the field of view must be between 0 and 180 degrees",
+            ),
+            (
+                "camera from 0 0 1 at 0 0 0 fov -5",
+                "test.scene:1: the field of view must be between 0 and 180 degrees",
+            ),
+            (
+                "camera from 0 0 nan at 0 0 0",
+                "test.scene:1: invalid z 'nan'",
+            ),
+            (
+                "camera from 0 0 1 at 0 0 0 shutter 1 0",
+                "test.scene:1: the shutter must open before it closes",
+            ),
+        ];
+        for (text, message) in cases.iter() {
+            // The camera comes last so it does not shift the line numbers
+            let text = format!("{}\n{}", text, camera);
+            assert_eq!(parse(&text).err().unwrap().to_string(), *message);
+        }
+        assert_eq!(
+            parse("samples 4").err().unwrap().to_string(),
+            "test.scene: the scene has no camera"
+        );
+    }
+}
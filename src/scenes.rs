@@ -0,0 +1,142 @@
+use crate::background::{Background, Gradient, SolidColor};
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::hittable_vec::HittableVec;
+use crate::light::{LightList, SphereLight};
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal};
+use crate::point::Point3;
+use crate::sphere::Sphere;
+use crate::vector::{Len, Vec3};
+use rand::distributions::Distribution;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::Arc;
+
+// Everything a render needs besides the camera and the settings
+pub struct Scene {
+    pub world: HittableVec,
+    // Emitters of the world that are sampled directly
+    pub lights: LightList,
+    pub background: Box<dyn Background>,
+}
+
+impl Scene {
+    pub fn new(world: HittableVec, background: Box<dyn Background>) -> Self {
+        Self {
+            world,
+            lights: LightList::new(),
+            background,
+        }
+    }
+
+    pub fn with_lights(mut self, lights: LightList) -> Self {
+        self.lights = lights;
+        self
+    }
+}
+
+// The same seed always produces the same scene
+pub fn random_scene(seed: u64) -> Scene {
+    let mut world = HittableVec::new();
+
+    world.push(Box::new(Sphere::new(
+        Point3(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::new(Color(0.5, 0.5, 0.5))),
+    )));
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let distribution = rand::distributions::Standard;
+    for a in -11..11 {
+        for b in -11..11 {
+            let a = a as f32;
+            let b = b as f32;
+            let choose_mat: f32 = distribution.sample(&mut rng);
+            let (dx, dy): (f32, f32) =
+                (distribution.sample(&mut rng), distribution.sample(&mut rng));
+            let center = Point3(a + 0.9 * dx, 0.2, b + 0.9 * dy);
+            if (center - Point3(4.0, 0.2, 0.0)).len() > 0.9 {
+                if choose_mat < 0.8 {
+                    // diffuse
+                    let albedo = Color::random(&mut rng) * Color::random(&mut rng);
+                    let material = Arc::new(Lambertian::new(albedo));
+                    world.push(Box::new(Sphere::new(center, 0.2, material)));
+                } else if choose_mat < 0.95 {
+                    // metal
+                    let albedo = Color::random_minmax(&mut rng, 0.5, 1.0);
+                    let fuzz = rng.gen_range(0.0..0.5);
+                    let material = Arc::new(Metal::new(albedo, fuzz));
+                    world.push(Box::new(Sphere::new(center, 0.2, material)));
+                } else {
+                    // glass
+                    let material = Arc::new(Dielectric::new(1.5));
+                    world.push(Box::new(Sphere::new(center, 0.2, material)));
+                }
+            }
+        }
+    }
+
+    world.push(Box::new(Sphere::new(
+        Point3(0.0, 1.0, 0.0),
+        1.0,
+        Arc::new(Dielectric::new(1.5)),
+    )));
+    world.push(Box::new(Sphere::new(
+        Point3(-4.0, 1.0, 0.0),
+        1.0,
+        Arc::new(Lambertian::new(Color(0.4, 0.2, 0.1))),
+    )));
+    world.push(Box::new(Sphere::new(
+        Point3(4.0, 1.0, 0.0),
+        1.0,
+        Arc::new(Metal::new(Color(0.7, 0.6, 0.5), 0.0)),
+    )));
+    Scene::new(world, Box::new(Gradient::sky()))
+}
+
+// Unit sized Cornell box lit only by a glowing sphere sunk into the ceiling.
+// The walls are huge spheres, so they are flat to the eye.
+pub fn cornell_box() -> Scene {
+    let mut world = HittableVec::new();
+
+    let red = Arc::new(Lambertian::new(Color(0.65, 0.05, 0.05)));
+    let white = Arc::new(Lambertian::new(Color(0.73, 0.73, 0.73)));
+    let green = Arc::new(Lambertian::new(Color(0.12, 0.45, 0.15)));
+    let light = Arc::new(DiffuseLight::new(Color(12.0, 12.0, 12.0)));
+
+    let r = 100.0;
+    let walls = [
+        (Point3(-r, 0.5, 0.5), green as Arc<_>),
+        (Point3(1.0 + r, 0.5, 0.5), red),
+        (Point3(0.5, -r, 0.5), white.clone()),
+        (Point3(0.5, 1.0 + r, 0.5), white.clone()),
+        (Point3(0.5, 0.5, 1.0 + r), white.clone()),
+    ];
+    for (center, material) in walls.iter() {
+        world.push(Box::new(Sphere::new(*center, r, material.clone())));
+    }
+
+    let lamp = (Point3(0.5, 1.08, 0.5), 0.15);
+    world.push(Box::new(Sphere::new(lamp.0, lamp.1, light.clone())));
+    let mut lights = LightList::new();
+    lights.push(Box::new(SphereLight::new(lamp.0, lamp.1, light)));
+    world.push(Box::new(Sphere::new(Point3(0.3, 0.18, 0.35), 0.18, white)));
+    world.push(Box::new(Sphere::new(
+        Point3(0.7, 0.18, 0.6),
+        0.18,
+        Arc::new(Dielectric::new(1.5)),
+    )));
+    Scene::new(world, Box::new(SolidColor::black())).with_lights(lights)
+}
+
+pub fn cornell_box_camera(aspect_ratio: f32) -> Camera {
+    Camera::new(
+        Point3(0.5, 0.5, -1.44),
+        Point3(0.5, 0.5, 0.0),
+        Vec3(0.0, 1.0, 0.0),
+        40.0,
+        aspect_ratio,
+        0.0,
+        1.0,
+    )
+}
@@ -1,18 +1,20 @@
+use crate::aabb::Aabb;
 use crate::hittable::{Hit, Hittable};
 use crate::material::Scatterable;
 use crate::point::Point3;
 use crate::ray::Ray;
 use crate::vector::{Dot, Len, Normalize, Vec3};
-use std::rc::Rc;
+use std::f32::consts::PI;
+use std::sync::Arc;
 
 pub struct Sphere {
     pub center: Point3,
     pub radius: f32,
-    pub material: Rc<dyn Scatterable>,
+    pub material: Arc<dyn Scatterable>,
 }
 
 impl Sphere {
-    pub fn new(center: Point3, radius: f32, material: Rc<dyn Scatterable>) -> Sphere {
+    pub fn new(center: Point3, radius: f32, material: Arc<dyn Scatterable>) -> Sphere {
         Sphere {
             center,
             radius,
@@ -21,39 +23,176 @@ impl Sphere {
     }
 }
 
+// Nearest intersection with the sphere in [t_min, t_max]
+pub(crate) fn hit_sphere(
+    center: Point3,
+    radius: f32,
+    ray: &Ray,
+    t_min: f32,
+    t_max: f32,
+) -> Option<Hit> {
+    let oc = ray.orig - center;
+    let a = Vec3::len_squared(ray.dir);
+    let half_b = Vec3::dot(oc, ray.dir);
+    let c = Vec3::len_squared(oc) - radius * radius;
+    // computing a discriminant
+    #[allow(clippy::suspicious_operation_groupings)]
+    // suspend lint for the operation: was triggered by half_b * half_b
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    // Find the nearest root that lies in the acceptable range.
+    let sqrt_d = discriminant.sqrt();
+    let mut root = (-half_b - sqrt_d) / a;
+    if root < t_min || t_max < root {
+        root = (-half_b + sqrt_d) / a;
+        if root < t_min || t_max < root {
+            return None;
+        }
+    }
+
+    let p = ray.at(root);
+    let (u, v) = sphere_uv(Vec3::normalize(p - center));
+    Some(Hit::new(ray.dir, p, Vec3::normalize((p - center) / radius), root).with_uv(u, v))
+}
+
+// Longitude and latitude of a point on the unit sphere, both in [0, 1].
+// v goes up from the -y pole and u around from -x through +z.
+fn sphere_uv(d: Vec3) -> (f32, f32) {
+    let theta = f32::acos((-d.y()).clamp(-1.0, 1.0));
+    let phi = f32::atan2(-d.z(), d.x()) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+fn sphere_bounding_box(center: Point3, radius: f32) -> Aabb {
+    let r = radius.abs();
+    let r = Vec3(r, r, r);
+    Aabb::new(center - r, center + r)
+}
+
 impl Hittable for Sphere {
     fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(Hit, &dyn Scatterable)> {
-        let oc = ray.orig - self.center;
-        let a = Vec3::len_squared(ray.dir);
-        let half_b = Vec3::dot(oc, ray.dir);
-        let c = Vec3::len_squared(oc) - self.radius * self.radius;
-        // computing a discriminant
-        #[allow(clippy::suspicious_operation_groupings)]
-        // suspend lint for the operation: was triggered by half_b * half_b
-        let discriminant = half_b * half_b - a * c;
-        if discriminant < 0.0 {
-            return None;
+        let hit = hit_sphere(self.center, self.radius, ray, t_min, t_max)?;
+        Some((hit, &*self.material))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        sphere_bounding_box(self.center, self.radius)
+    }
+}
+
+// A sphere moving in a straight line from center0 at time0 to center1
+// at time1. It rests at the end points outside of that interval.
+pub struct MovingSphere {
+    pub center0: Point3,
+    pub center1: Point3,
+    pub time0: f32,
+    pub time1: f32,
+    pub radius: f32,
+    pub material: Arc<dyn Scatterable>,
+}
+
+impl MovingSphere {
+    pub fn new(
+        center0: Point3,
+        center1: Point3,
+        time0: f32,
+        time1: f32,
+        radius: f32,
+        material: Arc<dyn Scatterable>,
+    ) -> MovingSphere {
+        assert!(time0 <= time1, "time0 must not exceed time1");
+        MovingSphere {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material,
         }
+    }
 
-        // Find the nearest root that lies in the acceptable range.
-        let sqrt_d = discriminant.sqrt();
-        let mut root = (-half_b - sqrt_d) / a;
-        if root < t_min || t_max < root {
-            root = (-half_b + sqrt_d) / a;
-            if root < t_min || t_max < root {
-                return None;
-            }
+    pub fn center(&self, time: f32) -> Point3 {
+        if self.time0 == self.time1 {
+            return self.center0;
         }
+        let t = ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0);
+        self.center0 + t * (self.center1 - self.center0)
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(Hit, &dyn Scatterable)> {
+        let hit = hit_sphere(self.center(ray.time), self.radius, ray, t_min, t_max)?;
+        Some((hit, &*self.material))
+    }
+
+    // Covers the whole path
+    fn bounding_box(&self) -> Aabb {
+        sphere_bounding_box(self.center0, self.radius)
+            .surrounding(sphere_bounding_box(self.center1, self.radius))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn moving_sphere_follows_the_ray_time() {
+        let sphere = MovingSphere::new(
+            Point3(0.0, 0.0, -5.0),
+            Point3(2.0, 0.0, -5.0),
+            0.0,
+            1.0,
+            0.5,
+            Arc::new(Lambertian::new(Color(0.5, 0.5, 0.5))),
+        );
+        assert_eq!(sphere.center(0.5), Point3(1.0, 0.0, -5.0));
+        assert_eq!(sphere.center(2.0), Point3(2.0, 0.0, -5.0));
+
+        let r = Ray::new(Point3(1.0, 0.0, 0.0), Vec3(0.0, 0.0, -1.0));
+        assert!(sphere.hit(&r, 0.001, f32::INFINITY).is_none());
+        let (hit, _) = sphere.hit(&r.with_time(0.5), 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t() - 4.5).abs() < 1e-5);
+        assert_eq!(hit.n(), Vec3(0.0, 0.0, 1.0));
+
+        assert_eq!(
+            sphere.bounding_box(),
+            Aabb::new(Point3(-0.5, -0.5, -5.5), Point3(2.5, 0.5, -4.5))
+        );
+    }
 
-        let p = ray.at(root);
-        Some((
-            Hit::new(
-                ray.dir,
-                p,
-                Vec3::normalize((p - self.center) / self.radius),
-                root,
-            ),
-            &*self.material,
-        ))
+    #[test]
+    fn sphere_uvs_are_longitude_and_latitude() {
+        let sphere = Sphere::new(
+            Point3(0.0, 0.0, -5.0),
+            2.0,
+            Arc::new(Lambertian::new(Color(0.5, 0.5, 0.5))),
+        );
+        let uv_from = |orig: Point3, dir: Vec3| {
+            let (hit, _) = sphere
+                .hit(&Ray::new(orig, dir), 0.001, f32::INFINITY)
+                .unwrap();
+            hit.uv()
+        };
+        let close = |(u, v): (f32, f32), (eu, ev): (f32, f32)| {
+            (u - eu).abs() < 1e-5 && (v - ev).abs() < 1e-5
+        };
+        // Hit from +z, +x, -x, then the poles
+        let uv = uv_from(Point3::zero(), Vec3(0.0, 0.0, -1.0));
+        assert!(close(uv, (0.25, 0.5)), "{:?}", uv);
+        let uv = uv_from(Point3(5.0, 0.0, -5.0), Vec3(-1.0, 0.0, 0.0));
+        assert!(close(uv, (0.5, 0.5)), "{:?}", uv);
+        let uv = uv_from(Point3(-5.0, 0.0, -5.0), Vec3(1.0, 0.0, 0.0));
+        assert!(close(uv, (0.0, 0.5)) || close(uv, (1.0, 0.5)), "{:?}", uv);
+        let uv = uv_from(Point3(0.0, 5.0, -5.0), Vec3(0.0, -1.0, 0.0));
+        assert!((uv.1 - 1.0).abs() < 1e-5);
+        let uv = uv_from(Point3(0.0, -5.0, -5.0), Vec3(0.0, 1.0, 0.0));
+        assert!(uv.1.abs() < 1e-5);
     }
 }
@@ -0,0 +1,310 @@
+use crate::color::Color;
+use crate::image::Framebuffer;
+use crate::noise::{scaled, Noise};
+use crate::point::Point3;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+// A color that varies over a surface, looked up by the texture
+// coordinates and the position of the hit
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f32, v: f32, p: Point3) -> Color;
+}
+
+// A plain color is a texture that is the same everywhere
+impl Texture for Color {
+    fn value(&self, _u: f32, _v: f32, _p: Point3) -> Color {
+        *self
+    }
+}
+
+// Cubes of size alternating between two textures through space,
+// so the pattern does not depend on the surface parameterization
+pub struct CheckerTexture {
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+    size: f32,
+}
+
+impl CheckerTexture {
+    pub fn new(size: f32, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        assert!(size > 0.0, "size must be positive");
+        Self { even, odd, size }
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f32, v: f32, p: Point3) -> Color {
+        let cell = |x: f32| (x / self.size).floor() as i64;
+        if (cell(p.x()) + cell(p.y()) + cell(p.z())).rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+// How texture coordinates outside of [0, 1] are brought back in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl WrapMode {
+    fn texel(self, i: i64, size: usize) -> usize {
+        let size = size as i64;
+        let i = match self {
+            WrapMode::Repeat => i.rem_euclid(size),
+            WrapMode::Clamp => i.clamp(0, size - 1),
+            WrapMode::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i < size {
+                    i
+                } else {
+                    2 * size - 1 - i
+                }
+            }
+        };
+        i as usize
+    }
+
+    // Moves t by whole periods of the wrap into [0, 2], so texel indices
+    // stay small. Coordinates that are not finite read the origin.
+    fn coordinate(self, t: f32) -> f32 {
+        if !t.is_finite() {
+            return 0.0;
+        }
+        match self {
+            WrapMode::Repeat => t.rem_euclid(1.0),
+            WrapMode::Clamp => t.clamp(0.0, 1.0),
+            WrapMode::Mirror => t.rem_euclid(2.0),
+        }
+    }
+}
+
+// Bilinearly filtered image, v = 0 is the bottom row
+pub struct ImageTexture {
+    image: Framebuffer<Color>,
+    wrap: WrapMode,
+}
+
+impl ImageTexture {
+    pub fn new(image: Framebuffer<Color>, wrap: WrapMode) -> Self {
+        assert!(
+            image.width() > 0 && image.height() > 0,
+            "image must not be empty"
+        );
+        Self { image, wrap }
+    }
+
+    // Any format Framebuffer::load reads, LDR images are linearized
+    pub fn load<P: AsRef<Path>>(path: P, wrap: WrapMode) -> io::Result<Self> {
+        let image = Framebuffer::<Color>::load(path)?;
+        if image.width() == 0 || image.height() == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the image is empty",
+            ));
+        }
+        Ok(Self::new(image, wrap))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f32, v: f32, _p: Point3) -> Color {
+        let (width, height) = (self.image.width(), self.image.height());
+        let (u, v) = (self.wrap.coordinate(u), self.wrap.coordinate(v));
+        // Texel centers are at half integer coordinates
+        let x = u * width as f32 - 0.5;
+        let y = (1.0 - v) * height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let (x1, y1) = (
+            self.wrap.texel(x0 + 1, width),
+            self.wrap.texel(y0 + 1, height),
+        );
+        let (x0, y0) = (self.wrap.texel(x0, width), self.wrap.texel(y0, height));
+
+        let top = Color::lerp(*self.image.get(x0, y0), *self.image.get(x1, y0), tx);
+        let bottom = Color::lerp(*self.image.get(x0, y1), *self.image.get(x1, y1), tx);
+        Color::lerp(top, bottom, ty)
+    }
+}
+
+// How a noise generator is turned into a value in [0, 1]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoisePattern {
+    Plain,
+    Fbm(u32),
+    Turbulence(u32),
+}
+
+// Noise mapped onto a ramp from one color to another. As a gray ramp it
+// also serves as a roughness or bump height map.
+pub struct NoiseTexture {
+    noise: Arc<dyn Noise>,
+    pattern: NoisePattern,
+    // Frequency of the noise in world space
+    scale: f32,
+    low: Color,
+    high: Color,
+}
+
+impl NoiseTexture {
+    // Black to white
+    pub fn new(noise: Arc<dyn Noise>, pattern: NoisePattern, scale: f32) -> Self {
+        Self {
+            noise,
+            pattern,
+            scale,
+            low: Color::zero(),
+            high: Color(1.0, 1.0, 1.0),
+        }
+    }
+
+    pub fn with_colors(mut self, low: Color, high: Color) -> Self {
+        self.low = low;
+        self.high = high;
+        self
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f32, _v: f32, p: Point3) -> Color {
+        let p = scaled(p, self.scale);
+        let t = match self.pattern {
+            NoisePattern::Plain => 0.5 * (1.0 + self.noise.noise(p)),
+            NoisePattern::Fbm(octaves) => 0.5 * (1.0 + self.noise.fbm(p, octaves)),
+            NoisePattern::Turbulence(octaves) => self.noise.turbulence(p, octaves),
+        };
+        Color::lerp(self.low, self.high, t.clamp(0.0, 1.0))
+    }
+}
+
+// Veins of turbulence running across z, as in marble
+pub struct MarbleTexture {
+    noise: Arc<dyn Noise>,
+    scale: f32,
+}
+
+impl MarbleTexture {
+    pub fn new(noise: Arc<dyn Noise>, scale: f32) -> Self {
+        Self { noise, scale }
+    }
+}
+
+impl Texture for MarbleTexture {
+    fn value(&self, _u: f32, _v: f32, p: Point3) -> Color {
+        let p = scaled(p, self.scale);
+        // The plain sum of the octaves, turbulence divides by their weights
+        let turbulence = self.noise.turbulence(p, 7) * (2.0 - 0.5f32.powi(6));
+        let gray = 0.5 * (1.0 + f32::sin(p.z() + 10.0 * turbulence));
+        Color(gray, gray, gray)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::{Perlin, Worley};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn checker_alternates_through_space() {
+        let black = Arc::new(Color::zero());
+        let white = Arc::new(Color(1.0, 1.0, 1.0));
+        let checker = CheckerTexture::new(2.0, white, black);
+        let at = |x, y, z| checker.value(0.0, 0.0, Point3(x, y, z));
+        assert_eq!(at(0.5, 0.5, 0.5), Color(1.0, 1.0, 1.0));
+        assert_eq!(at(2.5, 0.5, 0.5), Color::zero());
+        assert_eq!(at(-0.5, 0.5, 0.5), Color::zero());
+        assert_eq!(at(-0.5, -0.5, 0.5), Color(1.0, 1.0, 1.0));
+        assert_eq!(at(2.5, 2.5, 2.5), Color::zero());
+    }
+
+    #[test]
+    fn image_texture_filters_and_wraps() {
+        // Black on the left, white on the right, a red top row
+        let image = Framebuffer::from_pixels(
+            2,
+            2,
+            vec![
+                Color(1.0, 0.0, 0.0),
+                Color(1.0, 0.0, 0.0),
+                Color::zero(),
+                Color(1.0, 1.0, 1.0),
+            ],
+        );
+        let texture = ImageTexture::new(image.clone(), WrapMode::Clamp);
+        let at = |texture: &ImageTexture, u, v| texture.value(u, v, Point3::zero());
+        assert_eq!(at(&texture, 0.25, 0.75), Color(1.0, 0.0, 0.0));
+        assert_eq!(at(&texture, 0.25, 0.25), Color::zero());
+        assert_eq!(at(&texture, 0.5, 0.25), Color(0.5, 0.5, 0.5));
+        assert_eq!(at(&texture, 0.0, 0.0), Color::zero());
+        assert_eq!(at(&texture, 1.5, 0.25), Color(1.0, 1.0, 1.0));
+
+        // Repeating blends the edges, mirroring does not
+        let repeat = ImageTexture::new(image.clone(), WrapMode::Repeat);
+        assert_eq!(at(&repeat, 0.0, 0.25), Color(0.5, 0.5, 0.5));
+        assert_eq!(at(&repeat, 1.25, 0.25), Color::zero());
+        let mirror = ImageTexture::new(image.clone(), WrapMode::Mirror);
+        assert_eq!(at(&mirror, 0.0, 0.25), Color::zero());
+        assert_eq!(at(&mirror, 1.25, 0.25), Color(1.0, 1.0, 1.0));
+
+        // Far away coordinates wrap like near ones
+        assert_eq!(at(&repeat, 1e6 + 0.25, 0.25), Color::zero());
+        assert_eq!(at(&mirror, -1e6 - 0.75, 0.25), Color(1.0, 1.0, 1.0));
+        assert_eq!(at(&texture, 1e30, -1e30), Color(1.0, 1.0, 1.0));
+        for wrap in [WrapMode::Repeat, WrapMode::Clamp, WrapMode::Mirror].iter() {
+            let texture = ImageTexture::new(image.clone(), *wrap);
+            for &u in &[f32::NAN, f32::INFINITY, f32::NEG_INFINITY, f32::MAX] {
+                let c = at(&texture, u, u);
+                assert!(c.r().is_finite() && c.g().is_finite() && c.b().is_finite());
+            }
+        }
+    }
+
+    #[test]
+    fn marble_is_gray_and_deterministic() {
+        let noise: Arc<dyn Noise> = Arc::new(Perlin::new(&mut StdRng::seed_from_u64(3)));
+        let marble = MarbleTexture::new(noise.clone(), 4.0);
+        let again = MarbleTexture::new(noise, 4.0);
+        for i in 0..100 {
+            let p = Point3(i as f32 * 0.37, i as f32 * -0.11, i as f32 * 0.05);
+            let c = marble.value(0.0, 0.0, p);
+            assert_eq!(c, again.value(0.0, 0.0, p));
+            assert!(c.r() == c.g() && c.g() == c.b());
+            assert!((0.0..=1.0).contains(&c.r()));
+        }
+    }
+
+    #[test]
+    fn noise_texture_ramps_between_colors() {
+        let low = Color(0.1, 0.2, 0.3);
+        let high = Color(0.9, 0.5, 0.4);
+        let worley = Arc::new(Worley::new(&mut StdRng::seed_from_u64(4)));
+        for &pattern in [
+            NoisePattern::Plain,
+            NoisePattern::Fbm(5),
+            NoisePattern::Turbulence(5),
+        ]
+        .iter()
+        {
+            let texture = NoiseTexture::new(worley.clone(), pattern, 3.0).with_colors(low, high);
+            for i in 0..100 {
+                let p = Point3(i as f32 * 0.37, i as f32 * -0.11, i as f32 * 0.05);
+                let c = texture.value(0.0, 0.0, p);
+                for (channel, (l, h)) in
+                    [(c.r(), (low.r(), high.r())), (c.b(), (low.b(), high.b()))].iter()
+                {
+                    assert!(l.min(*h) - 1e-6 <= *channel && *channel <= l.max(*h) + 1e-6);
+                }
+            }
+        }
+    }
+}
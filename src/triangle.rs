@@ -0,0 +1,267 @@
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, Hittable};
+use crate::material::Scatterable;
+use crate::point::Point3;
+use crate::ray::Ray;
+use crate::vector::{Cross, Dot, Len, Normalize, Vec3};
+use std::sync::Arc;
+
+// Rays more parallel to the plane than this miss the triangle
+const PARALLEL_EPSILON: f32 = 1e-8;
+
+pub struct Triangle {
+    pub vertices: [Point3; 3],
+    // Per-vertex shading normals, the face normal is used without them
+    pub normals: Option<[Vec3; 3]>,
+    // Per-vertex texture coordinates, barycentrics are used without them
+    pub uvs: Option<[(f32, f32); 3]>,
+    pub material: Arc<dyn Scatterable>,
+}
+
+impl Triangle {
+    pub fn new(p0: Point3, p1: Point3, p2: Point3, material: Arc<dyn Scatterable>) -> Triangle {
+        Triangle {
+            vertices: [p0, p1, p2],
+            normals: None,
+            uvs: None,
+            material,
+        }
+    }
+
+    pub fn with_normals(mut self, normals: [Vec3; 3]) -> Triangle {
+        self.normals = Some(normals);
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: [(f32, f32); 3]) -> Triangle {
+        self.uvs = Some(uvs);
+        self
+    }
+}
+
+// Möller–Trumbore ray/triangle intersection.
+// Returns t and the barycentric weights of p1 and p2.
+pub fn intersect_triangle(
+    ray: &Ray,
+    [p0, p1, p2]: [Point3; 3],
+    t_min: f32,
+    t_max: f32,
+) -> Option<(f32, f32, f32)> {
+    let e1 = p1 - p0;
+    let e2 = p2 - p0;
+    let pvec = Vec3::cross(ray.dir, e2);
+    let det = Vec3::dot(e1, pvec);
+    // Also rejects degenerate triangles, whose edges are parallel
+    if det.abs() < PARALLEL_EPSILON {
+        return None;
+    }
+    let inv_det = det.recip();
+
+    let tvec = ray.orig - p0;
+    let b1 = Vec3::dot(tvec, pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let qvec = Vec3::cross(tvec, e1);
+    let b2 = Vec3::dot(ray.dir, qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+    let t = Vec3::dot(e2, qvec) * inv_det;
+    if t < t_min || t_max < t {
+        return None;
+    }
+    Some((t, b1, b2))
+}
+
+// Interpolates per-vertex attributes with barycentric weights
+pub fn interpolate_normal([n0, n1, n2]: [Vec3; 3], b1: f32, b2: f32) -> Vec3 {
+    (1.0 - b1 - b2) * n0 + b1 * n1 + b2 * n2
+}
+
+pub fn interpolate_uv([uv0, uv1, uv2]: [(f32, f32); 3], b1: f32, b2: f32) -> (f32, f32) {
+    let b0 = 1.0 - b1 - b2;
+    (
+        b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0,
+        b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1,
+    )
+}
+
+// Shading normal for a hit, kept on the same side as the face normal
+pub fn shading_normal(face_normal: Vec3, normals: Option<[Vec3; 3]>, b1: f32, b2: f32) -> Vec3 {
+    let n = match normals {
+        Some(normals) => interpolate_normal(normals, b1, b2),
+        None => return face_normal,
+    };
+    if n.len_squared() < 1e-12 {
+        return face_normal;
+    }
+    let n = n.normalize();
+    if Vec3::dot(n, face_normal) < 0.0 {
+        -n
+    } else {
+        n
+    }
+}
+
+pub fn triangle_bounding_box(vertices: &[Point3; 3]) -> Aabb {
+    let [p0, p1, p2] = *vertices;
+    Aabb::new(p0, p0)
+        .surrounding(Aabb::new(p1, p1))
+        .surrounding(Aabb::new(p2, p2))
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(Hit, &dyn Scatterable)> {
+        let (t, b1, b2) = intersect_triangle(ray, self.vertices, t_min, t_max)?;
+        let [p0, p1, p2] = self.vertices;
+        // Counter-clockwise vertices face the viewer
+        let face_normal = Vec3::cross(p1 - p0, p2 - p0).normalize();
+        let n = shading_normal(face_normal, self.normals, b1, b2);
+        let (u, v) = match self.uvs {
+            Some(uvs) => interpolate_uv(uvs, b1, b2),
+            None => (b1, b2),
+        };
+        let hit = Hit::new(ray.dir, ray.at(t), n, t)
+            .with_uv(u, v)
+            .with_barycentric(b1, b2);
+        Some((hit, &*self.material))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        triangle_bounding_box(&self.vertices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+
+    fn unit_triangle() -> Triangle {
+        Triangle::new(
+            Point3(0.0, 0.0, 0.0),
+            Point3(1.0, 0.0, 0.0),
+            Point3(0.0, 1.0, 0.0),
+            Arc::new(Lambertian::new(Color(0.5, 0.5, 0.5))),
+        )
+    }
+
+    fn down_z(x: f32, y: f32) -> Ray {
+        Ray::new(Point3(x, y, 1.0), Vec3(0.0, 0.0, -1.0))
+    }
+
+    #[test]
+    fn triangle_hit_barycentrics() {
+        let tri = unit_triangle();
+        let (hit, _) = tri.hit(&down_z(0.25, 0.5), 0.0, f32::MAX).unwrap();
+        assert!((hit.t() - 1.0).abs() < 1e-6);
+        assert!(Vec3::almost_eq(
+            hit.p() - Point3(0.25, 0.5, 0.0),
+            Vec3::zero(),
+            1e-6
+        ));
+        assert_eq!(hit.barycentric(), (0.25, 0.5));
+        assert_eq!(hit.uv(), (0.25, 0.5));
+        // The ray comes from the side the counter-clockwise winding faces
+        assert!(hit.front_face());
+        assert_eq!(hit.n(), Vec3(0.0, 0.0, 1.0));
+
+        let back = Ray::new(Point3(0.25, 0.25, -1.0), Vec3(0.0, 0.0, 1.0));
+        let (hit, _) = tri.hit(&back, 0.0, f32::MAX).unwrap();
+        assert!(!hit.front_face());
+        assert_eq!(hit.n(), Vec3(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn triangle_miss_outside_and_out_of_range() {
+        let tri = unit_triangle();
+        assert!(tri.hit(&down_z(0.6, 0.6), 0.0, f32::MAX).is_none());
+        assert!(tri.hit(&down_z(-0.1, 0.5), 0.0, f32::MAX).is_none());
+        assert!(tri.hit(&down_z(0.5, -0.1), 0.0, f32::MAX).is_none());
+        assert!(tri.hit(&down_z(0.25, 0.25), 0.0, 0.5).is_none());
+        assert!(tri.hit(&down_z(0.25, 0.25), 1.5, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn triangle_edges_and_vertices_are_hit() {
+        let tri = unit_triangle();
+        for &(x, y) in &[(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (0.5, 0.5), (0.5, 0.0)] {
+            assert!(
+                tri.hit(&down_z(x, y), 0.0, f32::MAX).is_some(),
+                "{} {}",
+                x,
+                y
+            );
+        }
+    }
+
+    #[test]
+    fn triangle_grazing_rays() {
+        let tri = unit_triangle();
+        // In the plane of the triangle
+        let in_plane = Ray::new(Point3(-1.0, 0.25, 0.0), Vec3(1.0, 0.0, 0.0));
+        assert!(tri.hit(&in_plane, 0.0, f32::MAX).is_none());
+        // Parallel just above it
+        let above = Ray::new(Point3(-1.0, 0.25, 1e-3), Vec3(1.0, 0.0, 0.0));
+        assert!(tri.hit(&above, 0.0, f32::MAX).is_none());
+        // Almost parallel, but still crossing the plane inside the triangle
+        let dir = Vec3(1.0, 0.0, -1e-3).normalize();
+        let nearly = Ray::new(Point3(-0.75, 0.25, 1e-3), dir);
+        let (hit, _) = tri.hit(&nearly, 0.0, f32::MAX).unwrap();
+        assert!(hit.p().z().abs() < 1e-5);
+        assert!((hit.p().x() - 0.25).abs() < 1e-2);
+    }
+
+    #[test]
+    fn degenerate_triangles_are_never_hit() {
+        let material = Arc::new(Lambertian::new(Color(0.5, 0.5, 0.5)));
+        // Collinear vertices and a triangle collapsed into a point
+        let line = Triangle::new(
+            Point3(0.0, 0.0, 0.0),
+            Point3(1.0, 1.0, 0.0),
+            Point3(2.0, 2.0, 0.0),
+            material.clone(),
+        );
+        let point = Triangle::new(Point3::zero(), Point3::zero(), Point3::zero(), material);
+        for tri in [line, point].iter() {
+            for &(x, y) in &[(0.0, 0.0), (1.0, 1.0), (0.5, 0.5)] {
+                assert!(tri.hit(&down_z(x, y), 0.0, f32::MAX).is_none());
+            }
+            let b = tri.bounding_box();
+            assert!(b.min.z() == 0.0 && b.max.z() == 0.0);
+        }
+    }
+
+    #[test]
+    fn triangle_interpolates_normals_and_uvs() {
+        let n = Vec3(1.0, 0.0, 1.0).normalize();
+        let tri = unit_triangle()
+            .with_normals([Vec3(0.0, 0.0, 1.0), n, n])
+            .with_uvs([(0.0, 0.0), (1.0, 0.0), (0.0, 2.0)]);
+        let (hit, _) = tri.hit(&down_z(0.5, 0.25), 0.0, f32::MAX).unwrap();
+        assert_eq!(hit.uv(), (0.5, 0.5));
+        let expected = (0.25 * Vec3(0.0, 0.0, 1.0) + 0.75 * n).normalize();
+        assert!(Vec3::almost_eq(hit.n(), expected, 1e-5));
+
+        // Normals pointing away from the face are flipped to its side
+        let flipped = unit_triangle().with_normals([Vec3(0.0, 0.0, -1.0); 3]);
+        let (hit, _) = flipped.hit(&down_z(0.25, 0.25), 0.0, f32::MAX).unwrap();
+        assert!(hit.front_face());
+        assert_eq!(hit.n(), Vec3(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn triangle_bounding_box_contains_vertices() {
+        let tri = Triangle::new(
+            Point3(1.0, -2.0, 3.0),
+            Point3(-1.0, 4.0, 0.0),
+            Point3(0.5, 0.0, -5.0),
+            Arc::new(Lambertian::new(Color(0.5, 0.5, 0.5))),
+        );
+        let b = tri.bounding_box();
+        assert_eq!(b.min, Point3(-1.0, -2.0, -5.0));
+        assert_eq!(b.max, Point3(1.0, 4.0, 3.0));
+    }
+}
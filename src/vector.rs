@@ -1,12 +1,15 @@
+use crate::point::Point3;
 use rand::distributions::Distribution;
 use rand::Rng;
 use rand_distr::StandardNormal;
-use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use std::ops::{Add, AddAssign, Div, DivAssign, Index, Mul, MulAssign, Neg, Sub, SubAssign};
 
 pub trait Dot: Sized + Copy {
     fn dot(self, w: Self) -> f32;
 }
 
+// Euclidean length, not a container size
+#[allow(clippy::len_without_is_empty)]
 pub trait Len: Sized + Copy + Dot {
     fn len(self) -> f32 {
         self.len_squared().sqrt()
@@ -61,6 +64,18 @@ impl Vec3 {
     }
 }
 
+impl Index<usize> for Vec3 {
+    type Output = f32;
+    fn index(&self, axis: usize) -> &f32 {
+        match axis {
+            0 => &self.0,
+            1 => &self.1,
+            2 => &self.2,
+            _ => panic!("axis must be in 0..3"),
+        }
+    }
+}
+
 impl Dot for Vec3 {
     fn dot(self, w: Self) -> f32 {
         self.0 * w.0 + self.1 * w.1 + self.2 * w.2
@@ -153,8 +168,546 @@ impl Cross for Vec3 {
     }
 }
 
+// Row-major 4x4 matrix of an affine or projective transform,
+// applied to column vectors: p' = M * p
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4(pub [[f32; 4]; 4]);
+
+impl Mat4 {
+    pub fn identity() -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Mat4(m)
+    }
+
+    pub fn translation(t: Vec3) -> Mat4 {
+        let mut m = Mat4::identity();
+        m.0[0][3] = t.x();
+        m.0[1][3] = t.y();
+        m.0[2][3] = t.z();
+        m
+    }
+
+    pub fn scaling(s: Vec3) -> Mat4 {
+        let mut m = Mat4::identity();
+        m.0[0][0] = s.x();
+        m.0[1][1] = s.y();
+        m.0[2][2] = s.z();
+        m
+    }
+
+    // Rotation by angle (in degrees) around axis
+    pub fn rotation(angle: f32, axis: Vec3) -> Mat4 {
+        let a = axis.normalize();
+        let (sin, cos) = f32::to_radians(angle).sin_cos();
+        let t = 1.0 - cos;
+        Mat4([
+            [
+                t * a.x() * a.x() + cos,
+                t * a.x() * a.y() - sin * a.z(),
+                t * a.x() * a.z() + sin * a.y(),
+                0.0,
+            ],
+            [
+                t * a.x() * a.y() + sin * a.z(),
+                t * a.y() * a.y() + cos,
+                t * a.y() * a.z() - sin * a.x(),
+                0.0,
+            ],
+            [
+                t * a.x() * a.z() - sin * a.y(),
+                t * a.y() * a.z() + sin * a.x(),
+                t * a.z() * a.z() + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Rotation given by the unit quaternion x i + y j + z k + w
+    pub fn from_quaternion(x: f32, y: f32, z: f32, w: f32) -> Mat4 {
+        Mat4([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - z * w),
+                2.0 * (x * z + y * w),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + z * w),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - x * w),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - y * w),
+                2.0 * (y * z + x * w),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Sixteen values stored column after column, as in glTF and OpenGL
+    pub fn from_column_major(values: &[f32; 16]) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, &v) in values.iter().enumerate() {
+            m[i % 4][i / 4] = v;
+        }
+        Mat4(m)
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = self.0[j][i];
+            }
+        }
+        Mat4(m)
+    }
+
+    // Determinant of the linear part, negative when the transform mirrors
+    pub fn linear_determinant(&self) -> f32 {
+        let m = &self.0;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    // Gauss-Jordan elimination with partial pivoting, None if singular
+    pub fn inverse(&self) -> Option<Mat4> {
+        let mut a = self.0;
+        let mut inv = Mat4::identity().0;
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&i, &j| f32::total_cmp(&a[i][col].abs(), &a[j][col].abs()))
+                .unwrap();
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+            let scale = a[col][col].recip();
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let factor = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= factor * a[col][j];
+                        inv[row][j] -= factor * inv[col][j];
+                    }
+                }
+            }
+        }
+        Some(Mat4(inv))
+    }
+
+    pub fn transform_point(&self, p: Point3) -> Point3 {
+        let m = &self.0;
+        let x = m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3];
+        let y = m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3];
+        let z = m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3];
+        let w = m[3][0] * p.x() + m[3][1] * p.y() + m[3][2] * p.z() + m[3][3];
+        if w == 1.0 {
+            Point3(x, y, z)
+        } else {
+            Point3(x / w, y / w, z / w)
+        }
+    }
+
+    // Ignores the translation
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.0;
+        Vec3(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+    fn mul(self, rhs: Mat4) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = (0..4).map(|k| self.0[i][k] * rhs.0[k][j]).sum();
+            }
+        }
+        Mat4(m)
+    }
+}
+
+// An invertible transform kept together with its inverse,
+// e.g. from object to world space
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    matrix: Mat4,
+    inverse: Mat4,
+}
+
+impl Transform {
+    // None if the matrix is singular
+    pub fn new(matrix: Mat4) -> Option<Transform> {
+        let inverse = matrix.inverse()?;
+        Some(Transform { matrix, inverse })
+    }
+
+    pub fn identity() -> Transform {
+        Transform {
+            matrix: Mat4::identity(),
+            inverse: Mat4::identity(),
+        }
+    }
+
+    pub fn translation(t: Vec3) -> Transform {
+        Transform {
+            matrix: Mat4::translation(t),
+            inverse: Mat4::translation(-t),
+        }
+    }
+
+    pub fn scaling(s: Vec3) -> Transform {
+        assert!(
+            s.x() != 0.0 && s.y() != 0.0 && s.z() != 0.0,
+            "scale factors must not be zero"
+        );
+        Transform {
+            matrix: Mat4::scaling(s),
+            inverse: Mat4::scaling(Vec3(s.x().recip(), s.y().recip(), s.z().recip())),
+        }
+    }
+
+    // Rotation by angle (in degrees) around axis
+    pub fn rotation(angle: f32, axis: Vec3) -> Transform {
+        let matrix = Mat4::rotation(angle, axis);
+        Transform {
+            matrix,
+            inverse: matrix.transpose(),
+        }
+    }
+
+    pub fn matrix(&self) -> &Mat4 {
+        &self.matrix
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform {
+            matrix: self.inverse,
+            inverse: self.matrix,
+        }
+    }
+
+    pub fn transform_point(&self, p: Point3) -> Point3 {
+        self.matrix.transform_point(p)
+    }
+
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        self.matrix.transform_vector(v)
+    }
+
+    // Normals go through the inverse transpose to stay perpendicular
+    // to the surface, the result is not normalized
+    pub fn transform_normal(&self, n: Vec3) -> Vec3 {
+        let m = &self.inverse.0;
+        Vec3(
+            m[0][0] * n.x() + m[1][0] * n.y() + m[2][0] * n.z(),
+            m[0][1] * n.x() + m[1][1] * n.y() + m[2][1] * n.z(),
+            m[0][2] * n.x() + m[1][2] * n.y() + m[2][2] * n.z(),
+        )
+    }
+}
+
+// a * b applies b first
+impl Mul for Transform {
+    type Output = Transform;
+    fn mul(self, rhs: Transform) -> Transform {
+        Transform {
+            matrix: self.matrix * rhs.matrix,
+            inverse: rhs.inverse * self.inverse,
+        }
+    }
+}
+
+// Translation, rotation (a unit quaternion x, y, z, w) and the remaining
+// scale and shear of an affine transform, M = T * R * S
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Decomposed {
+    translation: Vec3,
+    rotation: [f32; 4],
+    scale: Mat4,
+}
+
+impl Decomposed {
+    fn new(m: &Mat4) -> Decomposed {
+        let translation = Vec3(m.0[0][3], m.0[1][3], m.0[2][3]);
+        let mut linear = *m;
+        for row in linear.0.iter_mut().take(3) {
+            row[3] = 0.0;
+        }
+        // Polar decomposition, averaging with the inverse transpose converges
+        // to the closest rotation (or reflection)
+        let mut r = linear;
+        for _ in 0..100 {
+            let next = match r.transpose().inverse() {
+                Some(inverse_transpose) => {
+                    let mut next = r;
+                    for (row, inv_row) in next.0.iter_mut().zip(inverse_transpose.0.iter()) {
+                        for (v, inv) in row.iter_mut().zip(inv_row.iter()) {
+                            *v = 0.5 * (*v + inv);
+                        }
+                    }
+                    next
+                }
+                None => break,
+            };
+            let change: f32 = (0..3)
+                .map(|i| {
+                    (0..3)
+                        .map(|j| (next.0[i][j] - r.0[i][j]).abs())
+                        .sum::<f32>()
+                })
+                .fold(0.0, f32::max);
+            r = next;
+            if change < 1e-6 {
+                break;
+            }
+        }
+        // A reflection goes into the scale, so r is a proper rotation
+        if r.linear_determinant() < 0.0 {
+            for row in r.0.iter_mut().take(3) {
+                for v in row.iter_mut().take(3) {
+                    *v = -*v;
+                }
+            }
+        }
+        Decomposed {
+            translation,
+            rotation: rotation_to_quaternion(&r),
+            scale: r.transpose() * linear,
+        }
+    }
+
+    fn matrix(&self) -> Mat4 {
+        let [x, y, z, w] = self.rotation;
+        Mat4::translation(self.translation) * Mat4::from_quaternion(x, y, z, w) * self.scale
+    }
+
+    // (T R S)^-1 = S^-1 R^-1 T^-1, where the rotation is inverted by its
+    // conjugate and the translation by its negation
+    fn inverse_matrix(&self) -> Mat4 {
+        let [x, y, z, w] = self.rotation;
+        inverse_scale(&self.scale)
+            * Mat4::from_quaternion(-x, -y, -z, w)
+            * Mat4::translation(-self.translation)
+    }
+
+    fn is_valid(&self) -> bool {
+        self.translation.len().is_finite()
+            && self.rotation.iter().all(|c| c.is_finite())
+            && self.scale.linear_determinant().is_normal()
+            && self.scale.0.iter().flatten().all(|v| v.is_finite())
+    }
+}
+
+// The inverse of the symmetric scale and shear from its adjugate. Blending
+// two of them with the same sign of the determinant never makes it singular.
+fn inverse_scale(s: &Mat4) -> Mat4 {
+    let m = &s.0;
+    let det = s.linear_determinant();
+    let mut inverse = Mat4::identity();
+    for (i, row) in inverse.0.iter_mut().enumerate().take(3) {
+        for (j, v) in row.iter_mut().enumerate().take(3) {
+            let (r0, r1) = ((j + 1) % 3, (j + 2) % 3);
+            let (c0, c1) = ((i + 1) % 3, (i + 2) % 3);
+            *v = (m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]) / det;
+        }
+    }
+    inverse
+}
+
+fn rotation_to_quaternion(r: &Mat4) -> [f32; 4] {
+    let m = &r.0;
+    let trace = m[0][0] + m[1][1] + m[2][2];
+    let q = if trace > 0.0 {
+        let s = 2.0 * (trace + 1.0).sqrt();
+        [
+            (m[2][1] - m[1][2]) / s,
+            (m[0][2] - m[2][0]) / s,
+            (m[1][0] - m[0][1]) / s,
+            0.25 * s,
+        ]
+    } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+        let s = 2.0 * (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt();
+        [
+            0.25 * s,
+            (m[0][1] + m[1][0]) / s,
+            (m[0][2] + m[2][0]) / s,
+            (m[2][1] - m[1][2]) / s,
+        ]
+    } else if m[1][1] > m[2][2] {
+        let s = 2.0 * (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt();
+        [
+            (m[0][1] + m[1][0]) / s,
+            0.25 * s,
+            (m[1][2] + m[2][1]) / s,
+            (m[0][2] - m[2][0]) / s,
+        ]
+    } else {
+        let s = 2.0 * (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt();
+        [
+            (m[0][2] + m[2][0]) / s,
+            (m[1][2] + m[2][1]) / s,
+            0.25 * s,
+            (m[1][0] - m[0][1]) / s,
+        ]
+    };
+    let len = q.iter().map(|c| c * c).sum::<f32>().sqrt();
+    [q[0] / len, q[1] / len, q[2] / len, q[3] / len]
+}
+
+fn quaternion_dot(a: &[f32; 4], b: &[f32; 4]) -> f32 {
+    a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+}
+
+// Spherical interpolation along the shorter arc
+fn slerp(a: &[f32; 4], b: &[f32; 4], t: f32) -> [f32; 4] {
+    let mut dot = quaternion_dot(a, b);
+    let mut b = *b;
+    if dot < 0.0 {
+        b.iter_mut().for_each(|c| *c = -*c);
+        dot = -dot;
+    }
+    let (wa, wb) = if dot > 0.9995 {
+        (1.0 - t, t)
+    } else {
+        let theta = dot.acos();
+        let sin = theta.sin();
+        (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+    };
+    let mut q = [0.0; 4];
+    for (i, c) in q.iter_mut().enumerate() {
+        *c = wa * a[i] + wb * b[i];
+    }
+    let len = q.iter().map(|c| c * c).sum::<f32>().sqrt();
+    q.map(|c| c / len)
+}
+
+// A transform moving from `start` at time0 to `end` at time1, and resting
+// at the end points outside of that interval. Translation, rotation and
+// scale are interpolated separately, so a rotating object stays rigid.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnimatedTransform {
+    start: Transform,
+    end: Transform,
+    time0: f32,
+    time1: f32,
+    // None when the transform does not move
+    parts: Option<[Decomposed; 2]>,
+}
+
+impl AnimatedTransform {
+    // Both keyframes are decomposed up front, so every transform in between
+    // is invertible
+    pub fn new(
+        start: Transform,
+        end: Transform,
+        time0: f32,
+        time1: f32,
+    ) -> Result<AnimatedTransform, &'static str> {
+        if !time0.is_finite() || !time1.is_finite() || time0 > time1 {
+            return Err("time0 and time1 must be finite and in order");
+        }
+        if start == end || time0 == time1 {
+            return Ok(AnimatedTransform::fixed(start));
+        }
+        if (start.matrix.linear_determinant() < 0.0) != (end.matrix.linear_determinant() < 0.0) {
+            return Err("cannot animate between a mirrored and an unmirrored transform");
+        }
+        let parts = [Decomposed::new(&start.matrix), Decomposed::new(&end.matrix)];
+        if !parts.iter().all(Decomposed::is_valid) {
+            return Err("keyframe is not an invertible affine transform");
+        }
+        Ok(AnimatedTransform {
+            start,
+            end,
+            time0,
+            time1,
+            parts: Some(parts),
+        })
+    }
+
+    pub fn fixed(transform: Transform) -> AnimatedTransform {
+        AnimatedTransform {
+            start: transform,
+            end: transform,
+            time0: 0.0,
+            time1: 0.0,
+            parts: None,
+        }
+    }
+
+    pub fn is_animated(&self) -> bool {
+        self.parts.is_some()
+    }
+
+    pub fn time_range(&self) -> (f32, f32) {
+        (self.time0, self.time1)
+    }
+
+    pub fn at(&self, time: f32) -> Transform {
+        let [a, b] = match &self.parts {
+            Some(parts) => parts,
+            None => return self.start,
+        };
+        let t = (time - self.time0) / (self.time1 - self.time0);
+        if t <= 0.0 {
+            return self.start;
+        }
+        if t >= 1.0 {
+            return self.end;
+        }
+        let mut scale = a.scale;
+        for (row, b_row) in scale.0.iter_mut().zip(b.scale.0.iter()) {
+            for (v, b) in row.iter_mut().zip(b_row.iter()) {
+                *v += t * (b - *v);
+            }
+        }
+        let parts = Decomposed {
+            translation: a.translation + t * (b.translation - a.translation),
+            rotation: slerp(&a.rotation, &b.rotation, t),
+            scale,
+        };
+        Transform {
+            matrix: parts.matrix(),
+            inverse: parts.inverse_matrix(),
+        }
+    }
+
+    // Angle in radians the rotation turns by from time0 to time1
+    pub fn rotation_angle(&self) -> f32 {
+        match &self.parts {
+            Some([a, b]) => {
+                2.0 * quaternion_dot(&a.rotation, &b.rotation)
+                    .abs()
+                    .min(1.0)
+                    .acos()
+            }
+            None => 0.0,
+        }
+    }
+}
+
 #[allow(clippy::many_single_char_names)]
-pub fn uniform_in_unit_sphere<R: Rng>(rng: &mut R) -> (f32, f32, f32) {
+pub fn uniform_in_unit_sphere<R: Rng + ?Sized>(rng: &mut R) -> (f32, f32, f32) {
     // Let d = 5
     // Compute d random numbers with the Normal Distribution
     let distribution = StandardNormal;
@@ -176,7 +729,7 @@ pub fn uniform_in_unit_sphere<R: Rng>(rng: &mut R) -> (f32, f32, f32) {
     (inv_l * Vec3(u, v, w)).into()
 }
 
-pub fn uniform_on_unit_sphere<R: Rng>(rng: &mut R) -> (f32, f32, f32) {
+pub fn uniform_on_unit_sphere<R: Rng + ?Sized>(rng: &mut R) -> (f32, f32, f32) {
     // Use the same trick as in fn uniform_in_unit_sphere
     let distribution = StandardNormal;
     let u = distribution.sample(rng);
@@ -188,7 +741,7 @@ pub fn uniform_on_unit_sphere<R: Rng>(rng: &mut R) -> (f32, f32, f32) {
     (inv_l * Vec3(u, v, w)).into()
 }
 
-pub fn uniform_in_unit_disk<R: Rng>(rng: &mut R) -> (f32, f32) {
+pub fn uniform_in_unit_disk<R: Rng + ?Sized>(rng: &mut R) -> (f32, f32) {
     // Use the same trick as in fn uniform_in_unit_sphere
     let distribution = StandardNormal;
     let u: f32 = distribution.sample(rng);
@@ -201,6 +754,19 @@ pub fn uniform_in_unit_disk<R: Rng>(rng: &mut R) -> (f32, f32) {
     (inv_l * u, inv_l * v)
 }
 
+// Two unit vectors that complete the unit vector n to an orthonormal
+// right-handed frame, without a branch on the direction of n
+// (Duff et al., Building an Orthonormal Basis, Revisited)
+pub fn orthonormal_basis(n: Vec3) -> (Vec3, Vec3) {
+    let sign = 1.0f32.copysign(n.z());
+    let a = -1.0 / (sign + n.z());
+    let b = n.x() * n.y() * a;
+    (
+        Vec3(1.0 + sign * n.x() * n.x() * a, sign * b, -sign * n.x()),
+        Vec3(b, sign + n.y() * n.y() * a, -n.y()),
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -237,6 +803,14 @@ mod tests {
         assert_eq!(a.z(), a.2);
     }
 
+    #[test]
+    fn vec3_index() {
+        let a = Vec3(1.0, 2.0, 3.0);
+        assert_eq!(a[0], a.0);
+        assert_eq!(a[1], a.1);
+        assert_eq!(a[2], a.2);
+    }
+
     #[test]
     fn vec3_add() {
         let a = Vec3(1.0, 2.0, 3.0);
@@ -296,6 +870,23 @@ mod tests {
         assert_eq!(a.normalize(), Vec3(4.0 / 6.0, 4.0 / 6.0, 2.0 / 6.0));
     }
 
+    #[test]
+    fn orthonormal_basis_is_orthonormal() {
+        for &n in [
+            Vec3(0.0, 0.0, 1.0),
+            Vec3(0.0, 0.0, -1.0),
+            Vec3(1.0, 2.0, -3.0).normalize(),
+            Vec3(-0.3, 0.1, 0.2).normalize(),
+        ]
+        .iter()
+        {
+            let (t, b) = orthonormal_basis(n);
+            assert!((t.len() - 1.0).abs() < 1e-5 && (b.len() - 1.0).abs() < 1e-5);
+            assert!(t.dot(n).abs() < 1e-5 && b.dot(n).abs() < 1e-5 && t.dot(b).abs() < 1e-5);
+            assert!(Vec3::almost_eq(t.cross(b), n, 1e-5));
+        }
+    }
+
     #[test]
     fn vec3_cross() {
         let a = Vec3(1.0, 0.0, 0.0);
@@ -311,4 +902,154 @@ mod tests {
         assert_eq!(Vec3::cross(c, b), -a);
 
     }
+
+    #[test]
+    fn mat4_transforms() {
+        let m = Mat4::translation(Vec3(1.0, 2.0, 3.0)) * Mat4::scaling(Vec3(2.0, 2.0, 2.0));
+        assert_eq!(
+            m.transform_point(Point3(1.0, 0.0, 0.0)),
+            Point3(3.0, 2.0, 3.0)
+        );
+        assert_eq!(m.transform_vector(Vec3(1.0, 0.0, 0.0)), Vec3(2.0, 0.0, 0.0));
+
+        let r = Mat4::rotation(90.0, Vec3(0.0, 0.0, 1.0));
+        assert!(Vec3::almost_eq(
+            r.transform_vector(Vec3(1.0, 0.0, 0.0)),
+            Vec3(0.0, 1.0, 0.0),
+            1e-6
+        ));
+        // The same rotation as a quaternion
+        let h = f32::sqrt(0.5);
+        let q = Mat4::from_quaternion(0.0, 0.0, h, h);
+        for (a, b) in r.0.iter().flatten().zip(q.0.iter().flatten()) {
+            assert!((a - b).abs() < 1e-6);
+        }
+
+        let values: [f32; 16] = [
+            1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 4.0, 5.0, 6.0, 1.0,
+        ];
+        assert_eq!(
+            Mat4::from_column_major(&values),
+            Mat4::translation(Vec3(4.0, 5.0, 6.0))
+        );
+        assert_eq!(
+            Mat4::translation(Vec3(4.0, 5.0, 6.0)).transpose().0[3],
+            [4.0, 5.0, 6.0, 1.0]
+        );
+    }
+
+    #[test]
+    fn mat4_inverse() {
+        let m = Mat4::translation(Vec3(1.0, -2.0, 3.0))
+            * Mat4::rotation(30.0, Vec3(1.0, 1.0, 0.0))
+            * Mat4::scaling(Vec3(2.0, 0.5, 3.0));
+        let product = m * m.inverse().unwrap();
+        for (a, b) in product
+            .0
+            .iter()
+            .flatten()
+            .zip(Mat4::identity().0.iter().flatten())
+        {
+            assert!((a - b).abs() < 1e-5);
+        }
+        assert!(Mat4::scaling(Vec3(1.0, 0.0, 1.0)).inverse().is_none());
+        assert!((m.linear_determinant() - 3.0).abs() < 1e-5);
+        assert!(Mat4::scaling(Vec3(-1.0, 1.0, 1.0)).linear_determinant() < 0.0);
+    }
+
+    #[test]
+    fn transform_keeps_its_inverse() {
+        let t = Transform::translation(Vec3(1.0, 2.0, 3.0))
+            * Transform::rotation(90.0, Vec3(0.0, 0.0, 1.0))
+            * Transform::scaling(Vec3(2.0, 1.0, 1.0));
+        let p = t.transform_point(Point3(1.0, 0.0, 0.0));
+        assert!(Vec3::almost_eq(
+            p - Point3::zero(),
+            Vec3(1.0, 4.0, 3.0),
+            1e-5
+        ));
+        let back = t.inverse().transform_point(p);
+        assert!(Vec3::almost_eq(
+            back - Point3::zero(),
+            Vec3(1.0, 0.0, 0.0),
+            1e-5
+        ));
+        assert_eq!(Transform::new(*t.matrix()).unwrap().matrix(), t.matrix());
+        assert!(Transform::new(Mat4::scaling(Vec3(0.0, 1.0, 1.0))).is_none());
+
+        // Stretching a 45 degree slope along x makes its normal steeper
+        let stretch = Transform::scaling(Vec3(2.0, 1.0, 1.0));
+        let n = stretch.transform_normal(Vec3(1.0, 1.0, 0.0)).normalize();
+        let tangent = stretch.transform_vector(Vec3(1.0, -1.0, 0.0));
+        assert!(Vec3::dot(n, tangent).abs() < 1e-6);
+        assert!(Vec3::almost_eq(n, Vec3(1.0, 2.0, 0.0).normalize(), 1e-6));
+    }
+
+    #[test]
+    fn animated_transform_interpolates_the_parts() {
+        let start = Transform::translation(Vec3(1.0, 0.0, 0.0));
+        let end = Transform::translation(Vec3(3.0, 0.0, 0.0))
+            * Transform::rotation(90.0, Vec3(0.0, 0.0, 1.0))
+            * Transform::scaling(Vec3(3.0, 3.0, 3.0));
+        let motion = AnimatedTransform::new(start, end, 0.0, 2.0).unwrap();
+        assert!(motion.is_animated());
+        assert_eq!(motion.at(-1.0), start);
+        assert_eq!(motion.at(5.0), end);
+        assert!((motion.rotation_angle() - std::f32::consts::FRAC_PI_2).abs() < 1e-4);
+
+        // Halfway the rotation is 45 degrees, not a shrunken matrix blend
+        let halfway = Transform::translation(Vec3(2.0, 0.0, 0.0))
+            * Transform::rotation(45.0, Vec3(0.0, 0.0, 1.0))
+            * Transform::scaling(Vec3(2.0, 2.0, 2.0));
+        let p = Point3(1.0, 0.0, 0.0);
+        let expected = halfway.transform_point(p);
+        let actual = motion.at(1.0).transform_point(p);
+        assert!(Vec3::almost_eq(actual - expected, Vec3::zero(), 1e-4));
+
+        // Mirrored transforms keep their mirroring
+        let mirrored = Transform::scaling(Vec3(-1.0, 1.0, 1.0));
+        let motion = AnimatedTransform::new(
+            mirrored,
+            Transform::translation(Vec3(0.0, 2.0, 0.0)) * mirrored,
+            0.0,
+            1.0,
+        )
+        .unwrap();
+        let actual = motion.at(0.5).transform_point(p);
+        assert!(Vec3::almost_eq(
+            actual - Point3(-1.0, 1.0, 0.0),
+            Vec3::zero(),
+            1e-5
+        ));
+
+        let fixed = AnimatedTransform::new(start, start, 0.0, 1.0).unwrap();
+        assert!(!fixed.is_animated());
+        assert_eq!(fixed.at(0.5), start);
+    }
+
+    #[test]
+    fn animated_transform_inverts_the_parts() {
+        let shear = Mat4([
+            [1.0, 0.5, 0.0, 0.0],
+            [0.0, 2.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        let start = Transform::new(shear).unwrap();
+        let end = Transform::translation(Vec3(1.0, 2.0, 3.0))
+            * Transform::rotation(120.0, Vec3(1.0, 1.0, 0.0))
+            * Transform::scaling(Vec3(0.5, 3.0, 1.0));
+        let motion = AnimatedTransform::new(start, end, 0.0, 1.0).unwrap();
+        let p = Point3(0.3, -0.7, 2.0);
+        for &time in &[0.1, 0.5, 0.9] {
+            let t = motion.at(time);
+            let back = t.inverse().transform_point(t.transform_point(p));
+            assert!(Vec3::almost_eq(back - p, Vec3::zero(), 1e-4));
+        }
+
+        let mirrored = Transform::scaling(Vec3(-1.0, 1.0, 1.0));
+        assert!(AnimatedTransform::new(start, mirrored, 0.0, 1.0).is_err());
+        assert!(AnimatedTransform::new(start, end, 1.0, 0.0).is_err());
+        assert!(AnimatedTransform::new(start, end, 0.0, f32::NAN).is_err());
+    }
 }
@@ -0,0 +1,534 @@
+// Minimal zlib (RFC 1950) / deflate (RFC 1951) implementation, so image
+// formats can be written and read without any system libraries.
+// The compressor emits a single block with the fixed Huffman codes,
+// the decompressor understands every block type.
+use std::io;
+
+const WINDOW_SIZE: usize = 1 << 15;
+const HASH_BITS: usize = 15;
+const MAX_CHAIN: usize = 64;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// Order in which the code length code lengths are stored in a dynamic block
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 is the largest n such that the sums can not overflow before the modulo
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
+
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new(out: Vec<u8>) -> BitWriter {
+        BitWriter {
+            out,
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    // Writes the `count` low bits of `bits`, least significant bit first
+    fn write(&mut self, bits: u32, count: u32) {
+        self.bits |= (bits as u64) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes are stored starting from the most significant bit
+    fn write_code(&mut self, code: u32, len: u32) {
+        self.write(code.reverse_bits() >> (32 - len), len);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+}
+
+fn write_fixed_literal(w: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => w.write_code(0x30 + symbol, 8),
+        144..=255 => w.write_code(0x190 + symbol - 144, 9),
+        256..=279 => w.write_code(symbol - 256, 7),
+        _ => w.write_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(w: &mut BitWriter, len: usize, dist: usize) {
+    let code = LENGTH_BASE
+        .iter()
+        .rposition(|&b| b as usize <= len)
+        .unwrap();
+    write_fixed_literal(w, 257 + code as u32);
+    w.write(
+        (len - LENGTH_BASE[code] as usize) as u32,
+        LENGTH_EXTRA[code] as u32,
+    );
+
+    let code = DIST_BASE.iter().rposition(|&b| b as usize <= dist).unwrap();
+    w.write_code(code as u32, 5);
+    w.write(
+        (dist - DIST_BASE[code] as usize) as u32,
+        DIST_EXTRA[code] as u32,
+    );
+}
+
+fn hash(data: &[u8]) -> usize {
+    let v = (data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32;
+    (v.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+// LZ77 match finder over hash chains of 3-byte prefixes
+struct Matcher {
+    // Most recent position for each hash
+    head: Vec<usize>,
+    // Previous position with the same hash, indexed by position within the window
+    prev: Vec<usize>,
+}
+
+impl Matcher {
+    fn new() -> Matcher {
+        Matcher {
+            head: vec![usize::MAX; 1 << HASH_BITS],
+            prev: vec![usize::MAX; WINDOW_SIZE],
+        }
+    }
+
+    fn insert(&mut self, data: &[u8], pos: usize) {
+        if pos + MIN_MATCH <= data.len() {
+            let h = hash(&data[pos..]);
+            self.prev[pos % WINDOW_SIZE] = self.head[h];
+            self.head[h] = pos;
+        }
+    }
+
+    // Returns the length and distance of the longest earlier match
+    fn find(&self, data: &[u8], pos: usize) -> (usize, usize) {
+        let (mut best_len, mut best_dist) = (0, 0);
+        if pos + MIN_MATCH > data.len() {
+            return (best_len, best_dist);
+        }
+
+        let max_len = usize::min(MAX_MATCH, data.len() - pos);
+        let mut candidate = self.head[hash(&data[pos..])];
+        for _ in 0..MAX_CHAIN {
+            if candidate == usize::MAX || pos - candidate >= WINDOW_SIZE {
+                break;
+            }
+            let len = data[candidate..]
+                .iter()
+                .zip(&data[pos..pos + max_len])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if len > best_len {
+                best_len = len;
+                best_dist = pos - candidate;
+                if len == max_len {
+                    break;
+                }
+            }
+            let next = self.prev[candidate % WINDOW_SIZE];
+            // The slot may already hold a newer position from a later window
+            if next == usize::MAX || next >= candidate {
+                break;
+            }
+            candidate = next;
+        }
+        (best_len, best_dist)
+    }
+}
+
+// Compresses `data` into a zlib stream
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    // CMF: deflate with a 32K window, FLG: default level, no dictionary, checksum bits
+    let mut w = BitWriter::new(vec![0x78, 0x9c]);
+    w.write(1, 1); // BFINAL
+    w.write(1, 2); // BTYPE = fixed Huffman
+
+    let mut matcher = Matcher::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let (len, dist) = matcher.find(data, pos);
+        if len >= MIN_MATCH {
+            write_match(&mut w, len, dist);
+            for p in pos..pos + len {
+                matcher.insert(data, p);
+            }
+            pos += len;
+        } else {
+            write_fixed_literal(&mut w, data[pos] as u32);
+            matcher.insert(data, pos);
+            pos += 1;
+        }
+    }
+    write_fixed_literal(&mut w, 256);
+
+    let mut out = w.finish();
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bits: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            data,
+            pos: 0,
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    fn bits(&mut self, n: u32) -> io::Result<u32> {
+        while self.count < n {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| invalid_data("unexpected end of deflate stream"))?;
+            self.pos += 1;
+            self.bits |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let v = self.bits & ((1u64 << n) - 1) as u32;
+        self.bits >>= n;
+        self.count -= n;
+        Ok(v)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.bits = 0;
+        self.count = 0;
+    }
+}
+
+// Canonical Huffman code decoded one bit at a time
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Huffman> {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // Reject over-subscribed codes, incomplete ones are allowed by the format
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = 2 * left - count as i32;
+            if left < 0 {
+                return Err(invalid_data("over-subscribed Huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, r: &mut BitReader) -> io::Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= r.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid_data("invalid Huffman code"))
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (
+        Huffman::new(&lengths).unwrap(),
+        Huffman::new(&[5; 30]).unwrap(),
+    )
+}
+
+fn dynamic_codes(r: &mut BitReader) -> io::Result<(Huffman, Huffman)> {
+    let hlit = r.bits(5)? as usize + 257;
+    let hdist = r.bits(5)? as usize + 1;
+    let hclen = r.bits(4)? as usize + 4;
+    if hlit > 286 || hdist > 30 {
+        return Err(invalid_data("too many length or distance codes"));
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &i in &CODE_LENGTH_ORDER[..hclen] {
+        code_lengths[i] = r.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths)?;
+
+    let mut lengths = vec![0u8; hlit + hdist];
+    let mut i = 0;
+    while i < lengths.len() {
+        let symbol = code_length_code.decode(r)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths[..i]
+                    .last()
+                    .ok_or_else(|| invalid_data("repeat with no previous length"))?;
+                (previous, 3 + r.bits(2)? as usize)
+            }
+            17 => (0, 3 + r.bits(3)? as usize),
+            _ => (0, 11 + r.bits(7)? as usize),
+        };
+        if i + repeat > lengths.len() {
+            return Err(invalid_data("too many code lengths"));
+        }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+    if lengths[256] == 0 {
+        return Err(invalid_data("missing end-of-block code"));
+    }
+
+    Ok((
+        Huffman::new(&lengths[..hlit])?,
+        Huffman::new(&lengths[hlit..])?,
+    ))
+}
+
+fn inflate_block(
+    r: &mut BitReader,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> io::Result<()> {
+    loop {
+        let symbol = literals.decode(r)? as usize;
+        if symbol < 256 {
+            out.push(symbol as u8);
+        } else if symbol == 256 {
+            return Ok(());
+        } else {
+            let code = symbol - 257;
+            if code >= LENGTH_BASE.len() {
+                return Err(invalid_data("invalid length code"));
+            }
+            let len = LENGTH_BASE[code] as usize + r.bits(LENGTH_EXTRA[code] as u32)? as usize;
+            let code = distances.decode(r)? as usize;
+            if code >= DIST_BASE.len() {
+                return Err(invalid_data("invalid distance code"));
+            }
+            let dist = DIST_BASE[code] as usize + r.bits(DIST_EXTRA[code] as u32)? as usize;
+            if dist > out.len() {
+                return Err(invalid_data("distance too far back"));
+            }
+            // The copy may overlap with its own output
+            let start = out.len() - dist;
+            for i in 0..len {
+                out.push(out[start + i]);
+            }
+        }
+    }
+}
+
+// Decompresses a raw deflate stream
+pub fn inflate(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut r = BitReader::new(data);
+    let mut out = Vec::new();
+    loop {
+        let last = r.bits(1)? == 1;
+        match r.bits(2)? {
+            0 => {
+                r.align_to_byte();
+                let header = data
+                    .get(r.pos..r.pos + 4)
+                    .ok_or_else(|| invalid_data("unexpected end of deflate stream"))?;
+                let len = u16::from_le_bytes([header[0], header[1]]);
+                let nlen = u16::from_le_bytes([header[2], header[3]]);
+                if len != !nlen {
+                    return Err(invalid_data("stored block length mismatch"));
+                }
+                let start = r.pos + 4;
+                let block = data
+                    .get(start..start + len as usize)
+                    .ok_or_else(|| invalid_data("unexpected end of deflate stream"))?;
+                out.extend_from_slice(block);
+                r.pos = start + len as usize;
+            }
+            1 => {
+                let (literals, distances) = fixed_codes();
+                inflate_block(&mut r, &mut out, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut r)?;
+                inflate_block(&mut r, &mut out, &literals, &distances)?;
+            }
+            _ => return Err(invalid_data("invalid deflate block type")),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+// Decompresses a zlib stream and verifies its checksum
+pub fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    if data.len() < 6 {
+        return Err(invalid_data("zlib stream is too short"));
+    }
+    let (cmf, flg) = (data[0], data[1]);
+    if cmf & 0x0f != 8 || (cmf as u16 * 256 + flg as u16) % 31 != 0 || flg & 0x20 != 0 {
+        return Err(invalid_data("unsupported zlib header"));
+    }
+
+    let out = inflate(&data[2..data.len() - 4])?;
+    let checksum = &data[data.len() - 4..];
+    if adler32(&out).to_be_bytes() != checksum {
+        return Err(invalid_data("zlib checksum mismatch"));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn sample_text() -> Vec<u8> {
+        (0..40)
+            .flat_map(|i| {
+                format!(
+                    "line {}: the quick brown fox jumps over the lazy dog\n",
+                    i * i % 97
+                )
+                .into_bytes()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn adler32_known_value() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        assert_eq!(adler32(b""), 1);
+    }
+
+    #[test]
+    fn compress_roundtrip() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let noise: Vec<u8> = (0..100_000).map(|_| rng.gen()).collect();
+        let runs: Vec<u8> = (0..100_000).map(|i| (i / 1000) as u8).collect();
+        for data in [Vec::new(), vec![7], sample_text(), noise, runs] {
+            assert_eq!(decompress(&compress(&data)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn compress_shrinks_repetitive_data() {
+        let data = sample_text();
+        assert!(compress(&data).len() < data.len() / 4);
+    }
+
+    #[test]
+    fn decompress_dynamic_block() {
+        // zlib.compress(sample_text(), 9) from CPython, which uses a dynamic Huffman block
+        let compressed = [
+            0x78, 0xda, 0x9d, 0x94, 0x59, 0x12, 0xc2, 0x30, 0x0c, 0x43, 0xff, 0x7b, 0x0a, 0x1f,
+            0xa1, 0x4d, 0xb3, 0x38, 0xdc, 0x06, 0x68, 0x80, 0x42, 0x68, 0xa0, 0x0b, 0x05, 0x4e,
+            0xcf, 0xc0, 0x0d, 0x78, 0xdf, 0x1e, 0x8d, 0x65, 0x59, 0x52, 0xee, 0x87, 0x24, 0xf5,
+            0x46, 0xe6, 0x53, 0x92, 0xfb, 0xd2, 0xef, 0x2f, 0xb2, 0x1b, 0xcb, 0x3a, 0xc8, 0xa1,
+            0x3c, 0xe5, 0xbc, 0x5c, 0x6f, 0x93, 0x94, 0x47, 0x1a, 0x7f, 0xe3, 0xbc, 0x7d, 0xbf,
+            0xa4, 0x2b, 0xc7, 0x2a, 0x7f, 0x31, 0x0d, 0xc0, 0x58, 0x80, 0x89, 0x84, 0x9b, 0x07,
+            0x20, 0xe3, 0x00, 0xa8, 0x25, 0x9b, 0x2c, 0xb9, 0xc9, 0x13, 0xf1, 0x94, 0x7c, 0xa9,
+            0x25, 0xe2, 0x11, 0x76, 0x36, 0x00, 0x50, 0x30, 0x84, 0x1e, 0x91, 0x81, 0x68, 0xe7,
+            0xc9, 0xa6, 0x88, 0x9c, 0x47, 0xde, 0x14, 0x50, 0xd2, 0xc9, 0x4d, 0x8e, 0xd0, 0x8b,
+            0x28, 0x4d, 0xa8, 0x55, 0x50, 0x7d, 0xa1, 0x9b, 0x08, 0x3d, 0x47, 0xfe, 0xa4, 0xc4,
+            0xaf, 0xc4, 0x7a, 0x86, 0xe4, 0x56, 0x09, 0x3d, 0x47, 0xc4, 0x33, 0xc4, 0xaf, 0x8a,
+            0x3a, 0x19, 0xd5, 0x2b, 0x91, 0xbc, 0x21, 0x9b, 0x94, 0xc4, 0xc9, 0xff, 0x09, 0xfa,
+            0x00, 0x98, 0x09, 0xe4, 0xdc,
+        ];
+        assert_eq!(decompress(&compressed).unwrap(), sample_text());
+    }
+
+    #[test]
+    fn decompress_stored_block() {
+        // A single stored block holding "abc"
+        let mut data = vec![0x78, 0x01, 0x01, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c'];
+        data.extend_from_slice(&adler32(b"abc").to_be_bytes());
+        assert_eq!(decompress(&data).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn decompress_rejects_corrupted_data() {
+        let mut data = compress(&sample_text());
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        assert!(decompress(&data).is_err());
+        assert!(decompress(&data[..10]).is_err());
+        assert!(decompress(b"not zlib").is_err());
+    }
+}